@@ -0,0 +1,195 @@
+//! Attributes of the tag lines of the chat files (`<CHAT/>`, `<MESSAGE/>`…), without Neovim so the fuzz
+//! targets of `fuzz/` can link them.
+
+pub const ESCAPE: char = '\\';
+
+/// `\` and `"` of an attribute value are escaped by a `\`.
+pub fn escape_quote_arg(value: String) -> String {
+    let acc = String::with_capacity(value.len());
+    value.chars().fold(acc, |mut acc, c| {
+        match c {
+            c if matches!(c, ESCAPE | '"') => {
+                acc.push(ESCAPE);
+                acc.push(c)
+            }
+            c => acc.push(c),
+        }
+        acc
+    })
+}
+
+pub fn unescape_quote_arg(value: &str) -> String {
+    let acc = String::with_capacity(value.len());
+    let mut last_is_escape_char = false;
+    value.chars().fold(acc, |mut acc, c| {
+        if last_is_escape_char {
+            if matches!(c, ESCAPE | '"') {
+                acc.push(c)
+            } else {
+                // Act like it was, previously, not escaped correctly
+                acc.push(ESCAPE);
+                acc.push(c)
+            }
+            last_is_escape_char = false;
+        } else {
+            match c {
+                ESCAPE => last_is_escape_char = true,
+                c => acc.push(c),
+            }
+        }
+        acc
+    })
+}
+
+/// Blank lines and spaces kept in an attribute value, escaped as in a Rust string (`\n`, `\t`, `\u{a0}`…).
+pub fn escape_whitespace(value: &str) -> String {
+    value.escape_default().to_string()
+}
+
+/// Reverse of `escape_whitespace`, an unknown escape is kept as is.
+pub fn unescape_whitespace(value: &str) -> String {
+    let mut acc = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != ESCAPE {
+            acc.push(c);
+            continue;
+        }
+        let rest = chars.as_str();
+        let unescaped = match rest.chars().next() {
+            Some('n') => Some(('\n', 1)),
+            Some('t') => Some(('\t', 1)),
+            Some('r') => Some(('\r', 1)),
+            Some(c @ (ESCAPE | '\'' | '"')) => Some((c, 1)),
+            Some('u') => rest
+                .strip_prefix("u{")
+                .and_then(|hex| hex.split_once('}'))
+                .and_then(|(hex, _)| Some((char::from_u32(u32::from_str_radix(hex, 16).ok()?)?, hex.len() + 3))),
+            _ => None,
+        };
+        match unescaped {
+            Some((c, len)) => {
+                acc.push(c);
+                chars = rest[len..].chars();
+            }
+            None => acc.push(ESCAPE),
+        }
+    }
+    acc
+}
+
+/// Append ` key="value"` to the attributes of a tag line.
+pub fn write_arg(args: &mut String, key: &str, value: impl std::fmt::Display) {
+    let value = escape_quote_arg(value.to_string());
+    args.push_str(&format!(r#" {key}="{value}""#));
+}
+
+/// Call `parse_arg` with the key, the raw (escaped) value and the columns of the value of each attribute.
+pub fn parse_tag_line<Callback>(tag_line: &str, mut parse_arg: Callback)
+where
+    Callback: FnMut(String, String, std::ops::RangeInclusive<usize>),
+{
+    let mut in_quote = false;
+    // The current char follows a `\` in a value.
+    let mut escaped = false;
+    let mut key = String::new();
+    let mut value = String::new();
+    let mut column = 0;
+    let (mut start, mut end) = (0, 0);
+    let mut chars = tag_line.chars();
+    // Skip Tag
+    for c in chars.by_ref() {
+        column += 1;
+        if c == ' ' {
+            break;
+        }
+    }
+    let chars = " ".chars().chain(chars);
+    for c in chars.collect::<Vec<char>>().windows(2) {
+        let c = (c[0], c[1]);
+        let is_escaped = escaped;
+        escaped = in_quote && !is_escaped && c.1 == ESCAPE;
+        match c {
+            ('>', _) if !in_quote => return,
+            ('"', '/') | ('"', ' ') | ('"', '>') if !in_quote => {
+                let k = std::mem::take(&mut key);
+                let v = std::mem::take(&mut value);
+                parse_arg(k, v, start..=end);
+                (start, end) = (0, 0);
+            }
+            // Detect quotes
+            (_, '"') if !is_escaped => {
+                if in_quote {
+                    end = column;
+                } else {
+                    start = column + 1;
+                }
+                in_quote = !in_quote;
+            }
+            // Fill value
+            (_, c) if in_quote => value.push(c),
+            // Fill key
+            (_, ' ') => (),
+            (_, '=') => (),
+            (_, c) => key.push(c),
+        }
+        column += 1;
+    }
+}
+
+/// `prompt;completion;total` tokens, a missing or invalid count is 0.
+pub fn parse_usage(value: &str) -> [u32; 3] {
+    let mut split = value.split(';');
+    let mut parse = || {
+        split
+            .next()
+            .map(|s| s.parse().unwrap_or_default())
+            .unwrap_or_default()
+    };
+    [parse(), parse(), parse()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tag_line_attributes() {
+        let mut line = String::new();
+        write_arg(&mut line, "name", r#"C:\dossier\"#);
+        write_arg(&mut line, "description", r#"Un "outil" /> "#);
+        let line = format!("<CHAT{line}/>");
+        let mut args = Vec::new();
+        parse_tag_line(&line, |key, val, _cols| args.push((key, unescape_quote_arg(&val))));
+        assert_eq!(
+            args,
+            [
+                ("name".to_string(), r#"C:\dossier\"#.to_string()),
+                ("description".to_string(), r#"Un "outil" /> "#.to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn tag_line_columns() {
+        let line = r#"<MESSAGE role="User" model="Tiny Latest"/>"#;
+        let mut values = Vec::new();
+        parse_tag_line(line, |_key, _val, cols| values.push(&line[*cols.start()..*cols.end()]));
+        assert_eq!(values, ["User", "Tiny Latest"]);
+    }
+
+    #[test]
+    fn whitespace() {
+        let end = "  \n\t\r\n\u{a0}\u{3000}";
+        assert_eq!(escape_whitespace(end), r"  \n\t\r\n\u{a0}\u{3000}");
+        assert_eq!(unescape_whitespace(&escape_whitespace(end)), end);
+        assert_eq!(unescape_whitespace(r"\x\u{zz}\"), r"\x\u{zz}\");
+    }
+
+    #[test]
+    fn usage() {
+        assert_eq!(parse_usage("12;3;15"), [12, 3, 15]);
+        assert_eq!(parse_usage("12;x"), [12, 0, 0]);
+        assert_eq!(parse_usage(""), [0, 0, 0]);
+    }
+}
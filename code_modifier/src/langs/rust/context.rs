@@ -0,0 +1,446 @@
+use tree_sitter::{Node, StreamingIterator as _};
+
+use super::{QUERY_TODO, Tagger, is_outer_doc};
+use crate::{CodeParser, CodeQuery, LanguageExt as _, Range, TypeTagger as _};
+
+/// Last segment of a glob import (`use a::b::*`).
+pub const GLOB: &'static str = "*";
+
+/// A `todo!()` or `unimplemented!()` placeholder.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Todo {
+    pub range: Range,
+    /// The function containing the placeholder, `None` outside of a function (ex: a `const`).
+    pub function: Option<Range>,
+}
+
+/// Kinds of the items `:MistralDocument` writes a doc comment for.
+const DOCUMENTED: [&str; 5] = [
+    "function_item",
+    "function_signature_item",
+    "struct_item",
+    "enum_item",
+    "trait_item",
+];
+/// A reference is shown with its ancestor which is a child of one of these.
+const SITE_PARENTS: [&str; 6] = [
+    "block",
+    "declaration_list",
+    "source_file",
+    "field_declaration_list",
+    "match_block",
+    "parameters",
+];
+
+/// An item with the outer doc comments (`///`, `/** */`) and the attributes above it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Documented {
+    pub name: String,
+    /// Kind of the tree-sitter node (ex: `function_item`).
+    pub kind: &'static str,
+    /// Doc comments above the item or between its attributes.
+    pub docs: Vec<Range>,
+    /// From the first attribute to the end of the item.
+    pub item: Range,
+}
+
+impl<'code> CodeParser<'code, Tagger> {
+    /// Paths imported by the `use` declarations, lists and aliases are expanded :
+    /// `use crate::a::{b, c::D as E}` gives `crate::a::b` and `crate::a::c::D`.
+    pub fn list_use_paths(&mut self) -> Vec<Vec<String>> {
+        let code = self.code;
+        let query = &mut Tagger::UseDeclaration.get_query();
+        let mut matches = self.matches(query);
+        let mut paths = Vec::new();
+        while let Some(m) = matches.next() {
+            for capture in m.captures {
+                expand_use(capture.node, code, Vec::new(), &mut paths);
+            }
+        }
+        paths
+    }
+
+    /// Placeholders in order, the ones nested in another placeholder's arguments are skipped.
+    pub fn list_todos(&mut self) -> Vec<Todo> {
+        let query = &mut CodeQuery::new_valid_query(&super::Rust::language(), QUERY_TODO.into());
+        let index = query.query.capture_index_for_name("todo");
+        let mut matches = self.matches(query);
+        let mut todos: Vec<Todo> = Vec::new();
+        while let Some(m) = matches.next() {
+            let Some(capture) = m.captures.iter().find(|c| Some(c.index) == index) else {
+                continue;
+            };
+            let node = capture.node;
+            if todos
+                .last()
+                .is_some_and(|last| node.start_byte() < last.range.end_byte)
+            {
+                continue;
+            }
+            let mut function = node.parent();
+            while let Some(parent) = function
+                && parent.kind() != "function_item"
+            {
+                function = parent.parent();
+            }
+            todos.push(Todo {
+                range: Range(node.range()),
+                function: function.map(|f| Range(f.range())),
+            });
+        }
+        todos
+    }
+
+    /// The innermost function, struct, enum or trait containing `byte`, or documented by the comment
+    /// or the attribute at `byte`.
+    pub fn documented_at(&self, byte: usize) -> Option<Documented> {
+        let code = self.code;
+        let mut node = self
+            .tree
+            .root_node()
+            .descendant_for_byte_range(byte, byte)?;
+        let item = loop {
+            if DOCUMENTED.contains(&node.kind()) {
+                break node;
+            }
+            if is_outer_doc(node) || node.kind() == "attribute_item" {
+                let mut next = node.next_named_sibling();
+                while let Some(sibling) = next
+                    && (is_outer_doc(sibling) || sibling.kind() == "attribute_item")
+                {
+                    next = sibling.next_named_sibling();
+                }
+                break next.filter(|sibling| DOCUMENTED.contains(&sibling.kind()))?;
+            }
+            node = node.parent()?;
+        };
+        let name = text(item.child_by_field_name("name")?, code).to_string();
+        let mut docs = Vec::new();
+        let mut start = item;
+        let mut previous = item.prev_named_sibling();
+        while let Some(sibling) = previous {
+            if is_outer_doc(sibling) {
+                docs.push(Range(sibling.range()));
+            } else if sibling.kind() == "attribute_item" {
+                start = sibling;
+            } else {
+                break;
+            }
+            previous = sibling.prev_named_sibling();
+        }
+        docs.reverse();
+        Some(Documented {
+            name,
+            kind: item.kind(),
+            docs,
+            item: Range(start.range()) | Range(item.range()),
+        })
+    }
+
+    /// Statements (or other children of a block) naming `name`, outside of `excluded`.
+    /// `use` and `mod` declarations are skipped.
+    pub fn references(&self, name: &str, excluded: Option<Range>) -> Vec<Range> {
+        let code = self.code;
+        let mut sites: Vec<Range> = Vec::new();
+        let mut walker = self.tree.root_node().walk();
+        let mut stack = vec![self.tree.root_node()];
+        while let Some(node) = stack.pop() {
+            if excluded.is_some_and(|excluded| {
+                excluded.start_byte <= node.start_byte() && node.end_byte() <= excluded.end_byte
+            }) || matches!(node.kind(), "use_declaration" | "mod_item")
+            {
+                continue;
+            }
+            let identifier = matches!(node.kind(), "identifier" | "type_identifier" | "field_identifier");
+            if identifier && text(node, code) == name {
+                let mut site = node;
+                while let Some(parent) = site.parent()
+                    && !SITE_PARENTS.contains(&parent.kind())
+                {
+                    site = parent;
+                }
+                let range = Range(site.range());
+                if !sites.contains(&range) {
+                    sites.push(range);
+                }
+                continue;
+            }
+            stack.extend(node.named_children(&mut walker));
+        }
+        sites.sort_by_key(|site| site.start_byte);
+        sites
+    }
+
+    /// Test functions (`#[test]`, `#[tokio::test]`…) and the functions of a `mod tests`, with their attributes
+    /// and without the indentation of their module.
+    pub fn list_tests(&self) -> Vec<String> {
+        let code = self.code;
+        let mut tests = Vec::new();
+        let mut walker = self.tree.root_node().walk();
+        let mut stack = vec![self.tree.root_node()];
+        while let Some(node) = stack.pop() {
+            if node.kind() != "function_item" {
+                stack.extend(node.named_children(&mut walker));
+                continue;
+            }
+            let range = super::with_attributes(node, Range(node.range()));
+            let attributes = &code[range.start_byte..node.start_byte()];
+            let in_tests = node
+                .parent()
+                .and_then(|body| body.parent())
+                .is_some_and(|module| module.kind() == "mod_item" && is_tests_module(module, code));
+            if in_tests || str::from_utf8(attributes).is_ok_and(|attributes| attributes.contains("test]")) {
+                let text = str::from_utf8(&code[range.start_byte..range.end_byte]).unwrap_or_default();
+                tests.push((range.start_byte, dedent(text, range.start_point.column)));
+            }
+        }
+        tests.sort_by_key(|(start, _)| *start);
+        tests.into_iter().map(|(_, test)| test).collect()
+    }
+
+    /// The code with the `tests` merged in its top level `mod tests`, a test with the same name is replaced.
+    /// The module is created at the end of the code when missing.
+    pub fn merge_tests(&mut self, tests: &[String]) -> Option<Vec<u8>> {
+        let tests: Vec<String> = tests.iter().map(|test| indent(test, "    ")).collect();
+        let tests = tests.join("\n\n");
+        let mut code = self.code.to_vec();
+        let exists = {
+            let root = self.tree.root_node();
+            let mut walker = root.walk();
+            root.named_children(&mut walker)
+                .any(|item| item.kind() == "mod_item" && is_tests_module(item, self.code))
+        };
+        if exists {
+            // The blank line before each test is kept by the injection.
+            let injection = format!("#[cfg(test)]\nmod tests {{\n\n{tests}\n}}\n");
+            let modifications = self.inject(injection.as_bytes())?;
+            modifications.apply_injections(&mut code, injection.as_bytes());
+        } else {
+            if !code.ends_with(b"\n") {
+                code.push(b'\n');
+            }
+            let module = format!("\n#[cfg(test)]\nmod tests {{\n    use super::*;\n\n{tests}\n}}\n");
+            code.extend_from_slice(module.as_bytes());
+        }
+        Some(code)
+    }
+
+    /// Signatures of the top level items named in `names` (all public items and impls when empty).
+    /// Function bodies are dropped, inherent impls only keep their public methods.
+    pub fn signatures(&self, names: &[String]) -> Vec<String> {
+        let code = self.code;
+        let wanted = |name: &str| {
+            if names.is_empty() {
+                true
+            } else {
+                names.iter().any(|n| n == name)
+            }
+        };
+        let root = self.tree.root_node();
+        let mut walker = root.walk();
+        let mut signatures = Vec::new();
+        for item in root.named_children(&mut walker) {
+            let signature = match item.kind() {
+                "impl_item" => {
+                    let Some(type_name) = item.child_by_field_name("type").map(|t| base_type(t, code)) else {
+                        continue;
+                    };
+                    if !wanted(type_name) {
+                        continue;
+                    }
+                    impl_signature(item, code)
+                }
+                "function_item" | "struct_item" | "enum_item" | "trait_item" | "type_item" | "const_item"
+                | "static_item" | "union_item" => {
+                    let Some(name) = item
+                        .child_by_field_name("name")
+                        .and_then(|n| n.utf8_text(code).ok())
+                    else {
+                        continue;
+                    };
+                    if !wanted(name) || (names.is_empty() && !is_public(item)) {
+                        continue;
+                    }
+                    match item.kind() {
+                        "function_item" => function_signature(item, code),
+                        "trait_item" => trait_signature(item, code),
+                        _ => text(item, code).to_string(),
+                    }
+                }
+                _ => continue,
+            };
+            signatures.push(signature);
+        }
+        signatures
+    }
+}
+
+fn text<'code>(node: Node, code: &'code [u8]) -> &'code str {
+    node.utf8_text(code).unwrap_or_default()
+}
+
+/// A `mod tests` with a body.
+fn is_tests_module(module: Node, code: &[u8]) -> bool {
+    module.child_by_field_name("body").is_some()
+        && module
+            .child_by_field_name("name")
+            .is_some_and(|name| text(name, code) == "tests")
+}
+
+/// Remove up to `column` spaces at the start of the lines after the first one.
+fn dedent(text: &str, column: usize) -> String {
+    let mut lines = text.split('\n');
+    let mut result = lines.next().unwrap_or_default().to_string();
+    for line in lines {
+        let spaces = line.len() - line.trim_start_matches(' ').len();
+        result.push('\n');
+        result.push_str(&line[spaces.min(column)..]);
+    }
+    result
+}
+
+/// Indent the non blank lines.
+fn indent(text: &str, indent: &str) -> String {
+    text.split('\n')
+        .map(|line| {
+            if line.trim().is_empty() {
+                String::new()
+            } else {
+                format!("{indent}{line}")
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn is_public(node: Node) -> bool {
+    let mut walker = node.walk();
+    node.children(&mut walker)
+        .any(|child| child.kind() == "visibility_modifier")
+}
+
+/// `Foo` for `Foo<T>` or `module::Foo`.
+fn base_type<'code>(node: Node, code: &'code [u8]) -> &'code str {
+    match node.kind() {
+        "generic_type" => node
+            .child_by_field_name("type")
+            .map_or("", |t| base_type(t, code)),
+        "scoped_type_identifier" => node
+            .child_by_field_name("name")
+            .map_or("", |t| text(t, code)),
+        _ => text(node, code),
+    }
+}
+
+/// Everything before the body : `pub fn f(a: A) -> B;`.
+fn function_signature(node: Node, code: &[u8]) -> String {
+    let end = node
+        .child_by_field_name("body")
+        .map_or(node.end_byte(), |body| body.start_byte());
+    let head = str::from_utf8(&code[node.start_byte()..end]).unwrap_or_default();
+    format!("{};", head.trim_end())
+}
+
+fn header(node: Node, code: &[u8]) -> String {
+    let end = node
+        .child_by_field_name("body")
+        .map_or(node.end_byte(), |body| body.start_byte());
+    let head = str::from_utf8(&code[node.start_byte()..end]).unwrap_or_default();
+    head.trim_end().to_string()
+}
+
+fn trait_signature(node: Node, code: &[u8]) -> String {
+    let mut lines = vec![format!("{} {{", header(node, code))];
+    if let Some(body) = node.child_by_field_name("body") {
+        let mut walker = body.walk();
+        for item in body.named_children(&mut walker) {
+            match item.kind() {
+                "function_item" => lines.push(format!("    {}", function_signature(item, code))),
+                "function_signature_item" | "associated_type" | "const_item" => {
+                    lines.push(format!("    {}", text(item, code)))
+                }
+                _ => {}
+            }
+        }
+    }
+    lines.push("}".to_string());
+    lines.join("\n")
+}
+
+/// Trait impls are only announced, inherent impls list their public methods.
+fn impl_signature(node: Node, code: &[u8]) -> String {
+    if node.child_by_field_name("trait").is_some() {
+        return format!("{} {{}}", header(node, code));
+    }
+    let mut lines = vec![format!("{} {{", header(node, code))];
+    if let Some(body) = node.child_by_field_name("body") {
+        let mut walker = body.walk();
+        for item in body.named_children(&mut walker) {
+            if item.kind() == "function_item" && is_public(item) {
+                lines.push(format!("    {}", function_signature(item, code)));
+            }
+        }
+    }
+    lines.push("}".to_string());
+    lines.join("\n")
+}
+
+fn expand_use(node: Node, code: &[u8], mut prefix: Vec<String>, paths: &mut Vec<Vec<String>>) {
+    match node.kind() {
+        "scoped_identifier" => {
+            if let Some(path) = node.child_by_field_name("path") {
+                push_segments(path, code, &mut prefix);
+            }
+            if let Some(name) = node.child_by_field_name("name") {
+                prefix.push(text(name, code).to_string());
+            }
+            paths.push(prefix);
+        }
+        "use_as_clause" => {
+            if let Some(path) = node.child_by_field_name("path") {
+                expand_use(path, code, prefix, paths);
+            }
+        }
+        "scoped_use_list" => {
+            if let Some(path) = node.child_by_field_name("path") {
+                push_segments(path, code, &mut prefix);
+            }
+            if let Some(list) = node.child_by_field_name("list") {
+                expand_use(list, code, prefix, paths);
+            }
+        }
+        "use_list" => {
+            let mut walker = node.walk();
+            for child in node.named_children(&mut walker) {
+                expand_use(child, code, prefix.clone(), paths);
+            }
+        }
+        "use_wildcard" => {
+            let mut walker = node.walk();
+            if let Some(path) = node.named_children(&mut walker).next() {
+                push_segments(path, code, &mut prefix);
+            }
+            prefix.push(GLOB.to_string());
+            paths.push(prefix);
+        }
+        // `self` in a list imports the module itself.
+        "self" => paths.push(prefix),
+        _ => {
+            push_segments(node, code, &mut prefix);
+            paths.push(prefix);
+        }
+    }
+}
+
+fn push_segments(node: Node, code: &[u8], segments: &mut Vec<String>) {
+    if node.kind() == "scoped_identifier" {
+        if let Some(path) = node.child_by_field_name("path") {
+            push_segments(path, code, segments);
+        }
+        if let Some(name) = node.child_by_field_name("name") {
+            segments.push(text(name, code).to_string());
+        }
+    } else {
+        segments.push(text(node, code).to_string());
+    }
+}
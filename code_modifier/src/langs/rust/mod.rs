@@ -1,6 +1,8 @@
 #[cfg(test)]
 mod tests;
 
+pub mod context;
+
 use std::sync::LazyLock;
 
 use tree_sitter::QueryMatch;
@@ -25,6 +27,8 @@ pub const QUERY_CONSTANT: &'static str = r#"(const_item name: (identifier) @cons
 
 pub const QUERY_MOD_IMPORT_SUCCESSIVE: &'static str = r#"((mod_item !body name: (_)) @module_import)+"#;
 pub const QUERY_USE_SUCCESSIVE: &'static str = r#"((use_declaration argument: (_) @use))+"#;
+pub const QUERY_TODO: &'static str =
+    r#"((macro_invocation macro: (identifier) @name) @todo (#any-of? @name "todo" "unimplemented"))"#;
 
 // pub const QUERY_DECLARATION_LIST: &'static str = r#"(_ name: (_) @branchs body: (declaration_list))"#;
 pub const QUERY_DECLARATION_LIST: &'static str = r#"(_ [name: (_) type: (_)] @branchs body: (declaration_list))"#;
@@ -95,6 +99,21 @@ impl TypeTagger for Tagger {
         }
     }
 
+    /// At the start of the closing brace's line when it is alone on it (at the indentation of the branch),
+    /// so the injected items are whole lines.
+    fn parse_range_branch(branch: GroupTag<Self>) -> Range {
+        let indent = branch.range.start_point.column;
+        let mut range = branch.range.before_last_character();
+        // The point is still after the brace.
+        if range.start_point.column == indent + 1 {
+            range.0.start_byte -= indent;
+            range.0.end_byte = range.start_byte;
+            range.0.start_point.column = 0;
+            range.0.end_point = range.start_point;
+        }
+        range
+    }
+
     fn tag_from_match(&self, m: &QueryMatch, code: &[u8]) -> Option<GroupTag<Self>> {
         let target_node = m.captures.get(0)?.node;
         let Some(parent) = target_node.parent() else {
@@ -114,6 +133,11 @@ impl TypeTagger for Tagger {
                 .filter_map(|c| Range(c.node.parent()?.range()).into())
                 .reduce(|range, next_range| range | next_range),
         );
+        // The doc comments and attributes above an item belong to it (ex: `#[test]`).
+        let range = match self {
+            UseDeclaration | ModItemLeaf | DeclarationList => range,
+            _ => with_attributes(parent, range),
+        };
 
         // Parse ancestors to generate a tag's parts for this query
         let mut tag_parts: Vec<std::borrow::Cow<_>> = vec![target_name.into()];
@@ -164,3 +188,22 @@ impl TypeTagger for Tagger {
         Some(tag)
     }
 }
+
+/// `///` or `/** */`, not `//!` which documents the enclosing module.
+fn is_outer_doc(node: tree_sitter::Node) -> bool {
+    matches!(node.kind(), "line_comment" | "block_comment") && node.child_by_field_name("outer").is_some()
+}
+
+/// Extend `range` to the outer doc comments and attributes right above `item`.
+fn with_attributes(item: tree_sitter::Node, range: Range) -> Range {
+    let mut range = range;
+    let mut previous = item.prev_named_sibling();
+    while let Some(sibling) = previous {
+        if !is_outer_doc(sibling) && sibling.kind() != "attribute_item" {
+            break;
+        }
+        range = Range(sibling.range()) | range;
+        previous = sibling.prev_named_sibling();
+    }
+    range
+}
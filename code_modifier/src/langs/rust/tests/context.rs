@@ -0,0 +1,293 @@
+use super::*;
+
+const CODE_USE: &'static [u8] = br###"
+use std::sync::Arc;
+use crate::nvim::model::{self, Cursor, state::{State as S, chat::*}};
+use super::budget::Budget;
+"###;
+
+#[test]
+fn use_paths() -> Result<()> {
+    assert_no_error_in_code(CODE_USE)?;
+    let mut parser = LANG::new_parser(CODE_USE).unwrap();
+    let paths: Vec<String> = parser
+        .list_use_paths()
+        .into_iter()
+        .map(|path| path.join("::"))
+        .collect();
+    assert_eq!(
+        paths,
+        vec![
+            "std::sync::Arc",
+            "crate::nvim::model",
+            "crate::nvim::model::Cursor",
+            "crate::nvim::model::state::State",
+            "crate::nvim::model::state::chat::*",
+            "super::budget::Budget",
+        ]
+    );
+    Ok(())
+}
+
+const CODE_ITEMS: &'static [u8] = br###"
+/// Position.
+#[derive(Debug)]
+pub struct Cursor {
+    pub row: usize,
+}
+impl Cursor {
+    pub fn new(row: usize) -> Self {
+        Self { row }
+    }
+    fn private(&self) {}
+}
+impl std::fmt::Display for Cursor<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        todo!()
+    }
+}
+pub trait Move {
+    fn up(&mut self);
+    fn down(&mut self) {
+        self.up()
+    }
+}
+pub fn zero() -> Cursor {
+    Cursor::new(0)
+}
+fn hidden() {}
+"###;
+
+#[test]
+fn signatures() -> Result<()> {
+    assert_no_error_in_code(CODE_ITEMS)?;
+    let parser = LANG::new_parser(CODE_ITEMS).unwrap();
+    let signatures = parser.signatures(&["Cursor".to_string()]);
+    assert_eq!(
+        signatures,
+        vec![
+            "pub struct Cursor {\n    pub row: usize,\n}",
+            "impl Cursor {\n    pub fn new(row: usize) -> Self;\n}",
+            "impl std::fmt::Display for Cursor<T> {}",
+        ]
+    );
+    // All public items, with the impls.
+    let signatures = parser.signatures(&[]);
+    assert_eq!(
+        signatures,
+        vec![
+            "pub struct Cursor {\n    pub row: usize,\n}",
+            "impl Cursor {\n    pub fn new(row: usize) -> Self;\n}",
+            "impl std::fmt::Display for Cursor<T> {}",
+            "pub trait Move {\n    fn up(&mut self);\n    fn down(&mut self);\n}",
+            "pub fn zero() -> Cursor;",
+        ]
+    );
+    Ok(())
+}
+
+const CODE_TODO: &'static [u8] = br###"
+const C: usize = todo!();
+fn f(a: usize) -> usize {
+    if a == 0 { unimplemented!("zero") } else { todo!() }
+}
+fn g() {
+    println!("todo!()");
+    todo!(todo!())
+}
+"###;
+
+#[test]
+fn todos() -> Result<()> {
+    assert_no_error_in_code(CODE_TODO)?;
+    let mut parser = LANG::new_parser(CODE_TODO).unwrap();
+    let code = str::from_utf8(CODE_TODO).unwrap();
+    let todos: Vec<(&str, Option<&str>)> = parser
+        .list_todos()
+        .iter()
+        .map(|todo| {
+            let function = todo
+                .function
+                .map(|f| code[f.start_byte..f.end_byte].lines().next().unwrap());
+            (&code[todo.range.start_byte..todo.range.end_byte], function)
+        })
+        .collect();
+    assert_eq!(
+        todos,
+        vec![
+            ("todo!()", None),
+            ("unimplemented!(\"zero\")", Some("fn f(a: usize) -> usize {")),
+            ("todo!()", Some("fn f(a: usize) -> usize {")),
+            ("todo!(todo!())", Some("fn g() {")),
+        ]
+    );
+    Ok(())
+}
+
+const CODE_DOCUMENTED: &'static [u8] = br###"
+//! Module.
+
+/// Old doc.
+#[derive(Debug)]
+/// Between attributes.
+#[cfg(test)]
+pub struct Cursor {
+    pub row: usize,
+}
+
+impl Cursor {
+    // Not a doc comment.
+    pub fn up(&mut self) {
+        self.row -= 1;
+    }
+}
+
+fn main() {
+    let mut cursor = Cursor { row: 1 };
+    cursor.up();
+    if true {
+        cursor.up()
+    }
+}
+"###;
+
+#[test]
+fn documented() -> Result<()> {
+    assert_no_error_in_code(CODE_DOCUMENTED)?;
+    let parser = LANG::new_parser(CODE_DOCUMENTED).unwrap();
+    let code = str::from_utf8(CODE_DOCUMENTED).unwrap();
+    let lines = |range: Range| {
+        code[range.start_byte..range.end_byte]
+            .trim_end()
+            .to_string()
+    };
+    for cursor in ["Old doc", "derive", "pub row"] {
+        let item = parser.documented_at(code.find(cursor).unwrap()).unwrap();
+        assert_eq!(item.name, "Cursor");
+        let docs: Vec<String> = item.docs.into_iter().map(lines).collect();
+        assert_eq!(docs, vec!["/// Old doc.", "/// Between attributes."]);
+        assert!(lines(item.item).starts_with("#[derive(Debug)]\n/// Between attributes."));
+    }
+    let item = parser
+        .documented_at(code.find("self.row").unwrap())
+        .unwrap();
+    assert_eq!(item.name, "up");
+    assert_eq!(item.kind, "function_item");
+    assert_eq!(item.docs, vec![]);
+    assert!(lines(item.item).starts_with("pub fn up"));
+    assert_eq!(parser.documented_at(code.find("Module").unwrap()), None);
+    assert_eq!(
+        parser
+            .documented_at(code.find("let mut").unwrap())
+            .unwrap()
+            .name,
+        "main"
+    );
+
+    let sites: Vec<String> = parser
+        .references("up", Some(item.item))
+        .into_iter()
+        .map(lines)
+        .collect();
+    assert_eq!(sites, vec!["cursor.up();", "cursor.up()"]);
+    let sites: Vec<String> = parser
+        .references("Cursor", None)
+        .into_iter()
+        .map(lines)
+        .collect();
+    assert_eq!(
+        sites[1],
+        "impl Cursor {\n    // Not a doc comment.\n    pub fn up(&mut self) {\n        self.row -= 1;\n    }\n}"
+    );
+    assert_eq!(sites[2], "let mut cursor = Cursor { row: 1 };");
+    Ok(())
+}
+
+const CODE_TESTED: &'static [u8] = br###"fn add(a: u8) -> u8 {
+    a + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn add_one() {
+        assert_eq!(add(1), 3);
+    }
+}
+"###;
+
+const CODE_ANSWER: &'static [u8] = br###"
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn two() -> u8 {
+        2
+    }
+
+    #[test]
+    fn add_one() {
+        assert_eq!(add(1), two());
+    }
+
+    /// Overflow.
+    #[test]
+    #[should_panic]
+    fn add_max() {
+        add(u8::MAX);
+    }
+}
+"###;
+
+#[test]
+fn merge_tests() -> Result<()> {
+    assert_no_error_in_code(CODE_TESTED)?;
+    assert_no_error_in_code(CODE_ANSWER)?;
+    let answer = LANG::new_parser(CODE_ANSWER).unwrap();
+    let tests = answer.list_tests();
+    assert_eq!(
+        tests,
+        vec![
+            "fn two() -> u8 {\n    2\n}",
+            "#[test]\nfn add_one() {\n    assert_eq!(add(1), two());\n}",
+            "/// Overflow.\n#[test]\n#[should_panic]\nfn add_max() {\n    add(u8::MAX);\n}",
+        ]
+    );
+    let mut parser = LANG::new_parser(CODE_TESTED).unwrap();
+    let merged = parser.merge_tests(&tests).unwrap();
+    let expected = r###"fn add(a: u8) -> u8 {
+    a + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn add_one() {
+        assert_eq!(add(1), two());
+    }
+
+    fn two() -> u8 {
+        2
+    }
+
+    /// Overflow.
+    #[test]
+    #[should_panic]
+    fn add_max() {
+        add(u8::MAX);
+    }
+}
+"###;
+    assert_eq!(str::from_utf8(&merged).unwrap(), expected);
+
+    let code = b"fn add(a: u8) -> u8 {\n    a + 1\n}";
+    let mut parser = LANG::new_parser(code).unwrap();
+    let merged = parser.merge_tests(&tests[1..2]).unwrap();
+    let expected = "fn add(a: u8) -> u8 {\n    a + 1\n}\n\n#[cfg(test)]\nmod tests {\n    use super::*;\n\n    #[test]\n    fn add_one() {\n        assert_eq!(add(1), two());\n    }\n}\n";
+    assert_eq!(str::from_utf8(&merged).unwrap(), expected);
+    Ok(())
+}
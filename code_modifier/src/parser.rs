@@ -143,12 +143,15 @@ impl<'code, TT: TypeTagger> CodeParser<'code, TT> {
         if let Some(leaf) = self.find_tag(&inject_tag) {
             return TT::parse_range_leaf(leaf);
         }
-        // Then look for sub tags.
-        for branch in self.list_branches_tags() {
-            if inject_tag.tag.starts_with(branch.tag.as_slice()) {
-                // Inject into the branch.
-                TT::parse_range_branch(branch);
-            }
+        // Then look for the innermost branch containing it.
+        let branch = self
+            .list_branches_tags()
+            .into_iter()
+            .filter(|branch| inject_tag.tag.starts_with(branch.tag.as_slice()))
+            .max_by_key(|branch| branch.tag.len());
+        if let Some(branch) = branch {
+            // Inject into the branch.
+            return TT::parse_range_branch(branch);
         }
         // Nothing found then inject to then end of the code.
         TT::parse_range_no_match(&inject_tag)
@@ -166,7 +169,18 @@ impl<'code, TT: TypeTagger> CodeParser<'code, TT> {
         let mut modifications: Vec<_> = inject_parser
             .list_leafs_tags()
             .into_iter()
-            .map(|tag| (self.range_where_to_inject(&tag), tag.range))
+            // Reversed, so the leafs injected at the same place keep their order.
+            .rev()
+            .map(|tag| {
+                let target = self.range_where_to_inject(&tag);
+                // Inserted at the start of a line : the injected item takes its whole lines.
+                let range = if target.start_byte == target.end_byte && target.start_point.column == 0 {
+                    whole_lines(inject, tag.range)
+                } else {
+                    tag.range
+                };
+                (target, range)
+            })
             // .map(|tag| (self.(&tag), tag.range))
             .collect();
         //
@@ -174,3 +188,39 @@ impl<'code, TT: TypeTagger> CodeParser<'code, TT> {
         Some(ReversedInjectionsRanges(modifications))
     }
 }
+
+/// `range` from the start of its first line, when only indented, to after the new line ending it.
+fn whole_lines(code: &[u8], range: Range) -> Range {
+    let mut range = range;
+    let line_start = code[..range.start_byte]
+        .iter()
+        .rposition(|&c| c == b'\n')
+        .map_or(0, |i| i + 1);
+    if code[line_start..range.start_byte]
+        .iter()
+        .all(u8::is_ascii_whitespace)
+    {
+        range.0.start_byte = line_start;
+        range.0.start_point.column = 0;
+        // With the blank line separating it from the previous item.
+        if let Some(previous) = line_start.checked_sub(1) {
+            let previous_start = code[..previous]
+                .iter()
+                .rposition(|&c| c == b'\n')
+                .map_or(0, |i| i + 1);
+            if code[previous_start..previous]
+                .iter()
+                .all(u8::is_ascii_whitespace)
+            {
+                range.0.start_byte = previous_start;
+                range.0.start_point.row -= 1;
+            }
+        }
+    }
+    if code.get(range.end_byte) == Some(&b'\n') {
+        range.0.end_byte += 1;
+        range.0.end_point.row += 1;
+        range.0.end_point.column = 0;
+    }
+    range
+}
@@ -0,0 +1,22 @@
+#![no_main]
+
+use chat_format::{escape_quote_arg, escape_whitespace, unescape_quote_arg, unescape_whitespace};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|value: String| {
+    let escaped = escape_quote_arg(value.clone());
+    // Every quote of an attribute value is escaped.
+    let mut chars = escaped.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => assert!(matches!(chars.next(), Some('\\' | '"'))),
+            c => assert_ne!(c, '"'),
+        }
+    }
+    assert_eq!(unescape_quote_arg(&escaped), value);
+    // Any value, even badly escaped by hand.
+    let _ = unescape_quote_arg(&value);
+    // The end of a content, in the `trailing` attribute.
+    assert_eq!(unescape_whitespace(&escape_whitespace(&value)), value);
+    let _ = unescape_whitespace(&value);
+});
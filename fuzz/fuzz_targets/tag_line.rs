@@ -0,0 +1,28 @@
+#![no_main]
+
+use chat_format::{parse_tag_line, unescape_quote_arg, write_arg};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|input: (String, Vec<(String, String)>)| {
+    let (line, args) = input;
+    // Any line written by hand.
+    parse_tag_line(&line, |_key, _val, _cols| {});
+
+    // The attributes written by the plugin are read back.
+    let args: Vec<(String, String)> = args
+        .into_iter()
+        .filter(|(key, value)| {
+            !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') && !value.contains('\n')
+        })
+        .collect();
+    let mut line = String::new();
+    for (key, value) in &args {
+        write_arg(&mut line, key, value);
+    }
+    let line = format!("<MESSAGE{line}/>");
+    let mut parsed = Vec::new();
+    parse_tag_line(&line, |key, value, _cols| {
+        parsed.push((key, unescape_quote_arg(&value)))
+    });
+    assert_eq!(parsed, args);
+});
@@ -0,0 +1,17 @@
+#![no_main]
+
+use chat_format::parse_usage;
+use libfuzzer_sys::fuzz_target;
+
+fn usage_attribute([prompt_tokens, completion_tokens, total_tokens]: [u32; 3]) -> String {
+    format!("{prompt_tokens};{completion_tokens};{total_tokens}")
+}
+
+fuzz_target!(|input: (String, [u32; 3])| {
+    let (text, tokens) = input;
+    // Any `usage` attribute written by hand.
+    let usage = parse_usage(&text);
+    assert_eq!(parse_usage(&usage_attribute(usage)), usage);
+
+    assert_eq!(parse_usage(&usage_attribute(tokens)), tokens);
+});
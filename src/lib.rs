@@ -51,6 +51,28 @@ pub fn mistral_nvim() -> oxi::Result<()> {
                                     notify::error(format!("Chat : {}", err));
                                 }
                             }
+                            messages::IdMessage::Suggestion(buf_handle, id) => {
+                                if let Err(err) = nvim::vue::suggestion::handle_nvim_message(buf_handle, id, message, &s)
+                                {
+                                    notify::error(format!("Suggestion : {}", err));
+                                }
+                            }
+                            messages::IdMessage::Edit(buf_handle, id) => {
+                                if let Err(err) = nvim::vue::edit::handle_nvim_message(buf_handle, id, message, &s) {
+                                    notify::error(format!("Edit : {}", err));
+                                }
+                            }
+                            messages::IdMessage::Tests(buf_handle, id) => {
+                                if let Err(err) = nvim::vue::unit_tests::handle_nvim_message(buf_handle, id, message, &s)
+                                {
+                                    notify::error(format!("Tests : {}", err));
+                                }
+                            }
+                            messages::IdMessage::Ocr(buf_handle, id) => {
+                                if let Err(err) = nvim::vue::ocr::handle_nvim_message(buf_handle, id, message, &s) {
+                                    notify::error(format!("OCR : {}", err));
+                                }
+                            }
                         }
                     })
                 }
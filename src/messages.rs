@@ -5,7 +5,7 @@ use crate::{
     mistral,
     nvim::{
         self,
-        model::{Cursor, state::chat::MsgIndex},
+        model::{Cursor, config::FimBackend, state::chat::MsgIndex},
     },
     utils::notify::NotifyLevel,
 };
@@ -22,6 +22,13 @@ pub type BufferHandle = i32;
 pub enum IdMessage {
     FIM(BufferHandle, usize),
     Chat(BufferHandle, MsgIndex),
+    Suggestion(BufferHandle, usize),
+    /// Tests generated by `:MistralGenerateTests`.
+    Tests(BufferHandle, usize),
+    /// Rewrite of `:MistralEdit`.
+    Edit(BufferHandle, usize),
+    /// Markdown of `:MistralOcr`, inserted without the FIM post process.
+    Ocr(BufferHandle, usize),
 }
 
 pub struct MistralEnveloppe {
@@ -32,10 +39,16 @@ pub enum MistralMessage {
     InitializeTask(nvim::model::Cursor),
     // InitializeTask { id: Uuid, cursor: nvim::model::Cursor },
     UpdateContent(Vec<String>),
+    /// Reasoning trace of Magistral models, streamed before the content.
+    UpdateThinking(Vec<String>),
     UpdateRole(mistral::model::Role),
     // UpdateContent { id: Uuid, chunk: Vec<String> },
     RunTool(Vec<mistral::model::ToolCall>),
     FinalizeTask(mistral::model::stream::StreamResponse),
+    /// The OCR result is now in cache (or has failed), the parser can embed it.
+    OcrDone(mistral::model::ocr::OcrSource),
+    /// `cargo test` ran on the merged tests.
+    CargoTestDone(mistral::controlleur::cargo::CargoReport),
     Notify { message: String, level: NotifyLevel },
 }
 pub struct RunToolMessage {
@@ -83,19 +96,89 @@ pub enum NvimMessage {
     Abort,
     FimCursorLine(Normal),
     FimFunction(Normal),
-    // FimStatement(Normal),
-    // FimStructure(Normal),
+    FimStatement(Normal),
+    FimStructure(Normal),
+    FimImpl(Normal),
     FimVisual(Visual),
+    FimSuggestion(Suggest),
+    FimPlaceholder(Placeholder),
+    Edit(Edit),
+    Document(Document),
+    GenerateTests(GenerateTests),
+    CargoTest(CargoTest),
     Chat(mistral::model::completion::ChatRequest),
+    Ocr(Ocr),
 }
 
 pub struct Normal {
     pub data: nvim::model::BufferData,
+    pub backend: FimBackend,
+}
+
+pub struct Suggest {
+    pub data: nvim::model::BufferData,
+    pub backend: FimBackend,
+    /// Index of the candidate, alternatives are sampled with another seed.
+    pub candidate: usize,
+}
+
+/// A removed `todo!()`, the completion is inserted at `data.cursor`.
+pub struct Placeholder {
+    pub data: nvim::model::BufferData,
+    /// Start of the enclosing function.
+    pub prefix: String,
+    /// End of the enclosing function.
+    pub suffix: String,
+    pub backend: FimBackend,
+}
+
+/// An item for `:MistralDocument`, its doc comment is replaced once the answer is complete.
+pub struct Document {
+    /// The item with its previous doc comment.
+    pub item: String,
+    /// Name of the item, searched in the other files of the git index.
+    pub name: String,
+    /// The file of the item, relative to the working directory.
+    pub path: Option<std::path::PathBuf>,
+    /// Statements of the file using the item, each one after a `// path:row` comment.
+    pub call_sites: Vec<String>,
+    pub backend: FimBackend,
+}
+
+/// A Rust function for `:MistralGenerateTests`.
+pub struct GenerateTests {
+    /// The function with its doc comment and attributes.
+    pub function: String,
+    /// Name of the function, the generated tests start with it.
+    pub name: String,
+    /// Tests already in the file.
+    pub existing: Vec<String>,
+    pub backend: FimBackend,
+}
+
+pub struct CargoTest {
+    /// Only the tests whose path contains it are run.
+    pub filter: String,
+    /// Where cargo looks for the manifest.
+    pub dir: std::path::PathBuf,
+}
+
+pub struct Ocr {
+    pub source: mistral::model::ocr::OcrSource,
+    /// Where to insert the Markdown. When `None`, the result is only cached.
+    pub cursor: Option<Cursor>,
+}
+
+/// The selection of `:MistralEdit`, rewritten as told by the instruction.
+pub struct Edit {
+    pub visual: Visual,
+    pub instruction: String,
 }
 
 pub struct Visual {
     pub data: nvim::model::BufferData,
     pub selection: nvim::model::Selection,
+    pub backend: FimBackend,
 }
 
 impl Visual {
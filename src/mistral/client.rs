@@ -13,7 +13,10 @@ use crate::{
     messages::{self, IdMessage, MistralEnveloppe, MistralMessage},
     mistral::{
         controlleur::fim::SenderHandle,
-        model::stream::{ErrorMessageType, Status, StreamError, StreamEvent, StreamParam, StreamResponse},
+        model::{
+            ocr::OcrResponse,
+            stream::{ErrorMessageType, Status, StreamError, StreamEvent, StreamParam, StreamResponse},
+        },
     },
 };
 
@@ -38,6 +41,8 @@ pub struct MistralClient(Arc<MistralClientInner>);
 struct MistralClientInner {
     client: ReqwestClient,
     api_key: String,
+    /// `MISTRAL_API_URL`, any server with the same API (ex: a local one).
+    url: String,
     sendle_nvim: SenderHandle,
 }
 
@@ -59,10 +64,12 @@ impl MistralClient {
     pub fn new(sendle_nvim: SenderHandle) -> Self {
         let client = ReqwestClient::new();
         let api_key = std::env::var("MISTRAL_API_KEY").expect("No env var MISTRAL_API_KEY.");
+        let url = std::env::var("MISTRAL_API_URL").unwrap_or_else(|_| "https://api.mistral.ai/v1".to_string());
         // logs!("Mistral API : '{}'", api_key);
         Self(Arc::new(MistralClientInner {
             client,
             api_key,
+            url,
             sendle_nvim,
         }))
     }
@@ -70,7 +77,7 @@ impl MistralClient {
     pub fn request(&self, method: reqwest::Method, endpoint: &str) -> reqwest::RequestBuilder {
         self.0
             .client
-            .request(method, format!("https://api.mistral.ai/v1/{}", endpoint))
+            .request(method, format!("{}/{}", self.0.url, endpoint))
             .header("Authorization", format!("Bearer {}", self.0.api_key))
     }
 
@@ -198,6 +205,39 @@ impl MistralClient {
         response.usage.total_tokens = 101;
         self.send(id, MistralMessage::FinalizeTask(response));
     }
+    #[cfg(feature = "prod_mode")]
+    pub async fn ocr(&self, body: serde_json::Value) -> crate::Result<OcrResponse> {
+        let body = body.to_string();
+        let request = move |client: &Self| {
+            client
+                .request(reqwest::Method::POST, "ocr")
+                .header("Content-Type", "application/json")
+                .body(body.clone())
+        };
+        let response = self
+            .send_request(request)
+            .await
+            .map_err(|status| status.to_string())?;
+        let is_success = response.status().is_success();
+        let text = response
+            .text()
+            .await
+            .map_err(|err| err.to_string())?;
+        if !is_success {
+            return Err(format!("OCR request failed : {text}").into());
+        }
+        Ok(serde_json::from_str(&text)?)
+    }
+    #[cfg(not(feature = "prod_mode"))]
+    pub async fn ocr(&self, body: serde_json::Value) -> crate::Result<OcrResponse> {
+        let _ = body;
+        Ok(OcrResponse {
+            pages: vec![crate::mistral::model::ocr::OcrPage {
+                index: 0,
+                markdown: "# Spécification\n\n| Champ | Type |\n| --- | --- |\n| id | u32 |".to_string(),
+            }],
+        })
+    }
     #[allow(dead_code)]
     async fn stream_inner<Callback>(
         &self,
@@ -0,0 +1,95 @@
+use crate::{
+    messages::{self, IdMessage, MistralMessage},
+    mistral::controlleur::fim::SharedContext,
+};
+
+/// Errors kept in the summary of a build failure.
+const MAX_ERRORS: usize = 3;
+
+/// Result of `cargo test <filter>`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct CargoReport {
+    /// `false` when the crate (or its tests) did not build.
+    pub compiled: bool,
+    pub passed: bool,
+    /// The `test result:` lines, or the first errors when it did not build.
+    pub summary: String,
+}
+
+impl CargoReport {
+    /// From the exit status and the output (stdout then stderr) of `cargo test`.
+    pub fn parse(success: bool, output: &str) -> Self {
+        let results: Vec<&str> = output
+            .lines()
+            .map(str::trim)
+            .filter(|line| line.starts_with("test result:"))
+            .collect();
+        if !success && results.is_empty() {
+            let errors: Vec<&str> = output
+                .lines()
+                .filter(|line| line.starts_with("error"))
+                .take(MAX_ERRORS)
+                .collect();
+            return Self {
+                compiled: false,
+                passed: false,
+                summary: errors.join("\n"),
+            };
+        }
+        Self {
+            compiled: true,
+            passed: success,
+            summary: results.join("\n"),
+        }
+    }
+}
+
+/// Run `cargo test <filter>` in the directory of the file, Neovim is not blocked while it builds.
+pub async fn test(id: IdMessage, message: messages::CargoTest, context: SharedContext) -> crate::Result<()> {
+    let messages::CargoTest { filter, dir } = message;
+    let output = tokio::process::Command::new("cargo")
+        .arg("test")
+        .arg(&filter)
+        .current_dir(dir)
+        .kill_on_drop(true)
+        .output()
+        .await;
+    // Always answer, the buffer waits for the report to keep or restore the tests.
+    let report = match output {
+        Ok(output) => {
+            let text = String::from_utf8_lossy(&output.stdout) + String::from_utf8_lossy(&output.stderr);
+            CargoReport::parse(output.status.success(), &text)
+        }
+        Err(err) => CargoReport {
+            compiled: false,
+            passed: false,
+            summary: format!("Can't run cargo : {err}"),
+        },
+    };
+    context
+        .nvim_sendle
+        .send(id, MistralMessage::CargoTestDone(report));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report() {
+        let output = "running 2 tests\ntest tests::up_first ... ok\ntest tests::up_last ... FAILED\n\n\
+                      test result: FAILED. 1 passed; 1 failed; 0 ignored\n";
+        let report = CargoReport::parse(false, output);
+        assert!(report.compiled && !report.passed);
+        assert_eq!(report.summary, "test result: FAILED. 1 passed; 1 failed; 0 ignored");
+        let output = "   Compiling a v0.1.0\nerror[E0425]: cannot find value `b`\n  --> src/lib.rs:3:5\n\
+                      error: could not compile `a` (lib test) due to 1 previous error\n";
+        let report = CargoReport::parse(false, output);
+        assert!(!report.compiled);
+        assert_eq!(
+            report.summary,
+            "error[E0425]: cannot find value `b`\nerror: could not compile `a` (lib test) due to 1 previous error"
+        );
+    }
+}
@@ -0,0 +1,119 @@
+use crate::mistral::model::completion::Model;
+
+/// Conservative estimation for code, tokenizers average more than 3 chars per token.
+const CHARS_PER_TOKEN: usize = 3;
+/// Kept for the completion when `max_tokens` is not set.
+const COMPLETION_TOKENS: u32 = 4096;
+/// Share of the budget for the suffix when both sides are longer than the budget.
+const SUFFIX_RATIO: usize = 4;
+/// Maximal share of the budget for the signatures of the imported items.
+const HEADER_RATIO: usize = 4;
+/// Context window assumed for the models unknown to Mistral (ex: local ones).
+const DEFAULT_CONTEXT: u32 = 32_000;
+
+/// Size of the context sent around the target, so the prompt always fits in the model.
+pub struct Budget {
+    chars: usize,
+}
+
+impl Budget {
+    pub fn new(chars: usize) -> Self {
+        Self { chars }
+    }
+    pub fn for_model(model: &str, max_tokens: Option<u32>) -> Self {
+        let reserved = max_tokens.unwrap_or(COMPLETION_TOKENS);
+        let context = Model::from_name(model).map_or(DEFAULT_CONTEXT, |model| model.context_size());
+        let tokens = context.saturating_sub(reserved);
+        Self::new(tokens as usize * CHARS_PER_TOKEN)
+    }
+
+    /// Keep the first lines of the header, the rest of the budget goes to the prefix and the suffix.
+    pub fn header(&mut self, header: &str) -> String {
+        let header = keep_start(header, self.chars / HEADER_RATIO);
+        self.chars -= header.len();
+        header.to_string()
+    }
+
+    /// Keep the end of the prefix (where the completion starts) and the start of the suffix.
+    /// Both are cut on line boundaries, the unused share of one side goes to the other. The line of the cursor is
+    /// kept even when it is longer than the budget, truncated at its start.
+    pub fn trim(&self, prefix: &str, suffix: &str) -> (String, String) {
+        let suffix_share = std::cmp::min(self.chars / SUFFIX_RATIO, suffix.len());
+        let prefix = keep_end(prefix, self.chars - suffix_share);
+        let suffix = keep_start(suffix, self.chars.saturating_sub(prefix.len()));
+        (prefix.to_string(), suffix.to_string())
+    }
+}
+
+fn keep_end(text: &str, budget: usize) -> &str {
+    if text.len() <= budget {
+        return text;
+    }
+    let mut start = text.len() - budget;
+    while !text.is_char_boundary(start) {
+        start += 1;
+    }
+    match text[start..].find('\n') {
+        Some(line_break) => &text[start + line_break + 1..],
+        // The line of the cursor is longer than the budget, its end is the closest context.
+        None => &text[start..],
+    }
+}
+
+fn keep_start(text: &str, budget: usize) -> &str {
+    if text.len() <= budget {
+        return text;
+    }
+    let mut end = budget;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    match text[..end].rfind('\n') {
+        Some(line_break) => &text[..line_break],
+        None => "",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trim() {
+        let prefix = "use a;\nuse b;\nfn main() {";
+        let suffix = "}\n\nfn other() {}\n";
+        let (p, s) = Budget::new(100).trim(prefix, suffix);
+        assert_eq!((p.as_str(), s.as_str()), (prefix, suffix));
+        // Lines cut in the middle are dropped, the suffix gets what the prefix left.
+        let (p, s) = Budget::new(20).trim(prefix, suffix);
+        assert_eq!(p, "fn main() {");
+        assert_eq!(s, "}\n");
+        // Short suffix, the prefix takes the rest.
+        let (p, s) = Budget::new(20).trim(prefix, "}");
+        assert_eq!(p, "use b;\nfn main() {");
+        assert_eq!(s, "}");
+    }
+
+    #[test]
+    fn trim_long_cursor_line() {
+        let prefix = "fn main() {\n    let message = \"éèà\"; let value = ";
+        let (p, s) = Budget::new(15).trim(prefix, "");
+        assert_eq!(p, "\"; let value = ");
+        assert!(s.is_empty());
+        // Cut on a char boundary.
+        let (p, _) = Budget::new(16).trim(prefix, "");
+        assert_eq!(p, "\"; let value = ");
+        let (p, _) = Budget::new(17).trim(prefix, "");
+        assert_eq!(p, "à\"; let value = ");
+    }
+
+    #[test]
+    fn header() {
+        let mut budget = Budget::new(80);
+        let header = "// From src/a.rs :\n// pub fn a();\n// pub fn b();";
+        assert_eq!(budget.header(header), "// From src/a.rs :");
+        assert_eq!(budget.chars, 80 - "// From src/a.rs :".len());
+        let mut budget = Budget::new(200);
+        assert_eq!(budget.header(header), header);
+    }
+}
@@ -0,0 +1,66 @@
+use std::collections::VecDeque;
+
+use sha2::{Digest as _, Sha256};
+
+/// Completions kept, the least recently used is dropped first.
+const CAPACITY: usize = 64;
+
+/// Hash of the whole request body : model, prompt, suffix and params.
+pub type Key = [u8; 32];
+
+pub fn key(body: &serde_json::Value) -> Key {
+    Sha256::digest(body.to_string().as_bytes()).into()
+}
+
+/// Completions of the last FIM requests, replayed when the same request is sent again (ex: after an undo).
+pub struct Cache {
+    capacity: usize,
+    /// The most recently used first.
+    entries: VecDeque<(Key, String)>,
+}
+
+impl Default for Cache {
+    fn default() -> Self {
+        Self::new(CAPACITY)
+    }
+}
+
+impl Cache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: VecDeque::with_capacity(capacity),
+        }
+    }
+    pub fn get(&mut self, key: &Key) -> Option<String> {
+        let position = self.entries.iter().position(|(k, _)| k == key)?;
+        let entry = self.entries.remove(position)?;
+        let completion = entry.1.clone();
+        self.entries.push_front(entry);
+        Some(completion)
+    }
+    pub fn insert(&mut self, key: Key, completion: String) {
+        self.entries.retain(|(k, _)| *k != key);
+        self.entries.push_front((key, completion));
+        self.entries.truncate(self.capacity);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn least_recently_used() {
+        let [a, b, c] = [0, 1, 2].map(|i| key(&serde_json::json!({ "prompt": i })));
+        let mut cache = Cache::new(2);
+        cache.insert(a, "a".to_string());
+        cache.insert(b, "b".to_string());
+        assert_eq!(cache.get(&a).as_deref(), Some("a"));
+        // `b` is the least recently used.
+        cache.insert(c, "c".to_string());
+        assert_eq!(cache.get(&b), None);
+        assert_eq!(cache.get(&a).as_deref(), Some("a"));
+        assert_eq!(cache.get(&c).as_deref(), Some("c"));
+    }
+}
@@ -0,0 +1,151 @@
+use std::path::{Path, PathBuf};
+
+use code_modifier::{LanguageExt as _, Range, langs::rust::Rust};
+
+use super::template;
+use crate::mistral::model::{Message, Role};
+
+/// Call sites sent with the item, the ones of the current file first.
+pub const MAX_CALL_SITES: usize = 8;
+/// Longer call sites are cut.
+const MAX_SITE_LINES: usize = 12;
+
+/// The call site after a `// path:row` comment.
+pub fn call_site(path: &Path, code: &str, range: Range) -> String {
+    let lines: Vec<&str> = code[range.start_byte..range.end_byte]
+        .lines()
+        .take(MAX_SITE_LINES)
+        .collect();
+    let row = range.start_point.row + 1;
+    format!("// {}:{row}\n{}", path.display(), lines.join("\n"))
+}
+
+/// Path relative to the working directory when it is inside.
+pub fn relative(path: &Path) -> PathBuf {
+    std::env::current_dir()
+        .ok()
+        .and_then(|dir| path.strip_prefix(dir).ok())
+        .map_or_else(|| path.to_path_buf(), Path::to_path_buf)
+}
+
+/// Call sites in the other Rust files of the git index, none outside of a repository. The repository is
+/// searched from the working directory up, the files are read : call it on a blocking thread.
+pub fn project_call_sites(name: &str, current: Option<&Path>, max: usize) -> Vec<String> {
+    let Some(repo) = std::env::current_dir()
+        .ok()
+        .and_then(|cwd| gix::discover(cwd).ok())
+    else {
+        return Vec::new();
+    };
+    let (Some(work_dir), Ok(index)) = (repo.workdir(), repo.index()) else {
+        return Vec::new();
+    };
+    let mut sites = Vec::new();
+    for entry in index.entries() {
+        if sites.len() == max {
+            break;
+        }
+        // Index paths are relative to the work tree, the call sites show them relative to the working directory.
+        let path = relative(&work_dir.join(entry.path(&index).to_string()));
+        if path.extension().is_none_or(|ext| ext != "rs") || current.is_some_and(|current| current == path) {
+            continue;
+        }
+        let Ok(code) = std::fs::read_to_string(&path) else {
+            continue;
+        };
+        if !code.contains(name) {
+            continue;
+        }
+        let Some(parser) = Rust::new_parser(code.as_bytes()) else {
+            continue;
+        };
+        let missing = max - sites.len();
+        let references = parser.references(name, None).into_iter().take(missing);
+        sites.extend(references.map(|range| call_site(&path, &code, range)));
+    }
+    sites
+}
+
+const SYSTEM: &'static str = "You write the rustdoc comment of the Rust item sent by the user. Answer with the doc \
+                              comment only, as `///` lines, without the item. Start with a one line summary of what \
+                              the item is for, the call sites show how it is used. Add `# Errors` or `# Panics` \
+                              sections only when they apply. Keep what is still true in the current doc comment.";
+
+/// Chat messages asking the doc comment of `item`, which starts with its current doc comment if any.
+pub fn messages(item: &str, call_sites: &[String]) -> Vec<Message> {
+    let mut prompt = format!("Item :\n```rust\n{item}\n```");
+    if !call_sites.is_empty() {
+        prompt.push_str("\n\nCall sites :\n```rust\n");
+        prompt.push_str(&call_sites.join("\n\n"));
+        prompt.push_str("\n```");
+    }
+    vec![
+        Message {
+            role: Role::System,
+            content: SYSTEM.to_string(),
+            ..Default::default()
+        },
+        Message {
+            role: Role::User,
+            content: prompt,
+            ..Default::default()
+        },
+    ]
+}
+
+/// The `///` block written above the item (ending with a new line), from the answer of the model.
+/// When the answer repeats the item, only its first run of `///` lines is kept.
+pub fn doc_comment(answer: &str, indent: &str) -> String {
+    let answer = template::code_block(answer);
+    let lines: Vec<&str> = answer.lines().map(str::trim).collect();
+    let text: Vec<&str> = match lines.iter().position(|line| line.starts_with("///")) {
+        Some(start) => lines[start..]
+            .iter()
+            .take_while(|line| line.starts_with("///"))
+            .map(|line| line.trim_start_matches('/'))
+            .map(|line| line.strip_prefix(' ').unwrap_or(line))
+            .collect(),
+        None => lines
+            .iter()
+            .map(|line| {
+                let line = line
+                    .trim_start_matches("/**")
+                    .trim_end_matches("*/")
+                    .trim_start_matches('*');
+                line.strip_prefix(' ').unwrap_or(line)
+            })
+            .collect(),
+    };
+    let start = text
+        .iter()
+        .position(|line| !line.trim().is_empty())
+        .unwrap_or(text.len());
+    let end = text
+        .iter()
+        .rposition(|line| !line.trim().is_empty())
+        .map_or(start, |i| i + 1);
+    text[start..end]
+        .iter()
+        .map(|line| match line.trim_end() {
+            "" => format!("{indent}///\n"),
+            line => format!("{indent}/// {line}\n"),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prefixed_block() {
+        let answer = "```rust\n/// Moves up.\n///\n/// # Panics\n/// At the first row.\npub fn up(&mut self) {\n```";
+        assert_eq!(
+            doc_comment(answer, "    "),
+            "    /// Moves up.\n    ///\n    /// # Panics\n    /// At the first row.\n"
+        );
+        assert_eq!(doc_comment("\nMoves up.\n\n", ""), "/// Moves up.\n");
+        assert_eq!(doc_comment("/**\n * Moves up.\n */", ""), "/// Moves up.\n");
+        assert_eq!(doc_comment("", ""), "");
+    }
+}
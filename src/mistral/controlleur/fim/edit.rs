@@ -0,0 +1,25 @@
+use crate::mistral::model::{Message, Role};
+
+const SYSTEM: &'static str = "You rewrite the code selected by the user as told by the instruction. Answer with the \
+                              whole rewritten selection in a single code block, nothing else : same indentation, \
+                              no explanation, and the lines which don't need a change are kept as is.";
+
+/// Chat messages asking the rewrite of `selection`, the rest of the file is context only.
+pub fn messages(instruction: &str, filetype: &str, selection: &str, prefix: &str, suffix: &str) -> Vec<Message> {
+    let prompt = format!(
+        "Code before the selection :\n```{filetype}\n{prefix}\n```\n\nCode after the selection :\n```{filetype}\n\
+         {suffix}\n```\n\nSelection :\n```{filetype}\n{selection}\n```\n\nInstruction : {instruction}"
+    );
+    vec![
+        Message {
+            role: Role::System,
+            content: SYSTEM.to_string(),
+            ..Default::default()
+        },
+        Message {
+            role: Role::User,
+            content: prompt,
+            ..Default::default()
+        },
+    ]
+}
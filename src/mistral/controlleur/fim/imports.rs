@@ -0,0 +1,173 @@
+use std::path::{Path, PathBuf};
+
+use code_modifier::{
+    LanguageExt as _,
+    langs::rust::{Rust, context::GLOB},
+};
+
+use crate::nvim::model::BufferData;
+
+/// Signatures of the project's items imported by the buffer's `use` declarations, as comments.
+/// Empty for other languages, files outside of a cargo project and external crates. Blocking, the module
+/// files are read from disk. The `pub use` re-exports are not followed : an item re-exported by a module
+/// is only found when it's declared in that module's file.
+pub fn header(data: &BufferData) -> String {
+    let Some(file) = data.filepath.as_deref() else {
+        return String::new();
+    };
+    if data.filetype != "rust" {
+        return String::new();
+    }
+    let Some(root) = file
+        .ancestors()
+        .skip(1)
+        .find(|dir| dir.join("Cargo.toml").is_file())
+    else {
+        return String::new();
+    };
+    let src = root.join("src");
+    let Some(current) = module_path(&src, file) else {
+        return String::new();
+    };
+    let content = data.content.join("\n");
+    let Some(mut parser) = Rust::new_parser(content.as_bytes()) else {
+        return String::new();
+    };
+
+    // Names imported from each file, `None` when the whole module is imported.
+    let mut imports: Vec<(PathBuf, Option<Vec<String>>)> = Vec::new();
+    for path in parser.list_use_paths() {
+        let Some((module_file, item)) = resolve(&src, &current, &path) else {
+            continue;
+        };
+        if module_file == file {
+            continue;
+        }
+        let position = match imports.iter().position(|(f, _)| *f == module_file) {
+            Some(position) => position,
+            None => {
+                imports.push((module_file, Some(Vec::new())));
+                imports.len() - 1
+            }
+        };
+        match item {
+            Some(item) => {
+                if let Some(names) = &mut imports[position].1 {
+                    names.push(item);
+                }
+            }
+            None => imports[position].1 = None,
+        }
+    }
+
+    let mut lines = Vec::new();
+    for (module_file, names) in imports {
+        let Ok(code) = std::fs::read(&module_file) else {
+            continue;
+        };
+        let Some(parser) = Rust::new_parser(&code) else {
+            continue;
+        };
+        let signatures = parser.signatures(names.as_deref().unwrap_or_default());
+        if signatures.is_empty() {
+            continue;
+        }
+        let relative = module_file.strip_prefix(root).unwrap_or(&module_file);
+        lines.push(format!("// From {} :", relative.display()));
+        for line in signatures.iter().flat_map(|signature| signature.lines()) {
+            lines.push(format!("// {line}"));
+        }
+    }
+    lines.join("\n")
+}
+
+/// `src/a/b.rs` and `src/a/b/mod.rs` are the module `a::b`, `src/lib.rs` is the crate root.
+fn module_path(src: &Path, file: &Path) -> Option<Vec<String>> {
+    let relative = file.strip_prefix(src).ok()?.with_extension("");
+    let mut module: Vec<String> = relative
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy().to_string())
+        .collect();
+    match module.as_slice() {
+        [root] if root == "lib" || root == "main" => module.clear(),
+        [.., last] if last == "mod" => {
+            module.pop();
+        }
+        _ => {}
+    }
+    Some(module)
+}
+
+fn module_file(src: &Path, module: &[String]) -> Option<PathBuf> {
+    let candidates = if module.is_empty() {
+        vec![src.join("lib.rs"), src.join("main.rs")]
+    } else {
+        let dir: PathBuf = module.iter().collect();
+        vec![src.join(&dir).with_extension("rs"), src.join(&dir).join("mod.rs")]
+    };
+    candidates.into_iter().find(|file| file.is_file())
+}
+
+/// File of the module and the imported item, `None` as item when the whole module is imported.
+fn resolve(src: &Path, current: &[String], path: &[String]) -> Option<(PathBuf, Option<String>)> {
+    let (mut module, mut rest) = match path.first()?.as_str() {
+        "crate" => (Vec::new(), &path[1..]),
+        "self" => (current.to_vec(), &path[1..]),
+        "super" => (current.to_vec(), path),
+        // A child module of the current one (`use model::Cursor;` next to `mod model;`).
+        first if module_file(src, &[current, &[first.to_string()]].concat()).is_some() => (current.to_vec(), path),
+        // External crate.
+        _ => return None,
+    };
+    while let Some(("super", tail)) = rest.split_first().map(|(head, tail)| (head.as_str(), tail)) {
+        module.pop();
+        rest = tail;
+    }
+    let mut item = None;
+    for segment in rest {
+        module.push(segment.clone());
+        if module_file(src, &module).is_none() {
+            module.pop();
+            item = Some(segment.clone());
+            break;
+        }
+    }
+    let item = item.filter(|item| item != GLOB);
+    Some((module_file(src, &module)?, item))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_paths() {
+        let root = std::env::temp_dir().join(format!("mistral_imports_{}", std::process::id()));
+        let src = root.join("src");
+        std::fs::create_dir_all(src.join("nvim/model")).unwrap();
+        for file in ["lib.rs", "nvim/mod.rs", "nvim/model/mod.rs", "nvim/model/cursor.rs"] {
+            std::fs::write(src.join(file), "").unwrap();
+        }
+        let path = |p: &str| p.split("::").map(String::from).collect::<Vec<_>>();
+        let current = module_path(&src, &src.join("nvim/mod.rs")).unwrap();
+        assert_eq!(current, vec!["nvim"]);
+        assert_eq!(
+            resolve(&src, &current, &path("crate::nvim::model::cursor::Cursor")),
+            Some((src.join("nvim/model/cursor.rs"), Some("Cursor".to_string())))
+        );
+        assert_eq!(
+            resolve(&src, &current, &path("self::model::cursor::*")),
+            Some((src.join("nvim/model/cursor.rs"), None))
+        );
+        assert_eq!(
+            resolve(&src, &current, &path("super::Result")),
+            Some((src.join("lib.rs"), Some("Result".to_string())))
+        );
+        assert_eq!(
+            resolve(&src, &current, &path("model::cursor::Cursor")),
+            Some((src.join("nvim/model/cursor.rs"), Some("Cursor".to_string())))
+        );
+        assert_eq!(resolve(&src, &current, &path("std::sync::Arc")), None);
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
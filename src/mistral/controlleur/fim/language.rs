@@ -0,0 +1,257 @@
+/// Granularity of the tree-sitter target, the captured node containing the cursor is completed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueryKind {
+    Function,
+    Statement,
+    Struct,
+    Impl,
+    Docstring,
+}
+
+impl std::fmt::Display for QueryKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Function => "function",
+            Self::Statement => "statement",
+            Self::Struct => "struct",
+            Self::Impl => "impl",
+            Self::Docstring => "docstring",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// Named queries of a language, `None` when the language has no such construct.
+pub struct Queries {
+    pub function: &'static str,
+    pub statement: &'static str,
+    pub structure: Option<&'static str>,
+    pub implementation: Option<&'static str>,
+    pub docstring: &'static str,
+}
+
+pub struct Language {
+    pub name: &'static str,
+    /// Neovim's `filetype` values parsed with this grammar.
+    pub filetypes: &'static [&'static str],
+    grammar: fn() -> tree_sitter::Language,
+    queries: &'static Queries,
+    /// Starts of top level items, a completion reaching one of them went past its target.
+    pub stops: &'static [&'static str],
+}
+
+impl Language {
+    pub fn from_filetype(filetype: &str) -> Option<&'static Self> {
+        LANGUAGES
+            .iter()
+            .find(|language| language.filetypes.contains(&filetype))
+    }
+    pub fn grammar(&self) -> tree_sitter::Language {
+        (self.grammar)()
+    }
+    pub fn query(&self, kind: QueryKind) -> crate::Result<&'static str> {
+        let Queries {
+            function,
+            statement,
+            structure,
+            implementation,
+            docstring,
+        } = self.queries;
+        let query = match kind {
+            QueryKind::Function => Some(*function),
+            QueryKind::Statement => Some(*statement),
+            QueryKind::Struct => *structure,
+            QueryKind::Impl => *implementation,
+            QueryKind::Docstring => Some(*docstring),
+        };
+        query.ok_or_else(|| format!("No `{kind}` target for {}.", self.name).into())
+    }
+}
+
+static LANGUAGES: [Language; 7] = [
+    Language {
+        name: "Rust",
+        filetypes: &["rust"],
+        grammar: || tree_sitter_rust::LANGUAGE.into(),
+        queries: &RUST,
+        stops: &["\nfn ", "\npub fn ", "\nimpl", "\n#[cfg(test)]"],
+    },
+    Language {
+        name: "Python",
+        filetypes: &["python"],
+        grammar: || tree_sitter_python::LANGUAGE.into(),
+        queries: &PYTHON,
+        stops: &["\ndef ", "\nclass ", "\nif __name__"],
+    },
+    Language {
+        name: "Lua",
+        filetypes: &["lua"],
+        grammar: || tree_sitter_lua::LANGUAGE.into(),
+        queries: &LUA,
+        stops: &["\nfunction ", "\nlocal function "],
+    },
+    Language {
+        name: "TypeScript",
+        filetypes: &["typescript"],
+        grammar: || tree_sitter_typescript::LANGUAGE_TYPESCRIPT.into(),
+        queries: &TYPESCRIPT,
+        stops: &["\nfunction ", "\nexport ", "\nclass "],
+    },
+    Language {
+        name: "TSX",
+        filetypes: &["typescriptreact"],
+        grammar: || tree_sitter_typescript::LANGUAGE_TSX.into(),
+        queries: &TYPESCRIPT,
+        stops: &["\nfunction ", "\nexport ", "\nclass "],
+    },
+    Language {
+        name: "Go",
+        filetypes: &["go"],
+        grammar: || tree_sitter_go::LANGUAGE.into(),
+        queries: &GO,
+        stops: &["\nfunc ", "\ntype "],
+    },
+    Language {
+        name: "C",
+        filetypes: &["c"],
+        grammar: || tree_sitter_c::LANGUAGE.into(),
+        queries: &C,
+        stops: &["\nint main(", "\n#include"],
+    },
+];
+
+static RUST: Queries = Queries {
+    function: r#"([(block_comment(doc_comment)) (line_comment(doc_comment))]* @docstring . (attribute_item)* @attribute . (function_item) @function)"#,
+    statement: r#"[(let_declaration) (expression_statement) (match_arm) (field_initializer) (field_declaration)] @statement"#,
+    structure: Some(
+        r#"([(block_comment(doc_comment)) (line_comment(doc_comment))]* @docstring . (attribute_item)* @attribute . [(struct_item) (enum_item) (union_item)] @struct) (struct_expression) @struct"#,
+    ),
+    implementation: Some(
+        r#"([(block_comment(doc_comment)) (line_comment(doc_comment))]* @docstring . (attribute_item)* @attribute . [(impl_item) (trait_item)] @impl)"#,
+    ),
+    docstring: r#"[(block_comment(doc_comment)) (line_comment(doc_comment))]+ @docstring"#,
+};
+
+static PYTHON: Queries = Queries {
+    function: r#"[(decorated_definition definition: (function_definition)) (function_definition)] @function"#,
+    statement: r#"[(expression_statement) (return_statement) (if_statement) (for_statement) (while_statement) (with_statement) (try_statement) (raise_statement) (match_statement) (case_clause)] @statement"#,
+    structure: Some(r#"[(decorated_definition definition: (class_definition)) (class_definition)] @struct"#),
+    implementation: None,
+    docstring: r#"[(function_definition body: (block . (expression_statement (string) @docstring))) (class_definition body: (block . (expression_statement (string) @docstring)))]"#,
+};
+
+static LUA: Queries = Queries {
+    function: r#"((comment)* @docstring . [(function_declaration) (function_definition)] @function)"#,
+    statement: r#"[(variable_declaration) (assignment_statement) (function_call) (return_statement) (if_statement) (for_statement) (while_statement) (repeat_statement) (do_statement)] @statement"#,
+    structure: Some(r#"(table_constructor) @struct"#),
+    implementation: None,
+    docstring: r#"(comment)+ @docstring"#,
+};
+
+static TYPESCRIPT: Queries = Queries {
+    function: r#"((comment)* @docstring . [(function_declaration) (generator_function_declaration) (method_definition) (export_statement declaration: (function_declaration)) (lexical_declaration (variable_declarator value: [(arrow_function) (function_expression)]))] @function)"#,
+    statement: r#"[(lexical_declaration) (variable_declaration) (expression_statement) (return_statement) (if_statement) (for_statement) (for_in_statement) (while_statement) (switch_statement) (switch_case) (throw_statement)] @statement"#,
+    structure: Some(
+        r#"((comment)* @docstring . [(interface_declaration) (type_alias_declaration) (enum_declaration) (export_statement declaration: [(interface_declaration) (type_alias_declaration) (enum_declaration)])] @struct)"#,
+    ),
+    implementation: Some(
+        r#"((comment)* @docstring . [(class_declaration) (abstract_class_declaration) (export_statement declaration: [(class_declaration) (abstract_class_declaration)])] @impl)"#,
+    ),
+    docstring: r#"(comment)+ @docstring"#,
+};
+
+static GO: Queries = Queries {
+    function: r#"((comment)* @docstring . [(function_declaration) (method_declaration)] @function)"#,
+    statement: r#"[(short_var_declaration) (assignment_statement) (expression_statement) (inc_statement) (dec_statement) (return_statement) (if_statement) (for_statement) (expression_switch_statement) (expression_case) (var_declaration) (go_statement) (defer_statement)] @statement"#,
+    structure: Some(
+        r#"((comment)* @docstring . (type_declaration (type_spec type: [(struct_type) (interface_type)])) @struct)"#,
+    ),
+    implementation: None,
+    docstring: r#"(comment)+ @docstring"#,
+};
+
+static C: Queries = Queries {
+    function: r#"((comment)* @docstring . (function_definition) @function)"#,
+    statement: r#"[(declaration) (expression_statement) (return_statement) (if_statement) (for_statement) (while_statement) (do_statement) (switch_statement) (case_statement)] @statement"#,
+    structure: Some(
+        r#"[(struct_specifier body: (field_declaration_list)) (union_specifier body: (field_declaration_list)) (enum_specifier body: (enumerator_list))] @struct"#,
+    ),
+    implementation: None,
+    docstring: r#"(comment)+ @docstring"#,
+};
+
+#[cfg(test)]
+mod tests {
+    use tree_sitter::{Parser, Query, QueryCursor, StreamingIterator as _};
+
+    use super::*;
+
+    /// First line of the node captured as `@{kind}` by the first match.
+    fn first_target(filetype: &str, kind: QueryKind, code: &str) -> String {
+        let language = Language::from_filetype(filetype).unwrap();
+        let grammar = language.grammar();
+        let mut parser = Parser::new();
+        parser.set_language(&grammar).unwrap();
+        let tree = parser.parse(code, None).unwrap();
+        let query = Query::new(&grammar, language.query(kind).unwrap()).unwrap();
+        let index = query.capture_index_for_name(&kind.to_string()).unwrap();
+        let mut cursor = QueryCursor::new();
+        let mut matches = cursor.matches(&query, tree.root_node(), code.as_bytes());
+        while let Some(m) = matches.next() {
+            if let Some(capture) = m.captures.iter().find(|c| c.index == index) {
+                let text = &code[capture.node.byte_range()];
+                return text.lines().next().unwrap_or_default().to_string();
+            }
+        }
+        String::new()
+    }
+
+    #[test]
+    fn queries_compile() {
+        let kinds = [
+            QueryKind::Function,
+            QueryKind::Statement,
+            QueryKind::Struct,
+            QueryKind::Impl,
+            QueryKind::Docstring,
+        ];
+        for language in &LANGUAGES {
+            for kind in kinds {
+                if let Ok(query) = language.query(kind) {
+                    assert!(
+                        Query::new(&language.grammar(), query).is_ok(),
+                        "{} {kind}",
+                        language.name
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn function_targets() {
+        let python = "@cache\ndef f(a):\n    \"\"\"Doc.\"\"\"\n    return a + 1\n";
+        assert_eq!(first_target("python", QueryKind::Function, python), "@cache");
+        let go = "package main\n\n// Add adds.\nfunc (p P) Add(a int) int {\n\treturn a\n}\n";
+        assert_eq!(
+            first_target("go", QueryKind::Function, go),
+            "func (p P) Add(a int) int {"
+        );
+        let c = "// Add.\nint add(int a) {\n  return a;\n}\n";
+        assert_eq!(first_target("c", QueryKind::Function, c), "int add(int a) {");
+        let typescript = "const f = (a: number) => a + 1;\n";
+        assert_eq!(
+            first_target("typescript", QueryKind::Function, typescript),
+            typescript.trim()
+        );
+        let lua = "local function f(a)\n  return a + 1\nend\n";
+        assert_eq!(first_target("lua", QueryKind::Function, lua), "local function f(a)");
+        assert!(
+            Language::from_filetype("python")
+                .unwrap()
+                .query(QueryKind::Impl)
+                .is_err()
+        );
+        assert!(Language::from_filetype("markdown").is_none());
+    }
+}
@@ -0,0 +1,730 @@
+use std::{
+    collections::{HashMap, hash_map::Entry},
+    sync::{Arc, atomic::AtomicBool},
+};
+
+use tokio::sync::{Mutex, mpsc::UnboundedSender};
+use tree_sitter::{Query, QueryCursor, StreamingIterator as _};
+
+use crate::{
+    messages::{self, IdMessage, MistralEnveloppe, MistralMessage},
+    mistral::{
+        client::MistralClient,
+        model::{
+            completion::{ChatRequest, CompletionParams, FimCompletion, FimRequest, Model, TemplateRequest},
+            stream::{Status, StreamResponse},
+        },
+    },
+    notify::NotifyLevel,
+    nvim::{
+        self,
+        model::{
+            BufferData, Cursor,
+            config::{FimBackend, FimCache},
+        },
+    },
+};
+
+mod budget;
+mod cache;
+pub mod document;
+pub mod edit;
+mod imports;
+pub mod language;
+pub mod post_process;
+pub mod template;
+pub mod unit_tests;
+
+use budget::Budget;
+use language::Language;
+pub use language::QueryKind;
+
+pub struct AbortHandle {
+    atomic: Arc<AtomicBool>,
+    handle: tokio::task::AbortHandle,
+}
+impl AbortHandle {
+    pub fn new<Fut>(atomic: Arc<AtomicBool>, task: tokio::task::JoinHandle<Fut>) -> Self {
+        let handle = task.abort_handle();
+        Self { atomic, handle }
+    }
+    pub fn soft_abort(&mut self) {
+        self.atomic
+            .store(true, std::sync::atomic::Ordering::Relaxed);
+    }
+    pub fn hard_abort(&mut self) {
+        self.handle.abort()
+    }
+}
+#[derive(Clone)]
+pub struct SenderHandle {
+    tx_nvim: UnboundedSender<messages::MistralEnveloppe>,
+    handle_nvim: nvim_oxi::libuv::AsyncHandle,
+}
+impl SenderHandle {
+    pub fn send_enveloppe(&self, message: messages::MistralEnveloppe) {
+        if let Err(err) = self.tx_nvim.send(message) {
+            crate::log_tokio!(Error, "Can't send MistralEnveloppe to nvim : {err}");
+            return;
+        }
+        if let Err(err) = self.handle_nvim.send() {
+            crate::log_tokio!(Error, "Can't send AsyncHandle : {err}");
+        }
+    }
+    pub fn send(&self, id: messages::IdMessage, message: messages::MistralMessage) {
+        self.send_enveloppe(messages::MistralEnveloppe { id, message })
+    }
+    pub fn notify_warn(&self, msg: impl ToString) {
+        self.send_enveloppe(MistralEnveloppe::notify_warn(messages::IdMessage::FIM(0, 0), msg));
+    }
+    pub fn notify_error(&self, msg: impl ToString) {
+        self.send_enveloppe(MistralEnveloppe::notify_error(messages::IdMessage::FIM(0, 0), msg));
+    }
+}
+pub type SharedContext = Arc<Context>;
+pub struct Context {
+    pub nvim_sendle: SenderHandle,
+    pub tasks: Mutex<HashMap<messages::IdMessage, AbortHandle>>,
+    pub client: MistralClient,
+    pub fim_cache: std::sync::Mutex<cache::Cache>,
+}
+impl Context {
+    pub fn new(
+        tx_nvim: UnboundedSender<messages::MistralEnveloppe>,
+        handle_nvim: nvim_oxi::libuv::AsyncHandle,
+    ) -> Self {
+        let nvim_sendle = SenderHandle { tx_nvim, handle_nvim };
+        let client = MistralClient::new(SenderHandle::clone(&nvim_sendle));
+        Self {
+            nvim_sendle,
+            tasks: Default::default(),
+            client,
+            fim_cache: Default::default(),
+        }
+    }
+    // pub fn send(&self, id: IdMessage, message: MistralMessage) {
+    //     self.nvim_sendle.send(MistralEnveloppe { id, message });
+    // }
+}
+
+macro_rules! pipe {
+    ($self:ident -> $args:expr) => {
+        Pipe::new($args, $self.context, $self.id)
+    };
+}
+
+pub struct Pipe<T> {
+    args: T,
+    id: messages::IdMessage,
+    context: SharedContext,
+}
+
+#[allow(dead_code)]
+impl<T> Pipe<T> {
+    pub fn notify_error(&self, message: impl ToString) {
+        crate::log_tokio!(Error, "{}", message.to_string());
+        self.send(MistralMessage::Notify {
+            level: NotifyLevel::Error,
+            message: message.to_string(),
+        });
+    }
+    pub fn notify_warn(&self, message: impl ToString) {
+        crate::log_tokio!(Warn, "{}", message.to_string());
+        self.send(MistralMessage::Notify {
+            level: NotifyLevel::Warn,
+            message: message.to_string(),
+        });
+    }
+    pub fn send(&self, message: MistralMessage) {
+        self.context.nvim_sendle.send(self.id, message);
+    }
+    fn new(args: T, context: Arc<Context>, id: messages::IdMessage) -> Self {
+        Self { args, context, id }
+    }
+
+    #[must_use]
+    fn tap<F>(self, f: F) -> Self
+    where
+        F: FnOnce(&T, &Context),
+    {
+        f(&self.args, &self.context);
+        self
+    }
+
+    #[must_use]
+    fn map<U, F>(self, f: F) -> Pipe<U>
+    where
+        F: FnOnce(T, &Context) -> U,
+    {
+        pipe!(self -> f(self.args, &self.context))
+    }
+
+    fn next<Next>(self, value: Next) -> Pipe<Next> {
+        pipe!(self -> value)
+    }
+}
+
+pub struct TreeSitterContext {
+    registered: &'static Language,
+    language: tree_sitter::Language,
+    tree: tree_sitter::Tree,
+    content: String,
+    cursor: nvim::model::Cursor,
+}
+impl TreeSitterContext {
+    pub fn from_bufferdata(data: nvim::model::BufferData) -> crate::Result<Self> {
+        let nvim::model::BufferData {
+            filetype,
+            cursor,
+            content,
+            ..
+        } = data;
+        let content = content.join("\n");
+
+        let mut parser = tree_sitter::Parser::new();
+        let Some(registered) = Language::from_filetype(&filetype) else {
+            return Err(format!("Filetype `{filetype}` not supported.").into());
+        };
+        let language = registered.grammar();
+        parser.set_language(&language)?;
+
+        let Some(tree) = parser.parse(&content, None) else {
+            return Err("Can't parse with tree-sitter.".into());
+        };
+        Ok(TreeSitterContext {
+            registered,
+            language,
+            tree,
+            content,
+            cursor,
+        })
+    }
+    pub fn extract_query_vec(self, query: &str) -> crate::Result<Vec<String>> {
+        let TreeSitterContext {
+            language,
+            tree,
+            content,
+            ..
+        } = &self;
+        let query = Query::new(&language, query)?;
+        let mut query_cursor = QueryCursor::new();
+        // let mut matches = cursor.matches(&query, tree.root_node(), content.as_bytes());
+        // let captures_names = query.capture_names();
+        let mut matches = query_cursor.matches(&query, tree.root_node(), content.as_bytes());
+
+        let mut targets = Vec::new();
+        while let Some(m) = matches.next() {
+            // for capture in m.captures {
+            //     let node = capture.node;
+            //     let start: nvim::model::Cursor = node.start_position().into();
+            //     let end: nvim::model::Cursor = node.end_position().into();
+            //     // crate::log_libuv!(Trace,"-- {:?}({start}, {end}) --", captures_names.get(capture.index as usize));
+            // }
+            // let mut docstring_selction = if let Some(c) = m.captures.first() {
+            //     let node = c.node;
+            //     let start: nvim::model::Cursor = node.start_position().into();
+            //     let end: nvim::model::Cursor = node.end_position().into();
+            //     nvim::model::Selection { start, end }
+            // } else {
+            //     continue;
+            // };
+            let mut all_captured = String::new();
+            for capture in m.captures.iter() {
+                let node = capture.node;
+                // if node.grammar_name == ""
+                let start_byte = node.start_byte();
+                let end_byte = node.end_byte();
+                all_captured += &content[start_byte..end_byte];
+            }
+            targets.push(all_captured);
+        }
+        Ok(targets)
+    }
+    pub fn extract_query_vec_cursor(self, query: &str) -> crate::Result<Vec<Cursor>> {
+        let TreeSitterContext {
+            language,
+            tree,
+            content,
+            ..
+        } = &self;
+        let query = Query::new(&language, query)?;
+        let mut query_cursor = QueryCursor::new();
+        // let mut matches = cursor.matches(&query, tree.root_node(), content.as_bytes());
+        // let captures_names = query.capture_names();
+        let mut matches = query_cursor.matches(&query, tree.root_node(), content.as_bytes());
+
+        let mut targets = Vec::new();
+        while let Some(m) = matches.next() {
+            if let Some(c) = m.captures.first() {
+                let node = c.node;
+                let start: nvim::model::Cursor = node.start_position().into();
+                targets.push(start);
+            }
+        }
+        Ok(targets)
+    }
+}
+
+// Example : Compter les voyelles
+impl Pipe<messages::Visual> {
+    /// The completion starts at the end of the selection, with the rest of the file as suffix.
+    fn extract_selection(self) -> Pipe<(String, Option<String>, Cursor)> {
+        let (_selected_content, cursor) = self.args.get_selected_content();
+        // crate::log_libuv!(Trace,".........................................Init  : {cursor:?}\n\n”");
+        let (prefix, suffix) = split_at_cursor_col(&self.args.data.content, &cursor);
+        self.next((prefix, Some(suffix), cursor))
+    }
+}
+
+impl Pipe<messages::Normal> {
+    fn split_at_cursor_col(self) -> Pipe<(String, Option<String>, Cursor)> {
+        let messages::Normal {
+            data: nvim::model::BufferData { cursor, content, .. },
+            ..
+        } = &self.args;
+        let (prefix, suffix) = split_at_cursor_col(content, cursor);
+        let ctx = (prefix, Some(suffix), cursor.clone());
+        pipe!(self -> ctx)
+    }
+    fn tree_sitter(self) -> crate::Result<Pipe<TreeSitterContext>> {
+        let ctx = TreeSitterContext::from_bufferdata(self.args.data)?;
+        Ok(pipe!(self -> ctx))
+    }
+}
+
+impl Pipe<messages::Suggest> {
+    fn split_at_cursor_col(self) -> Pipe<(String, Option<String>, Cursor)> {
+        let nvim::model::BufferData { cursor, content, .. } = &self.args.data;
+        let (prefix, suffix) = split_at_cursor_col(content, cursor);
+        let ctx = (prefix, Some(suffix), cursor.clone());
+        pipe!(self -> ctx)
+    }
+}
+
+/// Split the buffer at the cursor's column, the prefix ends exactly where the completion starts.
+pub fn split_at_cursor_col(content: &[String], cursor: &Cursor) -> (String, String) {
+    let row = *cursor.row;
+    let Some(line) = content.get(row) else {
+        return (content.join("\n"), String::new());
+    };
+    let mut col = (*cursor.col).min(line.len());
+    while !line.is_char_boundary(col) {
+        col -= 1;
+    }
+    let (head, tail) = line.split_at(col);
+    let mut prefix = content[..row].join("\n");
+    if row > 0 {
+        prefix.push('\n');
+    }
+    prefix.push_str(head);
+    let mut suffix = tail.to_string();
+    for line in &content[row + 1..] {
+        suffix.push('\n');
+        suffix.push_str(line);
+    }
+    (prefix, suffix)
+}
+
+impl Pipe<TreeSitterContext> {
+    fn extract_query_under_cursor(self, kind: QueryKind) -> crate::Result<Pipe<(String, Option<String>, Cursor)>> {
+        let TreeSitterContext {
+            registered,
+            language,
+            tree,
+            content,
+            cursor,
+        } = &self.args;
+        let query = Query::new(&language, registered.query(kind)?)?;
+        if let Some(node) = innermost_capture(&query, tree, content, cursor) {
+            // The completion continues the captured node, with the rest of the file as suffix.
+            let (prefix, suffix) = content.split_at(node.end_byte());
+            let end = node.end_position().into();
+            let ctx = (prefix.to_string(), Some(suffix.to_string()), end);
+            return Ok(pipe!(self -> ctx));
+        }
+        Err(format!("No {kind} under the cursor.").into())
+    }
+}
+
+/// Nested targets (closure in a function, arm in a match) : the innermost capture containing the cursor.
+fn innermost_capture<'tree>(
+    query: &Query,
+    tree: &'tree tree_sitter::Tree,
+    content: &str,
+    cursor: &Cursor,
+) -> Option<tree_sitter::Node<'tree>> {
+    let mut query_cursor = QueryCursor::new();
+    let mut matches = query_cursor.matches(query, tree.root_node(), content.as_bytes());
+    let mut target: Option<tree_sitter::Node> = None;
+    while let Some(m) = matches.next() {
+        for capture in m.captures {
+            let node = capture.node;
+            let start: Cursor = node.start_position().into();
+            let end: Cursor = node.end_position().into();
+            let is_inner = target.is_none_or(|t| node.byte_range().len() < t.byte_range().len());
+            if start <= *cursor && *cursor <= end && is_inner {
+                target = Some(node);
+            }
+        }
+    }
+    target
+}
+
+/// What the payload needs besides the prefix and the suffix.
+struct FimContext {
+    /// Signatures of the imported items.
+    header: String,
+    filetype: String,
+    backend: FimBackend,
+}
+
+impl FimContext {
+    /// The imported files are read on a blocking thread, not on the runtime.
+    async fn new(data: &BufferData, backend: &FimBackend) -> Self {
+        let buffer = data.clone();
+        let header = tokio::task::spawn_blocking(move || imports::header(&buffer))
+            .await
+            .unwrap_or_default();
+        Self {
+            header,
+            filetype: data.filetype.clone(),
+            backend: backend.clone(),
+        }
+    }
+}
+
+/// Codestral has a FIM endpoint, the other models get the prefix and the suffix in a chat prompt.
+#[derive(serde::Serialize)]
+#[serde(untagged)]
+enum FimPayload {
+    Native(FimRequest),
+    Template(TemplateRequest),
+}
+
+impl FimPayload {
+    fn route(&self) -> &'static str {
+        match self {
+            Self::Native(_) => "fim/completions",
+            Self::Template(_) => "chat/completions",
+        }
+    }
+    fn params_mut(&mut self) -> &mut CompletionParams {
+        match self {
+            Self::Native(request) => &mut request.params,
+            Self::Template(request) => &mut request.params,
+        }
+    }
+}
+
+impl Pipe<(String, Option<String>, Cursor)> {
+    /// The header (signatures of the imported items) is put before the prompt, within the budget.
+    fn create_fim_payload(self, context: &FimContext) -> Pipe<FimPayload> {
+        self.send(MistralMessage::InitializeTask(self.args.2.clone()));
+        let FimContext {
+            header,
+            filetype,
+            backend,
+        } = context;
+        let params = CompletionParams::default();
+        let suffix = self.args.1.unwrap_or_default();
+        let mut budget = Budget::for_model(&backend.model, params.max_tokens);
+        let header = budget.header(header);
+        let (prompt, suffix) = budget.trim(&self.args.0, &suffix);
+        let prompt = if header.is_empty() {
+            prompt
+        } else {
+            format!("{header}\n\n{prompt}")
+        };
+        let payload = match &backend.template {
+            None => FimPayload::Native(FimRequest {
+                completion: FimCompletion {
+                    model: Model::from_name(&backend.model).unwrap_or_else(Model::fim),
+                    prompt,
+                    suffix: Some(suffix),
+                },
+                params,
+            }),
+            Some(fim_template) => FimPayload::Template(TemplateRequest {
+                model: backend.model.clone(),
+                messages: template::messages(fim_template, filetype, &prompt, &suffix),
+                params,
+            }),
+        };
+        pipe!(self -> payload)
+    }
+}
+
+impl Pipe<FimPayload> {
+    /// A cached completion of the same request is replayed at once, as if it was streamed.
+    async fn send_fim_request(self, cache: FimCache) -> crate::Result<()> {
+        let route = self.args.route();
+        let pipe = self.to_json_value()?;
+        let key = cache::key(&pipe.args);
+        if cache == FimCache::Use {
+            let cached = pipe
+                .context
+                .fim_cache
+                .lock()
+                .ok()
+                .and_then(|mut fim_cache| fim_cache.get(&key));
+            if let Some(content) = cached {
+                let chunk = content.split('\n').map(ToString::to_string).collect();
+                pipe.send(MistralMessage::UpdateContent(chunk));
+                let mut response = StreamResponse::new();
+                response.message.content = content;
+                pipe.send(MistralMessage::FinalizeTask(response));
+                return Ok(());
+            }
+        }
+        pipe.send_stream_request(route, (cache != FimCache::Off).then_some(key))
+            .await;
+        Ok(())
+    }
+}
+
+impl<Serializable> Pipe<Serializable>
+where
+    Serializable: serde::Serialize,
+{
+    fn to_json_value(self) -> crate::Result<Pipe<serde_json::Value>> {
+        Ok(pipe!(self -> serde_json::to_value(self.args)?))
+    }
+}
+
+impl Pipe<serde_json::Value> {
+    fn initialize_task_default(self) -> Self {
+        self.send(messages::MistralMessage::InitializeTask(nvim::model::Cursor::zero()));
+        self
+    }
+    /// With a `cache_key`, a completed answer is stored in the FIM cache.
+    async fn send_stream_request(self, route: &str, cache_key: Option<cache::Key>) {
+        let sendle = SenderHandle::clone(&self.context.nvim_sendle);
+        let id = self.id.clone();
+        let should_abort = Arc::new(AtomicBool::new(false));
+        let aborted = Arc::clone(&should_abort);
+        let context = SharedContext::clone(&self.context);
+        let callback = move |response: StreamResponse| {
+            let completed = matches!(response.status, Status::Completed)
+                && !aborted.load(std::sync::atomic::Ordering::Relaxed)
+                && !response.message.content.is_empty();
+            if let Some(key) = cache_key.filter(|_| completed) {
+                if let Ok(mut fim_cache) = context.fim_cache.lock() {
+                    fim_cache.insert(key, response.message.content.clone());
+                }
+            }
+            sendle.send(id, MistralMessage::FinalizeTask(response));
+        };
+
+        let client = MistralClient::clone(&self.context.client);
+        let mut lock = self.context.tasks.lock().await;
+        let task_entry = lock.entry(self.id);
+        let route = route.to_string();
+        match task_entry {
+            Entry::Occupied(_) => {
+                self.notify_error("Task already running for given ID.");
+            }
+            Entry::Vacant(vacant) => {
+                let should_abort_clone = Arc::clone(&should_abort);
+                crate::log_tokio!(Error, "Send Request : {}", self.args);
+                let task = tokio::task::spawn(async move {
+                    client
+                        .stream(&route, self.args, callback, should_abort, self.id)
+                        .await
+                });
+                vacant.insert(AbortHandle::new(should_abort_clone, task));
+            }
+        }
+    }
+}
+
+pub async fn cursor(id: IdMessage, message: messages::Normal, context: SharedContext) -> crate::Result<()> {
+    let fim = FimContext::new(&message.data, &message.backend).await;
+    Pipe::new(message, context, id)
+        .split_at_cursor_col()
+        .create_fim_payload(&fim)
+        .send_fim_request(fim.backend.cache)
+        .await
+}
+
+/// Complete the tree-sitter node of the given kind enclosing the cursor.
+pub async fn target(
+    id: IdMessage,
+    message: messages::Normal,
+    kind: QueryKind,
+    context: SharedContext,
+) -> crate::Result<()> {
+    let fim = FimContext::new(&message.data, &message.backend).await;
+    Pipe::new(message, context, id)
+        .tree_sitter()?
+        // .map_err(|err| err.to_string())?
+        .extract_query_under_cursor(kind)?
+        .create_fim_payload(&fim)
+        .send_fim_request(fim.backend.cache)
+        .await
+}
+
+pub async fn visual(id: IdMessage, message: messages::Visual, context: SharedContext) -> crate::Result<()> {
+    let fim = FimContext::new(&message.data, &message.backend).await;
+    Pipe::new(message, context, id)
+        .extract_selection()
+        .create_fim_payload(&fim)
+        .send_fim_request(fim.backend.cache)
+        .await
+}
+
+pub async fn suggestion(id: IdMessage, message: messages::Suggest, context: SharedContext) -> crate::Result<()> {
+    let random_seed = (message.candidate > 0).then_some(message.candidate as u32);
+    let fim = FimContext::new(&message.data, &message.backend).await;
+    Pipe::new(message, context, id)
+        .split_at_cursor_col()
+        .create_fim_payload(&fim)
+        .map(|mut payload, _| {
+            payload.params_mut().random_seed = random_seed;
+            payload
+        })
+        .send_fim_request(fim.backend.cache)
+        .await
+}
+
+/// The insertion is started by Neovim when the placeholders are removed, so positions stay in sync.
+pub async fn placeholder(id: IdMessage, message: messages::Placeholder, context: SharedContext) -> crate::Result<()> {
+    let fim = FimContext::new(&message.data, &message.backend).await;
+    let messages::Placeholder {
+        data, prefix, suffix, ..
+    } = message;
+    Pipe::new((prefix, Some(suffix), data.cursor), context, id)
+        .create_fim_payload(&fim)
+        .send_fim_request(fim.backend.cache)
+        .await
+}
+
+/// Doc comment of the item, the insertion is started by Neovim above the item. The call sites of the other
+/// files of the project are read on a blocking thread, not on the runtime.
+pub async fn document(id: IdMessage, message: messages::Document, context: SharedContext) -> crate::Result<()> {
+    let messages::Document {
+        item,
+        name,
+        path,
+        mut call_sites,
+        backend,
+    } = message;
+    let max = document::MAX_CALL_SITES.saturating_sub(call_sites.len());
+    let project = tokio::task::spawn_blocking(move || document::project_call_sites(&name, path.as_deref(), max))
+        .await
+        .unwrap_or_default();
+    call_sites.extend(project);
+    let payload = FimPayload::Template(TemplateRequest {
+        model: backend.model,
+        messages: document::messages(&item, &call_sites),
+        params: CompletionParams::default(),
+    });
+    Pipe::new(payload, context, id)
+        .send_fim_request(backend.cache)
+        .await
+}
+
+/// The selection holds whole lines, `selection.end.row` is the row after it.
+pub async fn edit(id: IdMessage, message: messages::Edit, context: SharedContext) -> crate::Result<()> {
+    let messages::Edit { visual, instruction } = message;
+    let (selection, _) = visual.get_selected_content();
+    let content = &visual.data.content;
+    let start = (*visual.selection.start.row).min(content.len());
+    let end = (*visual.selection.end.row).clamp(start, content.len());
+    let budget = Budget::for_model(&visual.backend.model, None);
+    let (prefix, suffix) = budget.trim(&content[..start].join("\n"), &content[end..].join("\n"));
+    let filetype = &visual.data.filetype;
+    let payload = FimPayload::Template(TemplateRequest {
+        model: visual.backend.model.clone(),
+        messages: edit::messages(&instruction, filetype, &selection, &prefix, &suffix),
+        params: CompletionParams::default(),
+    });
+    Pipe::new(payload, context, id)
+        .send_fim_request(visual.backend.cache)
+        .await
+}
+
+pub async fn generate_tests(
+    id: IdMessage,
+    message: messages::GenerateTests,
+    context: SharedContext,
+) -> crate::Result<()> {
+    let messages::GenerateTests {
+        function,
+        name,
+        existing,
+        backend,
+    } = message;
+    let payload = FimPayload::Template(TemplateRequest {
+        model: backend.model,
+        messages: unit_tests::messages(&function, &name, &existing),
+        params: CompletionParams::default(),
+    });
+    Pipe::new(payload, context, id)
+        .send_fim_request(backend.cache)
+        .await
+}
+
+pub async fn chat_completion(id: IdMessage, message: ChatRequest, context: SharedContext) -> crate::Result<()> {
+    Pipe::new(message, context, id)
+        .to_json_value()?
+        .initialize_task_default()
+        .send_stream_request("chat/completions", None)
+        .await;
+    Ok(())
+}
+
+pub async fn abort_task(id: IdMessage, context: SharedContext) -> crate::Result<()> {
+    // Removed before waiting, so a new request (ex: auto FIM) does not wait for the lock.
+    let Some(mut task) = context.tasks.lock().await.remove(&id) else {
+        return Ok(());
+    };
+    task.soft_abort();
+    tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+    task.hard_abort();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nvim::model::{Col, Row};
+
+    #[test]
+    fn split_at_col() {
+        let content = ["fn main() {", "    let x = ;", "}"].map(String::from);
+        let cursor = Cursor {
+            row: Row(1),
+            col: Col(12),
+        };
+        let (prefix, suffix) = split_at_cursor_col(&content, &cursor);
+        assert_eq!(prefix, "fn main() {\n    let x = ");
+        assert_eq!(suffix, ";\n}");
+        let cursor = Cursor {
+            row: Row(0),
+            col: Col::MAX,
+        };
+        assert_eq!(split_at_cursor_col(&content, &cursor).0, "fn main() {");
+    }
+
+    #[test]
+    fn innermost_statement() {
+        let code = "fn main() {\n    let y = match x {\n        1 => 2,\n        _ => \n    };\n}";
+        let rust = Language::from_filetype("rust").unwrap();
+        let mut parser = tree_sitter::Parser::new();
+        parser.set_language(&rust.grammar()).unwrap();
+        let tree = parser.parse(code, None).unwrap();
+        let query = Query::new(&rust.grammar(), rust.query(QueryKind::Statement).unwrap()).unwrap();
+        let cursor = Cursor {
+            row: Row(3),
+            col: Col(12),
+        };
+        let node = innermost_capture(&query, &tree, code, &cursor).unwrap();
+        assert_eq!(node.kind(), "match_arm");
+        let cursor = Cursor {
+            row: Row(1),
+            col: Col(6),
+        };
+        let node = innermost_capture(&query, &tree, code, &cursor).unwrap();
+        assert_eq!(node.kind(), "let_declaration");
+    }
+}
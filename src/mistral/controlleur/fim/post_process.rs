@@ -0,0 +1,361 @@
+use super::{document, language::Language, template};
+
+/// Markdown code block, the opening one is dropped and the closing one stops every completion.
+const FENCE: &'static str = "```";
+const CLOSING_FENCE: &'static str = "\n```";
+/// Lines of the suffix compared with the end of the completion.
+const SUFFIX_LINES: usize = 3;
+
+/// Cleans a streamed FIM completion before its insertion : markdown fences, stop sequences,
+/// indentation and the end duplicating the start of the suffix.
+/// Only the text that can't change anymore is returned, the rest is held until the next chunk.
+#[derive(Debug)]
+pub struct PostProcess {
+    text: String,
+    emitted: usize,
+    /// Indentation of the line where the completion is inserted.
+    indent: String,
+    /// Nothing but the indentation before the cursor.
+    at_indent: bool,
+    /// Rest of the line after the cursor, trimmed.
+    rest: String,
+    /// First non blank lines of the suffix after the cursor's line, trimmed.
+    following: Vec<String>,
+    stops: Vec<&'static str>,
+    answer: Answer,
+    prefix: String,
+    suffix: String,
+}
+
+/// Where the completion is in the answer of the model.
+#[derive(Debug)]
+enum Answer {
+    /// The whole answer.
+    Code,
+    /// Answer of a chat model to a template with this hole marker, the infill is extracted from the whole answer.
+    Infill(String),
+    /// Doc comment of an item, turned into a `///` block with the indentation.
+    DocComment,
+}
+
+impl PostProcess {
+    /// `prefix` is the text before the cursor, `suffix` the text after it.
+    pub fn new(filetype: &str, prefix: &str, suffix: &str) -> Self {
+        let before = prefix.rsplit('\n').next().unwrap_or_default();
+        let indent = before.chars().take_while(|c| c.is_whitespace()).collect();
+        let mut lines = suffix.split('\n');
+        let rest = lines.next().unwrap_or_default().trim().to_string();
+        let following = lines
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .take(SUFFIX_LINES)
+            .map(String::from)
+            .collect();
+        let mut stops = vec![CLOSING_FENCE];
+        if let Some(language) = Language::from_filetype(filetype) {
+            stops.extend_from_slice(language.stops);
+        }
+        Self {
+            text: String::new(),
+            emitted: 0,
+            at_indent: before.trim().is_empty(),
+            indent,
+            rest,
+            following,
+            stops,
+            answer: Answer::Code,
+            prefix: prefix.to_string(),
+            suffix: suffix.to_string(),
+        }
+    }
+
+    /// The completion is the answer of a chat model to a template with this hole marker.
+    pub fn infill(mut self, hole: &str) -> Self {
+        self.answer = Answer::Infill(hole.to_string());
+        self
+    }
+
+    /// The completion is the doc comment of an item indented with `indent`, turned into a `///` block.
+    pub fn doc_comment(indent: &str) -> Self {
+        Self {
+            indent: indent.to_string(),
+            answer: Answer::DocComment,
+            ..Self::new("", "", "")
+        }
+    }
+
+    /// Text to insert for this chunk, may be empty.
+    pub fn push(&mut self, chunk: &str) -> String {
+        self.text.push_str(chunk);
+        self.delta(false)
+    }
+
+    /// Text held until the end of the stream.
+    pub fn finish(&mut self) -> String {
+        self.delta(true)
+    }
+
+    fn delta(&mut self, finished: bool) -> String {
+        let processed = self.process(finished);
+        let delta = processed
+            .get(self.emitted..)
+            .unwrap_or_default()
+            .to_string();
+        self.emitted = self.emitted.max(processed.len());
+        delta
+    }
+
+    fn process(&self, finished: bool) -> String {
+        let infill;
+        let text = match &self.answer {
+            Answer::Code => self.text.as_str(),
+            // Only known once the whole answer is there.
+            _ if !finished => return String::new(),
+            Answer::Infill(hole) => {
+                infill = template::extract_infill(&self.text, hole, &self.prefix, &self.suffix);
+                infill.as_str()
+            }
+            Answer::DocComment => return document::doc_comment(&self.text, &self.indent),
+        };
+        let text = strip_fence(text, finished);
+        let (text, stopped) = cut_stop(text, &self.stops, finished);
+        let finished = finished || stopped;
+        let text = reindent(text, &self.indent, self.at_indent, finished);
+        if self.rest.is_empty() {
+            dedupe_lines(&text, &self.following, finished).to_string()
+        } else {
+            dedupe_rest(&text, &self.rest, finished).to_string()
+        }
+    }
+}
+
+/// Drop an opening fence (with its language) at the start of the completion.
+fn strip_fence(text: &str, finished: bool) -> &str {
+    let trimmed = text.trim_start();
+    if trimmed.starts_with(FENCE) {
+        trimmed.find('\n').map_or("", |i| &trimmed[i + 1..])
+    } else if !finished && FENCE.starts_with(trimmed) {
+        ""
+    } else {
+        text
+    }
+}
+
+/// Cut at the first stop sequence after the start of the completion, `true` when one was found.
+/// A tail which could be the start of a stop sequence is held.
+fn cut_stop<'text>(text: &'text str, stops: &[&str], finished: bool) -> (&'text str, bool) {
+    let start = text
+        .find(|c: char| !c.is_whitespace())
+        .unwrap_or(text.len());
+    let found = stops
+        .iter()
+        .filter_map(|stop| text[start..].find(stop))
+        .min();
+    if let Some(position) = found {
+        return (&text[..start + position], true);
+    }
+    if !finished {
+        for (index, _) in text.char_indices().skip(start) {
+            let tail = &text[index..];
+            if stops.iter().any(|stop| stop.starts_with(tail)) {
+                return (&text[..index], false);
+            }
+        }
+    }
+    (text, false)
+}
+
+/// Lines less indented than the cursor's line are shifted, when the first of them does not close a block.
+/// With the cursor on the indentation, the indentation repeated by the first line is dropped.
+fn reindent(text: &str, indent: &str, at_indent: bool, finished: bool) -> String {
+    let (first, rest) = match text.split_once('\n') {
+        Some((first, rest)) => (first, Some(rest)),
+        None => (text, None),
+    };
+    // The first line is held until its indentation is known.
+    if at_indent && !finished && rest.is_none() && first.trim().is_empty() {
+        return String::new();
+    }
+    let first = if at_indent {
+        first.strip_prefix(indent).unwrap_or(first)
+    } else {
+        first
+    };
+    let Some(rest) = rest else {
+        return first.to_string();
+    };
+    let mut lines: Vec<&str> = rest.split('\n').collect();
+    // The last line may still grow, it is held until it is not blank.
+    if !finished && lines.last().is_some_and(|line| line.trim().is_empty()) {
+        lines.pop();
+    }
+    let Some(reference) = lines.iter().find(|line| !line.trim().is_empty()) else {
+        if lines.is_empty() {
+            return first.to_string();
+        }
+        return format!("{first}\n{}", lines.join("\n"));
+    };
+    let content = reference.trim_start();
+    let base = &reference[..reference.len() - content.len()];
+    let closes = content.starts_with(['}', ')', ']']);
+    let extra = match indent.strip_prefix(base) {
+        Some(extra) if !closes => extra,
+        _ => "",
+    };
+    let mut result = first.to_string();
+    for line in lines {
+        result.push('\n');
+        if !line.trim().is_empty() {
+            result.push_str(extra);
+        }
+        result.push_str(line);
+    }
+    result
+}
+
+/// `(`, `[` and `{` opened minus the ones closed.
+fn depth(text: &str) -> i64 {
+    text.chars()
+        .map(|c| match c {
+            '(' | '[' | '{' => 1,
+            ')' | ']' | '}' => -1,
+            _ => 0,
+        })
+        .sum()
+}
+
+/// A duplicated closing bracket is only dropped when it closes a block of the prefix.
+fn can_drop(kept: &str, dropped: &str) -> bool {
+    depth(dropped) == 0 || (depth(kept) + depth(dropped) < 0 && depth(kept) <= 0)
+}
+
+/// The cursor is in the middle of a line : drop the end of the completion repeating the rest of the line.
+fn dedupe_rest<'text>(text: &'text str, rest: &str, finished: bool) -> &'text str {
+    if finished {
+        return match text.strip_suffix(rest) {
+            Some(kept) if can_drop(kept, rest) => kept,
+            _ => text,
+        };
+    }
+    for (index, _) in text.char_indices() {
+        let tail = &text[index..];
+        if !tail.contains('\n') && rest.starts_with(tail) {
+            return &text[..index];
+        }
+    }
+    text
+}
+
+/// The cursor is at the end of a line : drop the last lines of the completion repeating the next lines,
+/// the trailing blank lines are dropped.
+fn dedupe_lines<'text>(text: &'text str, following: &[String], finished: bool) -> &'text str {
+    let text = text.trim_end();
+    let mut offsets = vec![0];
+    offsets.extend(text.match_indices('\n').map(|(i, _)| i + 1));
+    let lines: Vec<&str> = text.split('\n').collect();
+    for start in 1..lines.len() {
+        if matches_following(&lines[start..], following, finished) {
+            let kept = &text[..offsets[start] - 1];
+            if !finished {
+                return kept;
+            }
+            if can_drop(kept, &text[offsets[start]..]) {
+                return kept;
+            }
+        }
+    }
+    text
+}
+
+/// Blank lines are skipped, the last line is only a start while the stream is running.
+fn matches_following(lines: &[&str], following: &[String], finished: bool) -> bool {
+    let mut matched = 0;
+    for (index, line) in lines.iter().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let Some(expected) = following.get(matched) else {
+            return false;
+        };
+        let partial = !finished && index == lines.len() - 1;
+        if (partial && !expected.starts_with(line)) || (!partial && line != expected) {
+            return false;
+        }
+        matched += 1;
+    }
+    matched > 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Streamed char by char, to check that the held text never changes once emitted.
+    fn run(filetype: &str, prefix: &str, suffix: &str, completion: &str) -> String {
+        let mut post_process = PostProcess::new(filetype, prefix, suffix);
+        let mut result = String::new();
+        for c in completion.chars() {
+            result += &post_process.push(&c.to_string());
+        }
+        result += &post_process.finish();
+        let mut whole = PostProcess::new(filetype, prefix, suffix);
+        assert_eq!(result, whole.push(completion) + &whole.finish());
+        result
+    }
+
+    #[test]
+    fn fences() {
+        let completion = "```rust\n    let a = 1;\n```\nExplanation.";
+        assert_eq!(run("rust", "    ", "\n}", completion), "let a = 1;");
+        assert_eq!(run("markdown", "", "", "`code`"), "`code`");
+    }
+
+    #[test]
+    fn duplicated_suffix() {
+        let suffix = "\n}\n\nfn g() {}";
+        let completion = "\n    a + 1\n}\n";
+        assert_eq!(run("rust", "fn f(a: u8) -> u8 {", suffix, completion), "\n    a + 1");
+        // The brace closes a block of the completion.
+        let completion = "\n    if a {\n        1\n    }";
+        assert_eq!(run("rust", "fn f(a: u8) -> u8 {", suffix, completion), completion);
+        assert_eq!(run("rust", "    let b = f(", ");", "a, 2);"), "a, 2");
+        assert_eq!(run("rust", "    let b = f(", ");", "g(a));"), "g(a)");
+    }
+
+    #[test]
+    fn indentation() {
+        let completion = "let a = 1;\nlet b = a;\n\nb";
+        assert_eq!(
+            run("python", "    ", "\n", completion),
+            "let a = 1;\n    let b = a;\n\n    b"
+        );
+        let completion = "a\n}\nfn g() {";
+        assert_eq!(run("c", "    ", "", completion), completion);
+    }
+
+    #[test]
+    fn chat_answer() {
+        let prefix = "fn f(a: u8) -> u8 {\n    let b = ";
+        let suffix = ";\n    b\n}";
+        let answer = "```rust\nfn f(a: u8) -> u8 {\n    let b = a + 1;\n    b\n}\n```";
+        let mut post_process = PostProcess::new("rust", prefix, suffix).infill("<FILL>");
+        assert_eq!(post_process.push(answer), "");
+        assert_eq!(post_process.finish(), "a + 1");
+    }
+
+    #[test]
+    fn doc_comment() {
+        let mut post_process = PostProcess::doc_comment("    ");
+        assert_eq!(post_process.push("/// Moves"), "");
+        assert_eq!(post_process.push(" up."), "");
+        assert_eq!(post_process.finish(), "    /// Moves up.\n");
+    }
+
+    #[test]
+    fn stops() {
+        let completion = "a + 1\n}\n\nfn g() {}";
+        assert_eq!(run("rust", "    ", "", completion), "a + 1\n}");
+        assert_eq!(run("go", "", "", "\nfunc f() {}"), "\nfunc f() {}");
+    }
+}
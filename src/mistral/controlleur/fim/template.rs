@@ -0,0 +1,159 @@
+use crate::{
+    mistral::model::{Message, Role},
+    nvim::model::config::FimTemplate,
+};
+
+/// Replace the `{name}` of the template in one pass, so a value containing `{hole}` is kept as is.
+fn render(template: &str, values: &[(&str, &str)]) -> String {
+    let mut result = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        result.push_str(&rest[..start]);
+        rest = &rest[start + 1..];
+        let value = values
+            .iter()
+            .find(|(name, _)| rest.starts_with(name) && rest[name.len()..].starts_with('}'));
+        match value {
+            Some((name, value)) => {
+                result.push_str(value);
+                rest = &rest[name.len() + 1..];
+            }
+            None => result.push('{'),
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+/// Chat messages asking the code of the hole between the prefix and the suffix.
+pub fn messages(template: &FimTemplate, filetype: &str, prefix: &str, suffix: &str) -> Vec<Message> {
+    let values = [
+        ("prefix", prefix),
+        ("suffix", suffix),
+        ("hole", template.hole.as_str()),
+        ("filetype", filetype),
+    ];
+    let mut messages = Vec::new();
+    if !template.system.is_empty() {
+        messages.push(Message {
+            role: Role::System,
+            content: render(&template.system, &values),
+            ..Default::default()
+        });
+    }
+    messages.push(Message {
+        role: Role::User,
+        content: render(&template.prompt, &values),
+        ..Default::default()
+    });
+    messages
+}
+
+/// Byte offset of the start of each line.
+fn line_offsets(text: &str) -> Vec<usize> {
+    let mut offsets = vec![0];
+    offsets.extend(text.match_indices('\n').map(|(i, _)| i + 1));
+    offsets
+}
+
+/// The first code block of a chat answer, the whole answer without one.
+pub fn code_block(answer: &str) -> &str {
+    match answer.find("```") {
+        Some(fence) => {
+            let start = answer[fence..]
+                .find('\n')
+                .map_or(answer.len(), |i| fence + i + 1);
+            let end = answer[start..]
+                .find("```")
+                .map_or(answer.len(), |i| start + i);
+            &answer[start..end]
+        }
+        None => answer,
+    }
+}
+
+/// The code of a chat answer : the first code block if any, without the hole marker
+/// and without the end of the prefix or the start of the suffix when the answer repeats them.
+pub fn extract_infill(answer: &str, hole: &str, prefix: &str, suffix: &str) -> String {
+    let code = code_block(answer).replace(hole, "");
+    let code = code.trim_end();
+    let code = strip_prefix(code, prefix);
+    strip_suffix(code, suffix).to_string()
+}
+
+/// The answer starts with the last lines of the prefix, the last one (before the cursor) may be partial.
+fn strip_prefix<'code>(code: &'code str, prefix: &str) -> &'code str {
+    let lines: Vec<&str> = code.split('\n').collect();
+    let offsets = line_offsets(code);
+    let prefix: Vec<&str> = prefix.split('\n').collect();
+    let Some(before) = prefix.last().map(|line| line.trim_start()) else {
+        return code;
+    };
+    for count in (1..=lines.len().min(prefix.len())).rev() {
+        if count == 1 && before.trim().is_empty() {
+            continue;
+        }
+        let full = &prefix[prefix.len() - count..prefix.len() - 1];
+        let same = full
+            .iter()
+            .zip(&lines)
+            .all(|(expected, line)| expected.trim() == line.trim());
+        let last = lines[count - 1].trim_start();
+        if same && last.starts_with(before) {
+            let start = offsets[count - 1] + lines[count - 1].len() - last.len() + before.len();
+            return &code[start..];
+        }
+    }
+    code
+}
+
+/// The answer ends with the first lines of the suffix, at least two lines so the end of a single line
+/// is left to the post process which checks the brackets.
+fn strip_suffix<'code>(code: &'code str, suffix: &str) -> &'code str {
+    let lines: Vec<&str> = code.split('\n').collect();
+    let offsets = line_offsets(code);
+    let suffix: Vec<&str> = suffix.split('\n').collect();
+    let rest = suffix.first().map_or("", |line| line.trim());
+    for count in (2..=lines.len().min(suffix.len())).rev() {
+        let first = lines.len() - count;
+        let same = suffix[1..count]
+            .iter()
+            .zip(&lines[first + 1..])
+            .all(|(expected, line)| expected.trim() == line.trim());
+        let line = lines[first].trim_end();
+        if same && line.ends_with(rest) {
+            return &code[..offsets[first] + line.len() - rest.len()];
+        }
+    }
+    code
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prompt() {
+        let template = FimTemplate {
+            system: "Fill {hole} in {filetype}.".to_string(),
+            prompt: "{prefix}{hole}{suffix} {unknown}".to_string(),
+            hole: "<FILL>".to_string(),
+        };
+        let messages = messages(&template, "rust", "let a = {hole};\nlet b = ", ";");
+        assert_eq!(messages[0].content, "Fill <FILL> in rust.");
+        assert_eq!(messages[1].content, "let a = {hole};\nlet b = <FILL>; {unknown}");
+    }
+
+    #[test]
+    fn infill() {
+        let prefix = "fn f(a: u8) -> u8 {\n    let b = ";
+        let suffix = ";\n    b\n}";
+        assert_eq!(extract_infill("a + 1", "<FILL>", prefix, suffix), "a + 1");
+        let answer = "Here is the code :\n```rust\n    let b = a + 1;\n```\n";
+        assert_eq!(extract_infill(answer, "<FILL>", prefix, suffix), "a + 1;");
+        // The whole function is repeated.
+        let answer = "fn f(a: u8) -> u8 {\n    let b = a + 1;\n    b\n}";
+        assert_eq!(extract_infill(answer, "<FILL>", prefix, suffix), "a + 1");
+        assert_eq!(extract_infill("<FILL>a + 1", "<FILL>", prefix, suffix), "a + 1");
+    }
+}
@@ -0,0 +1,30 @@
+use crate::mistral::model::{Message, Role};
+
+const SYSTEM: &'static str = "You write unit tests for the Rust function sent by the user. Answer with a single \
+                              ```rust code block of `#[test]` functions only : no `mod`, no `use`, the tests are \
+                              merged in the `mod tests` of the file which already has `use super::*;`. Cover the \
+                              edge cases, and the errors or panics the function documents. Do not repeat the \
+                              existing tests.";
+
+/// Chat messages asking the tests of `function`, each one named after `name` so `cargo test <name>` runs them.
+pub fn messages(function: &str, name: &str, existing: &[String]) -> Vec<Message> {
+    let mut prompt = format!("Function :\n```rust\n{function}\n```");
+    if !existing.is_empty() {
+        prompt.push_str("\n\nExisting tests :\n```rust\n");
+        prompt.push_str(&existing.join("\n\n"));
+        prompt.push_str("\n```");
+    }
+    prompt.push_str(&format!("\n\nThe name of each test starts with `{name}_`."));
+    vec![
+        Message {
+            role: Role::System,
+            content: SYSTEM.to_string(),
+            ..Default::default()
+        },
+        Message {
+            role: Role::User,
+            content: prompt,
+            ..Default::default()
+        },
+    ]
+}
@@ -1 +1,3 @@
+pub mod cargo;
 pub mod fim;
+pub mod ocr;
@@ -0,0 +1,44 @@
+use crate::{
+    messages::{self, IdMessage, MistralMessage},
+    mistral::{
+        controlleur::fim::SharedContext,
+        model::{ocr::OcrSource, stream::StreamResponse},
+    },
+};
+
+async fn extract_markdown(source: &OcrSource, context: &SharedContext) -> crate::Result<String> {
+    if let Some(markdown) = source.cached()? {
+        return Ok(markdown);
+    }
+    let body = serde_json::to_value(source.build_request()?)?;
+    let markdown = context.client.ocr(body).await?.markdown();
+    source.store(&markdown)?;
+    Ok(markdown)
+}
+
+pub async fn ocr(id: IdMessage, message: messages::Ocr, context: SharedContext) -> crate::Result<()> {
+    let messages::Ocr { source, cursor } = message;
+    let sendle = &context.nvim_sendle;
+    match cursor {
+        Some(cursor) => {
+            let markdown = extract_markdown(&source, &context).await?;
+            sendle.send(id, MistralMessage::InitializeTask(cursor));
+            // Start on a new line, the cursor is at the end of the current one.
+            let chunk = std::iter::once("")
+                .chain(markdown.split('\n'))
+                .map(ToString::to_string)
+                .collect();
+            sendle.send(id, MistralMessage::UpdateContent(chunk));
+            let mut response = StreamResponse::new();
+            response.message.content = markdown;
+            sendle.send(id, MistralMessage::FinalizeTask(response));
+        }
+        None => {
+            let result = extract_markdown(&source, &context).await;
+            // Always answer, otherwise the chat would wait for this document forever.
+            sendle.send(id, MistralMessage::OcrDone(source));
+            result?;
+        }
+    }
+    Ok(())
+}
@@ -9,7 +9,7 @@ pub mod client;
 pub mod controlleur;
 pub mod model;
 
-use controlleur::fim;
+use controlleur::fim::{self, QueryKind};
 
 #[tokio::main]
 pub async fn mistral_loop(mut rx: NvimReceiver, tx_nvim: MistralSender, nvim_handle: nvim_oxi::libuv::AsyncHandle) {
@@ -33,9 +33,19 @@ pub async fn handle_message(id: IdMessage, message: NvimMessage, ctx: SharedCont
         NvimMessage::Abort => fim::abort_task(id, ctx).await,
         // FIM
         NvimMessage::FimCursorLine(normal) => fim::cursor(id, normal, ctx).await,
-        NvimMessage::FimFunction(normal) => fim::function(id, normal, ctx).await,
-        // NvimMessage::FimStatement(normal) => todo!(),
+        NvimMessage::FimFunction(normal) => fim::target(id, normal, QueryKind::Function, ctx).await,
+        NvimMessage::FimStatement(normal) => fim::target(id, normal, QueryKind::Statement, ctx).await,
+        NvimMessage::FimStructure(normal) => fim::target(id, normal, QueryKind::Struct, ctx).await,
+        NvimMessage::FimImpl(normal) => fim::target(id, normal, QueryKind::Impl, ctx).await,
         NvimMessage::FimVisual(visual) => fim::visual(id, visual, ctx).await,
+        NvimMessage::FimSuggestion(suggest) => fim::suggestion(id, suggest, ctx).await,
+        NvimMessage::FimPlaceholder(placeholder) => fim::placeholder(id, placeholder, ctx).await,
+        NvimMessage::Edit(edit) => fim::edit(id, edit, ctx).await,
+        NvimMessage::Document(document) => fim::document(id, document, ctx).await,
+        NvimMessage::GenerateTests(generate) => fim::generate_tests(id, generate, ctx).await,
+        NvimMessage::CargoTest(cargo_test) => controlleur::cargo::test(id, cargo_test, ctx).await,
         NvimMessage::Chat(request) => fim::chat_completion(id, request, ctx).await,
+        // OCR
+        NvimMessage::Ocr(ocr) => controlleur::ocr::ocr(id, ocr, ctx).await,
     }
 }
@@ -67,6 +67,26 @@ impl Model {
     pub fn fim() -> Self {
         Self::CodestralLatest
     }
+    /// From the API name (`codestral-latest`), `None` for a model unknown to Mistral (ex: a local one).
+    pub fn from_name(name: &str) -> Option<Self> {
+        serde_json::from_value(serde_json::Value::String(name.to_string())).ok()
+    }
+    /// Context window in tokens.
+    pub fn context_size(&self) -> u32 {
+        match self {
+            Self::CodestralLatest => 256_000,
+            Self::MistralLargeLatest
+            | Self::MistralMediumLatest
+            | Self::MistralNemoLatest
+            | Self::DevstralMediumLatest
+            | Self::PixtralLargeLatest
+            | Self::Ministral3bLatest
+            | Self::Ministral8bLatest => 128_000,
+            Self::MagistralMediumLatest => 40_000,
+            Self::MistralTinyLatest | Self::Codestral2405 | Self::VoxtralMiniLatest => 32_000,
+            Self::MistralOcrLatest => 0,
+        }
+    }
 }
 
 #[derive(Serialize, Default)]
@@ -91,6 +111,15 @@ pub struct FimRequest {
     pub params: CompletionParams,
 }
 
+/// Chat request for a model given by name, the FIM of models without a FIM endpoint.
+#[derive(Serialize)]
+pub struct TemplateRequest {
+    pub model: String,
+    pub messages: Vec<Message>,
+    #[serde(flatten)]
+    pub params: CompletionParams,
+}
+
 #[derive(Serialize, Default)]
 pub struct FimCompletion {
     #[serde(default = "Model::fim")]
@@ -110,4 +139,7 @@ pub struct CompletionParams {
     #[serde(skip_deserializing)] // Not used by ChatState
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tools: Option<Vec<Tool>>,
+    #[serde(skip_deserializing)] // Not used by ChatState
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub random_seed: Option<u32>,
 }
@@ -1,5 +1,6 @@
 pub mod completion;
 pub mod message;
+pub mod ocr;
 pub mod stream;
 pub mod tools;
 
@@ -0,0 +1,244 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{LazyLock, Mutex},
+    time::SystemTime,
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest as _, Sha256};
+
+use super::completion::Model;
+
+/// A document to extract, as written in `<OCR path="spec.pdf" pages="1-5"/>`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct OcrSource {
+    pub path: PathBuf,
+    pub pages: Option<PageRange>,
+}
+
+/// Pages selected by the user, one-indexed like in any PDF reader (ex: `1-5,8`).
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct PageRange(Vec<u32>);
+
+impl std::str::FromStr for PageRange {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let mut pages = Vec::new();
+        for part in value.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let parse = |page: &str| match page.trim().parse::<u32>() {
+                Ok(0) => Err("Pages start at 1.".to_string()),
+                Ok(page) => Ok(page),
+                Err(_) => Err(format!("`{page}` is not a page number.")),
+            };
+            match part.split_once('-') {
+                Some((start, end)) => {
+                    let (start, end) = (parse(start)?, parse(end)?);
+                    if start > end {
+                        return Err(format!("Reversed page range `{part}`."));
+                    }
+                    pages.extend(start..=end);
+                }
+                None => pages.push(parse(part)?),
+            }
+        }
+        if pages.is_empty() {
+            return Err("No page selected.".to_string());
+        }
+        pages.sort_unstable();
+        pages.dedup();
+        Ok(Self(pages))
+    }
+}
+
+impl std::fmt::Display for PageRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut ranges: Vec<(u32, u32)> = Vec::new();
+        for page in &self.0 {
+            match ranges.last_mut() {
+                Some((_, end)) if *end + 1 == *page => *end = *page,
+                _ => ranges.push((*page, *page)),
+            }
+        }
+        let ranges: Vec<String> = ranges
+            .into_iter()
+            .map(|(start, end)| {
+                if start == end {
+                    start.to_string()
+                } else {
+                    format!("{start}-{end}")
+                }
+            })
+            .collect();
+        write!(f, "{}", ranges.join(","))
+    }
+}
+
+impl PageRange {
+    /// The OCR endpoint expects zero-indexed pages.
+    pub fn to_api(&self) -> Vec<u32> {
+        self.0.iter().map(|page| page - 1).collect()
+    }
+}
+
+#[derive(Serialize)]
+pub struct OcrRequest {
+    pub model: Model,
+    pub document: Document,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pages: Option<Vec<u32>>,
+    pub include_image_base64: bool,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Document {
+    DocumentUrl { document_url: String },
+    ImageUrl { image_url: String },
+}
+
+#[derive(Deserialize)]
+pub struct OcrResponse {
+    pub pages: Vec<OcrPage>,
+}
+
+#[derive(Deserialize)]
+pub struct OcrPage {
+    #[allow(dead_code)]
+    pub index: u32,
+    pub markdown: String,
+}
+
+impl OcrResponse {
+    pub fn markdown(&self) -> String {
+        self.pages
+            .iter()
+            .map(|page| page.markdown.trim())
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+}
+
+/// Hashes of the files already read, so `update_buffer` does not read the whole PDF each time.
+static HASHES: LazyLock<Mutex<HashMap<(PathBuf, SystemTime, u64), String>>> = LazyLock::new(Default::default);
+
+impl OcrSource {
+    pub fn new(path: impl Into<PathBuf>, pages: Option<&str>) -> crate::Result<Self> {
+        let pages = match pages.map(str::trim) {
+            None | Some("") => None,
+            Some(pages) => Some(pages.parse::<PageRange>()?),
+        };
+        Ok(Self {
+            path: path.into(),
+            pages,
+        })
+    }
+
+    pub fn build_request(&self) -> crate::Result<OcrRequest> {
+        use base64::Engine as _;
+        let mime =
+            mime_type(&self.path).ok_or(format!("OCR only supports pdf and images (`{}`).", self.path.display()))?;
+        let bytes = std::fs::read(&self.path)?;
+        let url = format!(
+            "data:{mime};base64,{}",
+            base64::engine::general_purpose::STANDARD.encode(bytes)
+        );
+        let document = if mime == "application/pdf" {
+            Document::DocumentUrl { document_url: url }
+        } else {
+            Document::ImageUrl { image_url: url }
+        };
+        Ok(OcrRequest {
+            model: Model::MistralOcrLatest,
+            document,
+            pages: self.pages.as_ref().map(PageRange::to_api),
+            include_image_base64: false,
+        })
+    }
+
+    fn file_hash(&self) -> crate::Result<String> {
+        let metadata = std::fs::metadata(&self.path)?;
+        let key = (self.path.clone(), metadata.modified()?, metadata.len());
+        if let Some(hash) = HASHES.lock()?.get(&key) {
+            return Ok(hash.clone());
+        }
+        let bytes = std::fs::read(&self.path)?;
+        let hash = format!("{:x}", Sha256::digest(&bytes));
+        HASHES.lock()?.insert(key, hash.clone());
+        Ok(hash)
+    }
+
+    /// The cache is keyed by the file's content, so moving or renaming a document keeps its result.
+    fn cache_path(&self) -> crate::Result<PathBuf> {
+        let pages = self
+            .pages
+            .as_ref()
+            .map_or("all".to_string(), ToString::to_string);
+        Ok(cache_dir()?.join(format!("{}-{pages}.md", self.file_hash()?)))
+    }
+
+    pub fn cached(&self) -> crate::Result<Option<String>> {
+        match std::fs::read_to_string(self.cache_path()?) {
+            Ok(markdown) => Ok(Some(markdown)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    pub fn store(&self, markdown: &str) -> crate::Result<()> {
+        let path = self.cache_path()?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, markdown)?;
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for OcrSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.pages {
+            Some(pages) => write!(f, "{} (pages {pages})", self.path.display()),
+            None => write!(f, "{}", self.path.display()),
+        }
+    }
+}
+
+fn mime_type(path: &Path) -> Option<&'static str> {
+    let extension = path.extension()?.to_str()?.to_lowercase();
+    Some(match extension.as_str() {
+        "pdf" => "application/pdf",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "webp" => "image/webp",
+        "gif" => "image/gif",
+        "avif" => "image/avif",
+        _ => return None,
+    })
+}
+
+fn cache_dir() -> crate::Result<PathBuf> {
+    let base = match std::env::var_os("XDG_CACHE_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(std::env::var_os("HOME").ok_or("No HOME to store OCR results.")?).join(".cache"),
+    };
+    Ok(base.join("mistral_nvim").join("ocr"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn page_range() {
+        let range: PageRange = "1-3, 7,5-5,2".parse().unwrap();
+        assert_eq!(range.0, vec![1, 2, 3, 5, 7]);
+        assert_eq!(range.to_string(), "1-3,5,7");
+        assert_eq!(range.to_api(), vec![0, 1, 2, 4, 6]);
+        assert!("0-2".parse::<PageRange>().is_err());
+        assert!("5-2".parse::<PageRange>().is_err());
+        assert!("a".parse::<PageRange>().is_err());
+        assert!("".parse::<PageRange>().is_err());
+    }
+}
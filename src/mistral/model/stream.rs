@@ -26,11 +26,45 @@ pub struct StreamEvent {
 
 #[derive(Deserialize)]
 pub struct Delta {
-    content: Option<String>,
+    content: Option<DeltaContent>,
     role: Option<Role>,
     tool_calls: Option<Vec<ToolCall>>,
 }
 
+/// Reasoning models (Magistral) send chunks instead of a plain string.
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum DeltaContent {
+    Text(String),
+    Chunks(Vec<ContentChunk>),
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContentChunk {
+    Text {
+        text: String,
+    },
+    Thinking {
+        thinking: Vec<ContentChunk>,
+    },
+    #[serde(other)]
+    Unknown,
+}
+
+impl ContentChunk {
+    fn collect_text(chunks: Vec<ContentChunk>) -> String {
+        chunks
+            .into_iter()
+            .map(|chunk| match chunk {
+                ContentChunk::Text { text } => text,
+                ContentChunk::Thinking { thinking } => Self::collect_text(thinking),
+                ContentChunk::Unknown => String::new(),
+            })
+            .collect()
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, Default)]
 pub struct Usage {
     pub prompt_tokens: u32,
@@ -40,15 +74,11 @@ pub struct Usage {
 
 impl From<String> for Usage {
     fn from(value: String) -> Self {
-        let mut split = value.split(';');
-        fn parse<'it>(next: Option<&str>) -> u32 {
-            next.map(|s| s.parse().unwrap_or_default())
-                .unwrap_or_default()
-        }
+        let [prompt_tokens, completion_tokens, total_tokens] = chat_format::parse_usage(&value);
         Self {
-            prompt_tokens: parse(split.next()),
-            completion_tokens: parse(split.next()),
-            total_tokens: parse(split.next()),
+            prompt_tokens,
+            completion_tokens,
+            total_tokens,
         }
     }
 }
@@ -89,6 +119,8 @@ pub struct StreamChoice {
 #[derive(Debug)]
 pub struct StreamResponse {
     pub message: Message,
+    /// Reasoning trace, kept out of `message` so it is never sent back by default.
+    pub thinking: Option<String>,
     pub status: Status,
     pub usage: Usage,
 }
@@ -97,11 +129,27 @@ impl StreamResponse {
     pub fn new() -> Self {
         StreamResponse {
             message: Message::default(),
+            thinking: None,
             status: Status::Completed,
             usage: Usage::default(),
         }
     }
 
+    fn add_content(&mut self, content: String, sendle: &SenderHandle, id: IdMessage) {
+        self.message.content += &content;
+        let chunk = content.split('\n').map(ToString::to_string).collect();
+        sendle.send(id, MistralMessage::UpdateContent(chunk));
+    }
+
+    fn add_thinking(&mut self, thinking: String, sendle: &SenderHandle, id: IdMessage) {
+        if thinking.is_empty() {
+            return;
+        }
+        self.thinking.get_or_insert_default().push_str(&thinking);
+        let chunk = thinking.split('\n').map(ToString::to_string).collect();
+        sendle.send(id, MistralMessage::UpdateThinking(chunk));
+    }
+
     pub async fn add_delta(&mut self, other: Delta, sendle: SenderHandle, id: IdMessage) -> Result<(), std::io::Error> {
         // logs!("SEND_DATA");
         let Delta {
@@ -113,10 +161,20 @@ impl StreamResponse {
             self.message.role = role.clone();
             sendle.send(id, MistralMessage::UpdateRole(role));
         }
-        if let Some(content) = content {
-            self.message.content += &content;
-            let chunk = content.split('\n').map(ToString::to_string).collect();
-            sendle.send(id, MistralMessage::UpdateContent(chunk));
+        match content {
+            Some(DeltaContent::Text(content)) => self.add_content(content, &sendle, id),
+            Some(DeltaContent::Chunks(chunks)) => {
+                for chunk in chunks {
+                    match chunk {
+                        ContentChunk::Text { text } => self.add_content(text, &sendle, id),
+                        ContentChunk::Thinking { thinking } => {
+                            self.add_thinking(ContentChunk::collect_text(thinking), &sendle, id)
+                        }
+                        ContentChunk::Unknown => (),
+                    }
+                }
+            }
+            None => (),
         }
         if let Some(tool_calls) = tool_calls {
             sendle.send(id, MistralMessage::RunTool(tool_calls.clone()));
@@ -0,0 +1,109 @@
+use std::{cell::RefCell, collections::HashMap, sync::LazyLock};
+
+use nvim_oxi::{
+    self as oxi, api,
+    api::opts::{CreateAutocmdOpts, CreateCommandOpts},
+    libuv::TimerHandle,
+};
+
+use crate::{
+    notify::NotifyExtV2 as _,
+    nvim::{
+        model::{
+            Cursor, Locker as _, SharedState,
+            config::{self, AutoFim},
+        },
+        vue::suggestion::dismiss,
+    },
+    utils::get_option,
+};
+
+static GROUP: LazyLock<u32> = LazyLock::new(|| api::create_augroup("MistralAutoFIM", &Default::default()).unwrap_or(0));
+
+thread_local! {
+    /// Only touched on Neovim's thread, the previous timer is stopped at each keystroke.
+    static TIMERS: RefCell<HashMap<api::Buffer, TimerHandle>> = Default::default();
+}
+
+/// Restart the idle timer of the buffer, the request is only sent after a pause.
+fn text_changed(state: &SharedState, buffer: api::Buffer) -> crate::Result<()> {
+    let config = AutoFim::read();
+    let filetype: String = get_option(&buffer, "filetype").unwrap_or_default();
+    let buftype: String = get_option(&buffer, "buftype").unwrap_or_default();
+    if !buftype.is_empty() || !config.allows(&filetype) {
+        return Ok(());
+    }
+    let generation = {
+        let mut s = state.lock();
+        // Accepting a part of the suggestion also changes the text.
+        if let Some(suggestion) = s.suggestions.get(&buffer)
+            && Cursor::from_window_current().as_ref() == Some(&suggestion.anchor)
+        {
+            return Ok(());
+        }
+        // Stale : aborts the request of the previous position.
+        dismiss(&buffer, &mut s)?;
+        next_generation(&mut s.auto_fim, &buffer)
+    };
+    let state = SharedState::clone(state);
+    let target = buffer.clone();
+    let timer = TimerHandle::once(config.delay, move || {
+        oxi::schedule(move |_| fire(&state, &target, generation).notify());
+    })
+    .map_err(|err| err.to_string())?;
+    TIMERS.with_borrow_mut(|timers| {
+        if let Some(mut previous) = timers.insert(buffer, timer) {
+            let _ = previous.stop();
+        }
+    });
+    Ok(())
+}
+
+fn next_generation(generations: &mut HashMap<api::Buffer, usize>, buffer: &api::Buffer) -> usize {
+    let generation = generations.entry(buffer.clone()).or_default();
+    *generation = generation.wrapping_add(1);
+    *generation
+}
+
+fn fire(state: &SharedState, buffer: &api::Buffer, generation: usize) -> crate::Result<()> {
+    let is_last = state.lock().auto_fim.get(buffer) == Some(&generation);
+    if !is_last || !api::get_mode().mode.is_insert() || api::Buffer::current() != *buffer {
+        return Ok(());
+    }
+    super::suggestion::suggest(state)
+}
+
+fn toggle() -> crate::Result<()> {
+    let enabled = !AutoFim::read().enabled;
+    config::set_var("auto_fim", enabled)?;
+    crate::notify::info(format!("Auto FIM {}.", if enabled { "enabled" } else { "disabled" }));
+    Ok(())
+}
+
+pub fn setup(s: &SharedState) -> crate::Result<()> {
+    let d = "Active/désactive la suggestion FIM automatique en mode insertion.";
+    let opts = CreateCommandOpts::builder().desc(d).build();
+    api::create_user_command("MistralAutoFIMToggle", |_| toggle().notify(), &opts)?;
+
+    let state = SharedState::clone(s);
+    let opts = CreateAutocmdOpts::builder()
+        .group(*GROUP)
+        .desc("Request a suggestion after a pause in insert mode.")
+        .callback(move |args: api::types::AutocmdCallbackArgs| -> bool {
+            text_changed(&state, args.buffer).notify();
+            false
+        })
+        .build();
+    api::create_autocmd(["TextChangedI"], &opts)?;
+    let state = SharedState::clone(s);
+    let opts = CreateAutocmdOpts::builder()
+        .group(*GROUP)
+        .desc("Cancel the pending suggestion.")
+        .callback(move |args: api::types::AutocmdCallbackArgs| -> bool {
+            next_generation(&mut state.lock().auto_fim, &args.buffer);
+            false
+        })
+        .build();
+    api::create_autocmd(["InsertLeave", "BufLeave"], &opts)?;
+    Ok(())
+}
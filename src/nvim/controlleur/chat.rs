@@ -1,14 +1,26 @@
 use std::sync::{Arc, LazyLock, Mutex};
 
-use nvim_oxi::api::{self, opts::CreateCommandOpts, types::CommandRange};
+use nvim_oxi::{
+    Array, Dictionary, Object,
+    api::{
+        self,
+        opts::CreateCommandOpts,
+        types::{CommandArgs, CommandComplete, CommandNArgs, CommandRange},
+    },
+};
 
 use super::form;
 use crate::{
+    mistral::model::completion::Model,
     notify::{NotifyExt as _, NotifyExtV2},
-    nvim::model::{self, Chat, ChatForm, Locker as _, RowRange, SharedState, state::chat::bar},
+    nvim::model::{
+        self, Chat, ChatForm, Locker as _, RowRange, SharedState,
+        state::chat::{ChatMetadata, ExportFormat, Imported, SearchQuery, bar, index},
+    },
 };
 
 mod code_block_paste;
+mod picker;
 use code_block_paste::code_block_paste;
 
 const CHAT_FILES: [&'static str; 1] = ["*.chat"];
@@ -28,6 +40,9 @@ pub fn setup_keymaps(state: &SharedState, buffer: &api::Buffer) -> crate::Result
         "<Right>" => {next_message(&state)} <= <state: SharedState>
         "<CR><CR>" => {send_prompt(&state)} <= <state: SharedState>
         "<Leader>cp" => {add_prompt(&state).notify()} <= <state: SharedState>
+        "<Leader>cg" => {regenerate(&state, None).notify()} <= <state: SharedState>
+        "<Leader>cv" => {cycle_version(&state, 1).notify()} <= <state: SharedState>
+        "<Leader>cV" => {cycle_version(&state, -1).notify()} <= <state: SharedState>
     }
     Ok(())
 }
@@ -41,12 +56,20 @@ pub fn setup_commands(s: &SharedState) -> crate::Result<()> {
     cmd("MistralNewChat", move |_| new_chat(&state), &opts)?;
     // let state = SharedState::clone(&s);
     // cmd("MistralTool", move |_| launch_tool(&state), &opts)?;
-    // let state = SharedState::clone(&s);
-    // cmd("MistralListChat", move |args| model::State::load_chat(&state), &opts)?;
-    // let state = SharedState::clone(&s);
-    // let one = CommandNArgs::One;
-    // let opts_arg = CreateCommandOpts::builder().nargs(one).build();
-    // cmd("MistralLoadChat", move |args| load_chat(&state, args), &opts_arg)?;
+    let d = "Liste les chats du projet : <CR> ouvre, p affiche un aperçu, r renomme et d supprime.";
+    let opts = CreateCommandOpts::builder().desc(d).build();
+    cmd("MistralListChat", move |_| picker::list_chats().notify(), &opts)?;
+    let d = "Ouvre un fichier `.chat`.";
+    let opts_arg = CreateCommandOpts::builder()
+        .desc(d)
+        .nargs(CommandNArgs::One)
+        .complete(CommandComplete::File)
+        .build();
+    cmd(
+        "MistralLoadChat",
+        move |args: CommandArgs| picker::load_chat(&args.args.unwrap_or_default()).notify(),
+        &opts_arg,
+    )?;
 
     let opts = CreateCommandOpts::builder().build();
     let state = SharedState::clone(&s);
@@ -71,6 +94,79 @@ pub fn setup_commands(s: &SharedState) -> crate::Result<()> {
     cmd("MistralChatReRunTools", move |_| rerun_tools(&state).notify(), &opts)?;
     let state = SharedState::clone(&s);
     cmd("MistralChatReRunTool", move |_| rerun_tool(&state).notify(), &opts)?;
+    let state = SharedState::clone(&s);
+    cmd(
+        "MistralChatToggleThinking",
+        move |_| toggle_thinking(&state).notify(),
+        &opts,
+    )?;
+    let d = "Exporte le chat à côté du fichier, ou vers la cible donnée, sans écraser un fichier existant : `md`, `html` ou `json` (les messages envoyés à l'API).";
+    let state = SharedState::clone(&s);
+    let opts_export = CreateCommandOpts::builder()
+        .desc(d)
+        .nargs(CommandNArgs::OneOrMore)
+        .complete(CommandComplete::File)
+        .build();
+    cmd(
+        "MistralChatExport",
+        move |args| export(&state, args).notify(),
+        &opts_export,
+    )?;
+    let d = "Importe un tableau `messages` de l'API (`json`), un journal de requêtes (`jsonl`) ou une transcription Markdown (`md`) dans un nouveau chat.";
+    let opts_import = CreateCommandOpts::builder()
+        .desc(d)
+        .nargs(CommandNArgs::OneOrMore)
+        .complete(CommandComplete::File)
+        .build();
+    cmd("MistralChatImport", move |args| import(args).notify(), &opts_import)?;
+    let d = "Copie la conversation jusqu'au message sous le curseur dans un nouveau chat, puis l'ouvre.";
+    let state = SharedState::clone(&s);
+    let opts = CreateCommandOpts::builder().desc(d).build();
+    cmd("MistralChatFork", move |_| fork(&state).notify(), &opts)?;
+    let d = "Liste les chats dérivés du chat courant dans la quickfix.";
+    let state = SharedState::clone(&s);
+    let opts = CreateCommandOpts::builder().desc(d).build();
+    cmd("MistralChatForks", move |_| list_forks(&state).notify(), &opts)?;
+    let d = "Cherche un texte dans les messages des chats du projet, filtré par `role:`, `model:`, `since:` ou `until:` (YYYY-MM-DD, date du message, ou de dernière modification du fichier pour les anciens messages).";
+    let opts_search = CreateCommandOpts::builder()
+        .desc(d)
+        .nargs(CommandNArgs::OneOrMore)
+        .build();
+    cmd("MistralChatSearch", move |args| search(args).notify(), &opts_search)?;
+    let d = "Régénère la réponse sous le curseur, avec un autre modèle en option (`mistral-large-latest`), et garde les versions précédentes. Avec `!`, arrête la régénération en cours.";
+    let state = SharedState::clone(&s);
+    let opts = CreateCommandOpts::builder()
+        .desc(d)
+        .nargs(CommandNArgs::ZeroOrOne)
+        .bang(true)
+        .build();
+    cmd(
+        "MistralChatRegenerate",
+        move |args: CommandArgs| {
+            if args.bang {
+                stop_regeneration(&state, &api::Buffer::current()).notify()
+            } else {
+                regenerate(&state, args.args.filter(|arg| !arg.is_empty())).notify()
+            }
+        },
+        &opts,
+    )?;
+    let d = "Affiche la version suivante de la réponse sous le curseur.";
+    let state = SharedState::clone(&s);
+    let opts = CreateCommandOpts::builder().desc(d).build();
+    cmd(
+        "MistralChatNextVersion",
+        move |_| cycle_version(&state, 1).notify(),
+        &opts,
+    )?;
+    let d = "Affiche la version précédente de la réponse sous le curseur.";
+    let state = SharedState::clone(&s);
+    let opts = CreateCommandOpts::builder().desc(d).build();
+    cmd(
+        "MistralChatPrevVersion",
+        move |_| cycle_version(&state, -1).notify(),
+        &opts,
+    )?;
 
     let vopt = CreateCommandOpts::builder()
         .range(CommandRange::WholeFile)
@@ -90,6 +186,29 @@ pub fn setup_commands(s: &SharedState) -> crate::Result<()> {
         .build();
     api::create_autocmd(["BufRead", "VimEnter"], &opts).unwrap();
 
+    let opts = api::opts::CreateAutocmdOpts::builder()
+        .group(*GROUP)
+        .desc("Fold the reasoning traces.")
+        .patterns(CHAT_FILES)
+        .callback(move |_args: api::types::AutocmdCallbackArgs| -> bool {
+            configure_folds(&api::Window::current());
+            false
+        })
+        .build();
+    api::create_autocmd(["BufWinEnter"], &opts).unwrap();
+
+    let state = SharedState::clone(&s);
+    let opts = api::opts::CreateAutocmdOpts::builder()
+        .group(*GROUP)
+        .desc("Stop the regeneration of a closed chat.")
+        .patterns(CHAT_FILES)
+        .callback(move |args: api::types::AutocmdCallbackArgs| -> bool {
+            stop_regeneration(&state, &args.buffer).notify();
+            false
+        })
+        .build();
+    api::create_autocmd(["BufUnload"], &opts).unwrap();
+
     let modified_rows: ModifiedRows = Default::default();
     let modified_rows_cloned = Arc::clone(&modified_rows);
     let modified_rows = Arc::clone(&modified_rows_cloned);
@@ -184,6 +303,17 @@ fn update_buffer_with_modified_rows(state: &SharedState, modified_rows: &Modifie
     }
 }
 
+/// `<THINKING>` sections are folded, `zo` to read them.
+fn configure_folds(window: &api::Window) {
+    use crate::utils::set_option_win;
+    // The reasoning traces and the other versions of an answer, a version holds the fold of its trace.
+    let expr = r"getline(v:lnum)=~#'^<\(THINKING\|VERSION\)[ >]'?'a1':getline(v:lnum)=~#'^</\(THINKING\|VERSION\)>$'?'s1':'='";
+    set_option_win(window, "foldmethod", "expr");
+    set_option_win(window, "foldexpr", expr);
+    set_option_win(window, "foldlevel", 0);
+    set_option_win(window, "foldenable", true);
+}
+
 fn new_chat(state: &SharedState) {
     form::formulaire(&state, |chat_form: ChatForm, state: SharedState| {
         let buffer = api::Buffer::current();
@@ -201,6 +331,7 @@ fn new_chat(state: &SharedState) {
         };
         let chat = crate::nvim::model::Chat::from_state(chat);
         Chat::clone(&chat).configure_statusline(&buffer, api::Window::current());
+        configure_folds(&api::Window::current());
         state.lock().chats.insert(chat);
     })
 }
@@ -270,7 +401,9 @@ pub fn load_chat(state: &SharedState, buffer: api::Buffer) {
 
 fn send_prompt(state: &SharedState) {
     if let Some(chat) = Chat::from_current_buffer_target_prompt(&state) {
-        chat.lock().send_prompt(&state).notify_warn()
+        let mut chat = chat.lock();
+        chat.prompt_window = Some(api::Window::current());
+        chat.send_prompt(&state).notify_warn()
     }
 }
 
@@ -365,3 +498,213 @@ fn rerun_tool(state: &SharedState) -> crate::Result<()> {
     }
     Ok(())
 }
+
+fn toggle_thinking(state: &SharedState) -> crate::Result<()> {
+    if let Some(chat) = Chat::from_current_buffer(&state) {
+        let mut chat = chat.lock();
+        chat.mut_chat(|chat| chat.metadata.keep_thinking = !chat.metadata.keep_thinking)?;
+        if chat.metadata.keep_thinking {
+            crate::notify::info("Reasoning traces are sent back to the model.");
+        } else {
+            crate::notify::info("Reasoning traces are kept out of the history.");
+        }
+    }
+    Ok(())
+}
+
+/// `:MistralChatImport {source} [target]` : Write the conversation in a `.chat` file, then open it.
+fn import(args: CommandArgs) -> crate::Result<()> {
+    let mut fargs = args.fargs.into_iter();
+    let Some(source) = fargs.next() else {
+        return Err("Usage : `:MistralChatImport {source} [target.chat]`.".into());
+    };
+    let source: String = api::call_function("fnamemodify", (source, ":p"))?;
+    let source = std::path::PathBuf::from(source);
+    let target = match fargs.next() {
+        Some(target) => std::path::PathBuf::from(api::call_function::<_, String>("fnamemodify", (target, ":p"))?),
+        None => source.with_extension("chat"),
+    };
+    if target.exists() {
+        return Err(format!("`{}` already exists.", target.display()).into());
+    }
+    let extension = source
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or_default();
+    let mut imported = Imported::parse(extension, &std::fs::read_to_string(&source)?)?;
+    if imported.metadata.name.is_empty() {
+        let name = source
+            .file_stem()
+            .and_then(|name| name.to_str())
+            .unwrap_or_default();
+        imported.metadata.name = name.to_string();
+    }
+    let nb_messages = imported.messages.len();
+    std::fs::write(&target, imported.into_chat())?;
+    // The `BufRead` autocmd loads the chat.
+    let target: String = api::call_function("fnameescape", (target.display().to_string(),))?;
+    api::command(&format!("edit {target}"))?;
+    crate::notify::info(format!("{nb_messages} messages imported."));
+    Ok(())
+}
+/// `:MistralChatFork` : The fork is written next to the chat (`review.fork1.chat`), then opened.
+fn fork(state: &SharedState) -> crate::Result<()> {
+    let Some(chat) = Chat::from_current_buffer(&state) else {
+        return Ok(());
+    };
+    let (content, target) = {
+        let chat = chat.lock();
+        let parent = chat
+            .path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+        let content = chat.fork(&api::Window::current(), parent)?;
+        let stem = chat.path.file_stem().unwrap_or_default().to_string_lossy();
+        let target = (1..)
+            .map(|n| chat.path.with_file_name(format!("{stem}.fork{n}.chat")))
+            .find(|path| !path.exists())
+            .unwrap();
+        (content, target)
+    };
+    std::fs::write(&target, content)?;
+    let target: String = api::call_function("fnameescape", (target.display().to_string(),))?;
+    api::command(&format!("edit {target}"))?;
+    Ok(())
+}
+/// `:MistralChatForks` : The chats of the directory whose `fork` attribute names the current one.
+fn list_forks(state: &SharedState) -> crate::Result<()> {
+    let Some(chat) = Chat::from_current_buffer(&state) else {
+        return Ok(());
+    };
+    let parent = chat.lock().path.clone();
+    let parent_name = parent
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string();
+    let dir = parent.parent().unwrap_or(std::path::Path::new("."));
+    let mut items = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_none_or(|ext| ext != "chat") {
+            continue;
+        }
+        let Some(metadata) = ChatMetadata::from_file(&path) else {
+            continue;
+        };
+        if metadata.fork.as_ref() != Some(&parent_name) {
+            continue;
+        }
+        let text = format!("{} : {}", metadata.name, metadata.description);
+        items.push(Object::from(Dictionary::from_iter([
+            ("filename", Object::from(path.display().to_string())),
+            ("lnum", Object::from(1i64)),
+            ("text", Object::from(text)),
+        ])));
+    }
+    if items.is_empty() {
+        crate::notify::info("No fork of this chat.");
+        return Ok(());
+    }
+    let what = Dictionary::from_iter([
+        ("title", Object::from(format!("Forks of {parent_name}"))),
+        ("items", Object::from(Array::from_iter(items))),
+    ]);
+    api::call_function::<_, i64>("setqflist", (Array::new(), " ", what))?;
+    api::command("copen")?;
+    Ok(())
+}
+/// `:MistralChatSearch [role:user] [model:large] [since:2026-01-01] [until:2026-12-31] text` : One quickfix entry
+/// per matching message, on its tag line. The dates filter the messages by their `date`, or by the last modification
+/// of the file for the messages written without it.
+fn search(args: CommandArgs) -> crate::Result<()> {
+    let args = args.args.unwrap_or_default();
+    let query = SearchQuery::parse(&args)?;
+    let root: String = api::call_function("getcwd", Array::new())?;
+    let mut items = Vec::new();
+    for chat in index::index(std::path::Path::new(&root)) {
+        // For the messages written without their date.
+        let file_date: Option<String> = match chat.modified_secs() {
+            Some(secs) if query.has_date() => Some(api::call_function("strftime", ("%Y-%m-%d", secs as i64))?),
+            _ => None,
+        };
+        for found in query.search(&chat, file_date.as_deref()) {
+            let message = &chat.messages[found.index];
+            let text = format!("{} · {} : {}", message.message.role, message.model, found.snippet);
+            items.push(Object::from(Dictionary::from_iter([
+                ("filename", Object::from(chat.path.display().to_string())),
+                ("lnum", Object::from(found.row.0 as i64 + 1)),
+                ("text", Object::from(text)),
+            ])));
+        }
+    }
+    if items.is_empty() {
+        crate::notify::info("No message found.");
+        return Ok(());
+    }
+    let what = Dictionary::from_iter([
+        ("title", Object::from(format!("MistralChatSearch {args}"))),
+        ("items", Object::from(Array::from_iter(items))),
+    ]);
+    api::call_function::<_, i64>("setqflist", (Array::new(), " ", what))?;
+    api::command("copen")?;
+    Ok(())
+}
+/// `:MistralChatRegenerate [model]` : The model is given by its API name.
+fn regenerate(state: &SharedState, model: Option<String>) -> crate::Result<()> {
+    let model = match model {
+        Some(name) => match Model::from_name(name.trim()) {
+            Some(model) => Some(model),
+            None => return Err(format!("Unknown model `{name}`.").into()),
+        },
+        None => None,
+    };
+    if let Some(chat) = Chat::from_current_buffer(&state) {
+        let envelop = chat.lock().regenerate(&api::Window::current(), model)?;
+        state.lock().tx_mistral.send(envelop).unwrap();
+    }
+    Ok(())
+}
+/// `:MistralChatRegenerate!` : The answer of the pending regeneration is dropped.
+fn stop_regeneration(state: &SharedState, buffer: &api::Buffer) -> crate::Result<()> {
+    let Some(chat) = state.lock().chats.get_by_buffer(buffer).cloned() else {
+        return Ok(());
+    };
+    let Some(abort) = chat.lock().stop_regeneration() else {
+        return Ok(());
+    };
+    state.lock().tx_mistral.send(abort).unwrap();
+    crate::notify::info("Regeneration stopped.");
+    Ok(())
+}
+fn cycle_version(state: &SharedState, inc: isize) -> crate::Result<()> {
+    if let Some(chat) = Chat::from_current_buffer(&state) {
+        chat.lock().cycle_version(&api::Window::current(), inc)?;
+    }
+    Ok(())
+}
+fn export(state: &SharedState, args: CommandArgs) -> crate::Result<()> {
+    let mut fargs = args.fargs.into_iter();
+    let format = fargs.next().unwrap_or_default().parse::<ExportFormat>()?;
+    let target = match fargs.next() {
+        Some(target) => Some(std::path::PathBuf::from(api::call_function::<_, String>(
+            "fnamemodify",
+            (target, ":p"),
+        )?)),
+        None => None,
+    };
+    if let Some(chat) = Chat::from_current_buffer(&state) {
+        let chat = chat.lock();
+        let path = target.unwrap_or_else(|| chat.path.with_extension(format.extension()));
+        // Next to `design.chat`, a `design.md` may be a document of the project.
+        if path.exists() {
+            return Err(format!("`{}` already exists, give another target.", path.display()).into());
+        }
+        let content = chat.export(format)?;
+        std::fs::write(&path, content)?;
+        crate::notify::info(format!("Chat exported to `{}`.", path.display()));
+    }
+    Ok(())
+}
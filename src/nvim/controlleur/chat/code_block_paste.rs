@@ -7,12 +7,16 @@ use std::{
 
 use nvim_oxi::api::{self, types::CommandArgs};
 
-use crate::nvim::model::{BufferData, Selection};
+use crate::nvim::model::{BufferData, Selection, config::FimBackend};
 
 pub(super) fn code_block_paste(args: CommandArgs) -> crate::Result<()> {
     let (data, _buffer) = BufferData::from_current_buffer()?;
     let selection = Selection::from_command_args(&args);
-    let visual = crate::messages::Visual { data, selection };
+    let visual = crate::messages::Visual {
+        data,
+        selection,
+        backend: FimBackend::read(),
+    };
     let content = visual.get_selected_content().0;
 
     if content.is_empty() {
@@ -0,0 +1,210 @@
+//! `:MistralListChat` : the chats of the project in a picker buffer, the most recently modified first.
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use nvim_oxi::{Array, api};
+
+use crate::{
+    notify::{IntoNotification as _, NotifyExtV2 as _},
+    nvim::model::{
+        self, Row,
+        state::chat::{ChatFile, index},
+    },
+    utils::set_option,
+};
+
+const HEADER: &'static str = "<CR> ouvrir · p aperçu · r renommer · d supprimer · q fermer";
+
+/// The picker buffer, its n-th entry is on the row n + 1.
+#[derive(Clone)]
+struct Picker {
+    buffer: api::Buffer,
+    root: PathBuf,
+    paths: Arc<Mutex<Vec<PathBuf>>>,
+    preview: Arc<Mutex<Option<api::Window>>>,
+}
+
+pub(super) fn list_chats() -> crate::Result<()> {
+    let root: String = api::call_function("getcwd", Array::new())?;
+    api::command("botright new")?;
+    let buffer = api::get_current_buf();
+    set_option(&buffer, "buftype", "nofile");
+    set_option(&buffer, "bufhidden", "wipe");
+    set_option(&buffer, "swapfile", false);
+    set_option(&buffer, "filetype", "mistralchats");
+    let picker = Picker {
+        buffer,
+        root: PathBuf::from(root),
+        paths: Default::default(),
+        preview: Default::default(),
+    };
+    picker.refresh()?;
+    picker.setup_keymaps();
+    Ok(())
+}
+
+/// `:MistralLoadChat {path}` : the `BufRead` autocmd loads the chat.
+pub(super) fn load_chat(path: &str) -> crate::Result<()> {
+    if !Path::new(path).exists() {
+        return Err(format!("`{path}` does not exist.").into_warn());
+    }
+    open(Path::new(path))
+}
+
+fn open(path: &Path) -> crate::Result<()> {
+    let path: String = api::call_function("fnameescape", (path.display().to_string(),))?;
+    api::command(&format!("edit {path}"))?;
+    Ok(())
+}
+
+/// A chat opened in a buffer is not modified behind its back.
+fn ensure_closed(path: &Path) -> crate::Result<()> {
+    let loaded: i64 = api::call_function("bufloaded", (path.display().to_string(),))?;
+    if loaded == 1 {
+        return Err(format!("`{}` is open, close it first.", path.display()).into_warn());
+    }
+    Ok(())
+}
+
+impl Picker {
+    fn setup_keymaps(&self) {
+        let mut modes = crate::utils::ShortcutBuilder::new(self.buffer.clone());
+        let picker = self;
+        use api::types::Mode::*;
+        crate::set_keymaps! {
+            modes (Normal) :
+            "<CR>" => {picker.open().notify()} <= <picker: Self>
+            "p" => {picker.preview().notify()} <= <picker: Self>
+            "r" => {picker.rename().notify()} <= <picker: Self>
+            "d" => {picker.delete().notify()} <= <picker: Self>
+            "q" => {picker.close().notify()} <= <picker: Self>
+        }
+    }
+    fn refresh(&self) -> crate::Result<()> {
+        let chats = index::index(&self.root);
+        let mut lines = vec![HEADER.to_string()];
+        for chat in &chats {
+            lines.push(self.entry_line(chat)?);
+        }
+        let mut buffer = self.buffer.clone();
+        set_option(&buffer, "modifiable", true);
+        buffer.set_lines(.., false, lines)?;
+        set_option(&buffer, "modifiable", false);
+        *self.paths.lock().unwrap() = chats.iter().map(|chat| chat.path.clone()).collect();
+        Ok(())
+    }
+    /// `name · last model · messages · tokens · date · path · description`
+    fn entry_line(&self, chat: &ChatFile) -> crate::Result<String> {
+        let ChatFile {
+            path,
+            metadata,
+            messages,
+            ..
+        } = chat;
+        let name = match metadata.name.as_str() {
+            "" => path
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string(),
+            name => name.to_string(),
+        };
+        let model = chat
+            .last_model()
+            .map(|model| model.to_string())
+            .unwrap_or_default();
+        let date: String = match chat.modified_secs() {
+            Some(secs) => api::call_function("strftime", ("%Y-%m-%d %H:%M", secs as i64))?,
+            None => String::new(),
+        };
+        let path = path.strip_prefix(&self.root).unwrap_or(path).display();
+        Ok(format!(
+            "{name:<24} {model:<16} {:>4} msg {:>8} tokens  {date:<16}  {path}  {}",
+            messages.len(),
+            metadata.usage.total_tokens,
+            metadata.description,
+        ))
+    }
+    /// Path of the entry under the cursor.
+    fn selected(&self) -> Option<PathBuf> {
+        let (row, _) = model::get_cursor(&api::get_current_win())?;
+        let index = row.0.checked_sub(1)?;
+        self.paths.lock().unwrap().get(index).cloned()
+    }
+    fn open(&self) -> crate::Result<()> {
+        let Some(path) = self.selected() else {
+            return Ok(());
+        };
+        self.close()?;
+        open(&path)
+    }
+    /// The file is shown as is in a scratch buffer, the chat is not loaded.
+    fn preview(&self) -> crate::Result<()> {
+        let Some(path) = self.selected() else {
+            return Ok(());
+        };
+        let content = std::fs::read_to_string(&path)?;
+        let picker_win = api::get_current_win();
+        let mut preview = self.preview.lock().unwrap();
+        let mut win = match preview.as_ref().filter(|win| win.is_valid()) {
+            Some(win) => win.clone(),
+            None => {
+                api::command("vertical rightbelow new")?;
+                let buffer = api::get_current_buf();
+                set_option(&buffer, "buftype", "nofile");
+                set_option(&buffer, "bufhidden", "wipe");
+                set_option(&buffer, "swapfile", false);
+                set_option(&buffer, "filetype", "markdown");
+                let win = api::get_current_win();
+                api::set_current_win(&picker_win)?;
+                win
+            }
+        };
+        if win == picker_win {
+            return Err("Can't open the preview.".into_error());
+        }
+        let mut buffer = win.get_buf()?;
+        buffer.set_lines(.., false, content.lines())?;
+        model::set_cursor(&mut win, Row(0), 0);
+        *preview = Some(win);
+        Ok(())
+    }
+    fn rename(&self) -> crate::Result<()> {
+        let Some(path) = self.selected() else {
+            return Ok(());
+        };
+        ensure_closed(&path)?;
+        let current = ChatFile::read(&path)?.metadata.name;
+        let name: String = api::call_function("input", ("Nom du chat : ", current))?;
+        let name = name.trim();
+        if name.is_empty() {
+            return Ok(());
+        }
+        ChatFile::rename(&path, name)?;
+        self.refresh()
+    }
+    fn delete(&self) -> crate::Result<()> {
+        let Some(path) = self.selected() else {
+            return Ok(());
+        };
+        ensure_closed(&path)?;
+        let question = format!("Supprimer `{}` ?", path.display());
+        let choice: i64 = api::call_function("confirm", (question, "&Oui\n&Non", 2))?;
+        if choice != 1 {
+            return Ok(());
+        }
+        std::fs::remove_file(&path)?;
+        self.refresh()
+    }
+    fn close(&self) -> crate::Result<()> {
+        if let Some(win) = self.preview.lock().unwrap().take()
+            && win.is_valid()
+        {
+            win.close(true)?;
+        }
+        api::command("close")?;
+        Ok(())
+    }
+}
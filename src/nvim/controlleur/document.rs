@@ -0,0 +1,102 @@
+use std::path::Path;
+
+use code_modifier::{LanguageExt as _, Range, langs::rust::Rust};
+
+use crate::{
+    messages::{Document, IdMessage, NvimEnveloppe, NvimMessage},
+    mistral::controlleur::fim::{
+        document::{MAX_CALL_SITES, call_site, relative},
+        post_process::PostProcess,
+    },
+    notify::IntoNotification as _,
+    nvim::model::{BufferData, Col, Cursor, Locker as _, ReplacedDocs, Row, SharedState, config::FimBackend},
+};
+
+/// Rows of a doc comment, a `///` comment ends at the start of the next line.
+fn rows(range: &Range) -> (usize, usize) {
+    let end = match range.end_point.column {
+        0 if range.end_point.row > range.start_point.row => range.end_point.row - 1,
+        _ => range.end_point.row,
+    };
+    (range.start_point.row, end)
+}
+
+/// Offset of the cursor in the lines of the buffer joined with `\n`.
+pub(super) fn cursor_byte(data: &BufferData) -> usize {
+    let row = (*data.cursor.row).min(data.content.len());
+    let byte: usize = data.content[..row]
+        .iter()
+        .map(|line| line.len() + 1)
+        .sum::<usize>()
+        + *data.cursor.col;
+    let len = data
+        .content
+        .iter()
+        .map(|line| line.len() + 1)
+        .sum::<usize>();
+    byte.min(len.saturating_sub(1))
+}
+
+/// `:MistralDocument` : Write (or rewrite) the doc comment of the Rust function, struct, enum or trait
+/// under the cursor, above its attributes. The code of the item is not sent back, the previous doc comment is
+/// deleted once the new one is written.
+pub fn document(state: &SharedState) -> crate::Result<()> {
+    let (data, buffer) = BufferData::from_current_buffer()?;
+    if data.filetype != "rust" {
+        return Err("`:MistralDocument` only supports Rust.".into_warn());
+    }
+    let code = data.content.join("\n");
+    let Some(parser) = Rust::new_parser(code.as_bytes()) else {
+        return Err("Can't parse with tree-sitter.".into_error());
+    };
+    let Some(item) = parser.documented_at(cursor_byte(&data)) else {
+        return Err("No function, struct, enum or trait under the cursor.".into_warn());
+    };
+
+    // The item with its current doc comment, from the start of the line.
+    let start = item
+        .docs
+        .first()
+        .map_or(item.item.start_byte, |doc| doc.start_byte.min(item.item.start_byte));
+    let start = code[..start].rfind('\n').map_or(0, |i| i + 1);
+    let text = code[start..item.item.end_byte].to_string();
+
+    let path = data.filepath.as_deref().map(relative);
+    // The ones of the other files are searched by the request.
+    let call_sites: Vec<String> = parser
+        .references(&item.name, Some(item.item))
+        .into_iter()
+        .take(MAX_CALL_SITES)
+        .map(|range| call_site(path.as_deref().unwrap_or(Path::new("")), &code, range))
+        .collect();
+
+    let indent: String = data.content[item.item.start_point.row]
+        .chars()
+        .take_while(|c| c.is_whitespace())
+        .collect();
+    let doc_rows: Vec<(usize, usize)> = item.docs.iter().map(rows).collect();
+    let id = {
+        let mut s = state.lock();
+        let id = s.add_fim(&buffer);
+        let cursor = Cursor {
+            row: Row(item.item.start_point.row),
+            col: Col(0),
+        };
+        s.start_processed_insertion(&buffer, id, cursor, PostProcess::doc_comment(&indent))?;
+        let docs = ReplacedDocs::new(&buffer, &doc_rows)?;
+        s.replaced_docs.insert((buffer.clone(), id), docs);
+        id
+    };
+    let envelop = NvimEnveloppe {
+        id: IdMessage::FIM(buffer.handle(), id),
+        message: NvimMessage::Document(Document {
+            item: text,
+            name: item.name,
+            path,
+            call_sites,
+            backend: FimBackend::read(),
+        }),
+    };
+    state.lock().tx_mistral.send(envelop).unwrap();
+    Ok(())
+}
@@ -0,0 +1,155 @@
+use nvim_oxi::api::{
+    self,
+    opts::{CreateCommandOpts, SetKeymapOpts},
+    types::{CommandArgs, CommandNArgs, CommandRange, Mode},
+};
+
+use crate::{
+    messages::{Edit, IdMessage, NvimEnveloppe, NvimMessage, Visual},
+    notify::{IntoNotification as _, NotifyExtV2 as _},
+    nvim::{
+        model::{BufferData, Col, Cursor, InlineEdit, Locker as _, Row, Selection, SharedState, config::FimBackend},
+        vue::edit::dismiss,
+    },
+};
+
+/// Ask the instruction in the command line, empty when cancelled.
+fn ask_instruction() -> crate::Result<String> {
+    let args = nvim_oxi::Array::from_iter(["Instruction : "]);
+    let instruction: String = api::call_function("input", args)?;
+    Ok(instruction.trim().to_string())
+}
+
+/// `:MistralEdit` : Rewrite the lines `first..=last` (one-indexed) as told by the instruction, the rewrite
+/// is shown as a diff over the buffer.
+pub fn edit(state: &SharedState, first: usize, last: usize, instruction: String) -> crate::Result<()> {
+    let instruction = match instruction.trim() {
+        "" => ask_instruction()?,
+        instruction => instruction.to_string(),
+    };
+    if instruction.is_empty() {
+        return Ok(());
+    }
+    let (data, buffer) = BufferData::from_current_buffer()?;
+    // Whole lines, the end is the row after the selection.
+    let selection = Selection {
+        start: Cursor {
+            row: Row(first.saturating_sub(1)),
+            col: Col(0),
+        },
+        end: Cursor {
+            row: Row(last.min(data.content.len())),
+            col: Col::MAX,
+        },
+    };
+    let visual = Visual {
+        data,
+        selection,
+        backend: FimBackend::read(),
+    };
+    let (selected, _) = visual.get_selected_content();
+    let original = selected.split('\n').map(String::from).collect();
+    let id = {
+        let mut s = state.lock();
+        dismiss(&buffer, &mut s)?;
+        let id = s.next_edit_id();
+        let edit = InlineEdit::new(buffer.clone(), id, *visual.selection.start.row, original)?;
+        s.edits.insert(buffer.clone(), edit);
+        id
+    };
+    let envelop = NvimEnveloppe {
+        id: IdMessage::Edit(buffer.handle(), id),
+        message: NvimMessage::Edit(Edit { visual, instruction }),
+    };
+    state.lock().tx_mistral.send(envelop).unwrap();
+    Ok(())
+}
+
+/// Accept (or reject) the hunk under the cursor, or every hunk.
+pub fn resolve(state: &SharedState, accept: bool, all: bool) -> crate::Result<()> {
+    let buffer = api::Buffer::current();
+    let mut s = state.lock();
+    let Some(edit) = s.edits.get_mut(&buffer) else {
+        return Err("No edit in this buffer.".into_warn());
+    };
+    if !edit.is_finished() {
+        if !accept && all {
+            return dismiss(&buffer, &mut s);
+        }
+        return Err("The edit is still streaming.".into_warn());
+    }
+    let row = Cursor::from_window_current().map_or(0, |cursor| *cursor.row);
+    let indexes = match all {
+        // Each hunk is removed from the diff once resolved.
+        true => vec![0; edit.hunks().len()],
+        false => edit.hunk_at(row)?.into_iter().collect(),
+    };
+    for index in indexes {
+        if accept {
+            if let Err(err) = edit.accept(index) {
+                dismiss(&buffer, &mut s)?;
+                return Err(err);
+            }
+        } else {
+            edit.reject(index);
+        }
+    }
+    if edit.hunks().is_empty() {
+        dismiss(&buffer, &mut s)
+    } else {
+        edit.render()
+    }
+}
+
+pub fn setup(s: &SharedState) -> crate::Result<()> {
+    use api::create_user_command as cmd;
+
+    let d = "Réécrit les lignes sélectionnées selon une instruction, le résultat s'affiche en diff.";
+    let state = SharedState::clone(s);
+    let opts = CreateCommandOpts::builder()
+        .desc(d)
+        .range(CommandRange::CurrentLine)
+        .nargs(CommandNArgs::Any)
+        .build();
+    let edit_args = move |args: CommandArgs| {
+        let instruction = args.args.unwrap_or_default();
+        edit(&state, args.line1, args.line2, instruction).notify()
+    };
+    cmd("MistralEdit", edit_args, &opts)?;
+    // `:` on a selection gives its range to the command, the instruction is asked.
+    let opts = SetKeymapOpts::builder().desc(d).noremap(true).build();
+    api::set_keymap(Mode::Visual, "<Leader>me", ":MistralEdit<CR>", &opts)?;
+
+    let d = "Accepte le bloc du diff sous le curseur (tous avec `!`).";
+    let state = SharedState::clone(s);
+    let opts = CreateCommandOpts::builder().desc(d).bang(true).build();
+    cmd(
+        "MistralEditAccept",
+        move |args: CommandArgs| resolve(&state, true, args.bang).notify(),
+        &opts,
+    )?;
+    let state = SharedState::clone(s);
+    let opts = SetKeymapOpts::builder()
+        .desc(d)
+        .noremap(true)
+        .callback(move |_| resolve(&state, true, false).notify())
+        .build();
+    api::set_keymap(Mode::Normal, "<Leader>mea", "", &opts)?;
+
+    let d = "Rejette le bloc du diff sous le curseur (tous avec `!`).";
+    let state = SharedState::clone(s);
+    let opts = CreateCommandOpts::builder().desc(d).bang(true).build();
+    cmd(
+        "MistralEditReject",
+        move |args: CommandArgs| resolve(&state, false, args.bang).notify(),
+        &opts,
+    )?;
+    let state = SharedState::clone(s);
+    let opts = SetKeymapOpts::builder()
+        .desc(d)
+        .noremap(true)
+        .callback(move |_| resolve(&state, false, false).notify())
+        .build();
+    api::set_keymap(Mode::Normal, "<Leader>mer", "", &opts)?;
+    Ok(())
+}
@@ -20,7 +20,7 @@ pub fn new_buffer(state: &SharedState) -> crate::Result<(api::Buffer, usize, Buf
 #[macro_export]
 macro_rules! n {
     ($message:expr) => {
-        |state: SharedState| {
+        |state: SharedState, fresh: bool| {
             let (buffer, id, data) = match crate::nvim::controlleur::fim::new_buffer(&state) {
                 Ok(r) => r,
                 Err(err) => {
@@ -28,11 +28,17 @@ macro_rules! n {
                     return;
                 }
             };
+            let backend = crate::nvim::model::config::FimBackend::read().fresh(fresh);
+            let mut s = state.lock();
+            s.fim_backends.insert((buffer.clone(), id), backend.clone());
             let envelop = crate::messages::NvimEnveloppe {
                 id: crate::messages::IdMessage::FIM(buffer.handle(), id),
-                message: $message(crate::messages::Normal { data }),
+                message: $message(crate::messages::Normal {
+                    data,
+                    backend,
+                }),
             };
-            state.lock().tx_mistral.send(envelop).unwrap();
+            s.tx_mistral.send(envelop).unwrap();
         }
     };
 }
@@ -40,7 +46,7 @@ macro_rules! n {
 #[macro_export]
 macro_rules! v {
     ($message:expr) => {
-        |state: SharedState, selection: crate::nvim::model::Selection| {
+        |state: SharedState, selection: crate::nvim::model::Selection, fresh: bool| {
             crate::notify::info(format!("Mistral v! : Selection : {selection:?}"));
             let (buffer, id, data) = match crate::nvim::controlleur::fim::new_buffer(&state) {
                 Ok(r) => r,
@@ -49,18 +55,25 @@ macro_rules! v {
                     return;
                 }
             };
+            let backend = crate::nvim::model::config::FimBackend::read().fresh(fresh);
+            let mut s = state.lock();
+            s.fim_backends.insert((buffer.clone(), id), backend.clone());
             let envelop = crate::messages::NvimEnveloppe {
                 id: crate::messages::IdMessage::FIM(buffer.handle(), id),
-                message: $message(crate::messages::Visual { data, selection }),
+                message: $message(crate::messages::Visual {
+                    data,
+                    selection,
+                    backend,
+                }),
             };
-            state.lock().tx_mistral.send(envelop).unwrap();
+            s.tx_mistral.send(envelop).unwrap();
         }
     };
 }
 
 pub fn vmap<F>(state: &SharedState, f: F, short_cut: &str, opts: &mut opts::SetKeymapOptsBuilder) -> oxi::Result<()>
 where
-    F: Fn(SharedState, Selection) + 'static,
+    F: Fn(SharedState, Selection, bool) + 'static,
 {
     let state_clone = SharedState::clone(state);
     api::set_keymap(
@@ -76,29 +89,36 @@ where
                         return;
                     }
                 };
-                f(SharedState::clone(&state_clone), selection)
+                f(SharedState::clone(&state_clone), selection, false)
             })
             .build(),
     )?;
     Ok(())
 }
 
+/// With a bang, the completion is requested again even when it is cached.
 pub fn vcmd<F>(state: &SharedState, f: F, command: &str, opts: &mut opts::CreateCommandOptsBuilder) -> oxi::Result<()>
 where
-    F: Fn(SharedState, Selection) + 'static,
+    F: Fn(SharedState, Selection, bool) + 'static,
 {
     let state_clone = SharedState::clone(state);
     api::create_user_command(
         command,
-        move |args: types::CommandArgs| f(SharedState::clone(&state_clone), Selection::from_command_args(&args)),
-        &opts.range(types::CommandRange::CurrentLine).build(),
+        move |args: types::CommandArgs| {
+            let selection = Selection::from_command_args(&args);
+            f(SharedState::clone(&state_clone), selection, args.bang)
+        },
+        &opts
+            .range(types::CommandRange::CurrentLine)
+            .bang(true)
+            .build(),
     )?;
     Ok(())
 }
 
 pub fn nmap<F>(state: &SharedState, f: F, short_cut: &str, opts: &mut opts::SetKeymapOptsBuilder) -> oxi::Result<()>
 where
-    F: Fn(SharedState) + 'static,
+    F: Fn(SharedState, bool) + 'static,
 {
     let state_clone = SharedState::clone(state);
     api::set_keymap(
@@ -106,21 +126,25 @@ where
         short_cut,
         "",
         &opts
-            .callback(move |_| f(SharedState::clone(&state_clone)))
+            .callback(move |_| f(SharedState::clone(&state_clone), false))
             .build(),
     )?;
     Ok(())
 }
 
+/// With a bang, the completion is requested again even when it is cached.
 pub fn ncmd<F>(state: &SharedState, f: F, command: &str, opts: &mut opts::CreateCommandOptsBuilder) -> oxi::Result<()>
 where
-    F: Fn(SharedState) + 'static,
+    F: Fn(SharedState, bool) + 'static,
 {
     let state_clone = SharedState::clone(state);
     api::create_user_command(
         command,
-        move |_| f(SharedState::clone(&state_clone)),
-        &opts.range(types::CommandRange::CurrentLine).build(),
+        move |args: types::CommandArgs| f(SharedState::clone(&state_clone), args.bang),
+        &opts
+            .range(types::CommandRange::CurrentLine)
+            .bang(true)
+            .build(),
     )?;
     Ok(())
 }
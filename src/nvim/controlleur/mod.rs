@@ -7,10 +7,17 @@ use crate::{
     v,
 };
 
+mod auto_fim;
 pub mod chat;
+mod document;
+mod edit;
 mod fim;
 mod form;
 mod latex;
+mod ocr;
+mod suggestion;
+mod todos;
+mod unit_tests;
 
 // pub fn setup(sender: mpsc::UnboundedSender<NvimEnveloppe>, state: SharedState) -> crate::Result<()> {
 pub fn setup(s: &SharedState) -> crate::Result<()> {
@@ -30,10 +37,71 @@ pub fn setup(s: &SharedState) -> crate::Result<()> {
     nmap(s, n!(FimFunction), "<Leader>mff", k_opts().desc(d).noremap(true))?;
     ncmd(s, n!(FimFunction), "MistralFIMFunction", c_opts().desc(d))?;
 
+    let d = "Applique FIM sur l'instruction sous le curseur (let, bras de match...).";
+    nmap(s, n!(FimStatement), "<Leader>mfs", k_opts().desc(d).noremap(true))?;
+    ncmd(s, n!(FimStatement), "MistralFIMStatement", c_opts().desc(d))?;
+
+    let d = "Applique FIM sur la structure ou l'enum sous le curseur.";
+    nmap(s, n!(FimStructure), "<Leader>mft", k_opts().desc(d).noremap(true))?;
+    ncmd(s, n!(FimStructure), "MistralFIMStructure", c_opts().desc(d))?;
+
+    let d = "Applique FIM sur le bloc impl (ou trait, classe) sous le curseur.";
+    nmap(s, n!(FimImpl), "<Leader>mfi", k_opts().desc(d).noremap(true))?;
+    ncmd(s, n!(FimImpl), "MistralFIMImpl", c_opts().desc(d))?;
+
     let d = "Applique FIM en utilisant l'intégralité du fichier avec la position du curseur.";
     nmap(s, n!(FimCursorLine), "<Leader>mfc", k_opts().desc(d).noremap(true))?;
     ncmd(s, n!(FimCursorLine), "MistralFIMCursor", c_opts().desc(d))?;
 
+    {
+        use crate::notify::NotifyExtV2 as _;
+        let d = "Remplit les `todo!()` du buffer (du projet avec `!`) par FIM, à revoir dans la liste quickfix.";
+        let state = SharedState::clone(s);
+        let opts = c_opts().desc(d).bang(true).build();
+        let fill = move |args: nvim_oxi::api::types::CommandArgs| todos::fill_todos(&state, args).notify();
+        nvim_oxi::api::create_user_command("MistralFIMTodos", fill, &opts)?;
+    }
+
+    {
+        use crate::notify::NotifyExtV2 as _;
+        let d = "Écrit (ou réécrit) le commentaire de documentation de l'élément Rust sous le curseur.";
+        let state = SharedState::clone(s);
+        let opts = c_opts().desc(d).build();
+        let document = move |_| document::document(&state).notify();
+        nvim_oxi::api::create_user_command("MistralDocument", document, &opts)?;
+    }
+
+    {
+        use crate::notify::NotifyExtV2 as _;
+        let d = "Génère les tests de la fonction Rust sous le curseur, ajoutés au `mod tests` puis vérifiés par `cargo test`.";
+        let state = SharedState::clone(s);
+        let opts = c_opts().desc(d).build();
+        let generate = move |_| unit_tests::generate_tests(&state).notify();
+        nvim_oxi::api::create_user_command("MistralGenerateTests", generate, &opts)?;
+    }
+
+    // Inline edit
+    edit::setup(s)?;
+
+    // Ghost text
+    suggestion::setup(s)?;
+    auto_fim::setup(s)?;
+
+    // OCR
+    {
+        use nvim_oxi::api::types::{CommandComplete, CommandNArgs};
+
+        use crate::notify::NotifyExtV2 as _;
+        let d = "Insère le Markdown d'un PDF ou d'une image (OCR) sous le curseur : `:MistralOcr spec.pdf 1-5`.";
+        let state = SharedState::clone(s);
+        let opts = c_opts()
+            .desc(d)
+            .nargs(CommandNArgs::OneOrMore)
+            .complete(CommandComplete::File)
+            .build();
+        nvim_oxi::api::create_user_command("MistralOcr", move |args| ocr::ocr(&state, args).notify(), &opts)?;
+    }
+
     chat::setup_commands(s)?;
 
     #[cfg(not(feature = "no_logs"))]
@@ -0,0 +1,32 @@
+use nvim_oxi::api::{self, types::CommandArgs};
+
+use crate::{
+    messages::{IdMessage, NvimEnveloppe, NvimMessage, Ocr},
+    mistral::model::ocr::OcrSource,
+    nvim::model::{Col, Cursor, Locker as _, SharedState},
+};
+
+/// `:MistralOcr {path} [pages]` : Insert the Markdown of the document under the cursor's line.
+pub fn ocr(state: &SharedState, args: CommandArgs) -> crate::Result<()> {
+    let mut fargs = args.fargs.into_iter();
+    let Some(path) = fargs.next() else {
+        return Err("Usage : `:MistralOcr {path} [pages]`.".into());
+    };
+    let path: String = api::call_function("fnamemodify", (path, ":p"))?;
+    let pages = fargs.next();
+    let source = OcrSource::new(path, pages.as_deref())?;
+    let (buffer, id, data) = super::fim::new_buffer(state)?;
+    let cursor = Cursor {
+        row: data.cursor.row,
+        col: Col::MAX,
+    };
+    let envelop = NvimEnveloppe {
+        id: IdMessage::Ocr(buffer.handle(), id),
+        message: NvimMessage::Ocr(Ocr {
+            source,
+            cursor: Some(cursor),
+        }),
+    };
+    state.lock().tx_mistral.send(envelop).unwrap();
+    Ok(())
+}
@@ -0,0 +1,224 @@
+use std::sync::LazyLock;
+
+use nvim_oxi::api::{
+    self,
+    opts::{CreateAutocmdOpts, CreateCommandOpts, SetKeymapOpts},
+    types::{CommandArgs, CommandNArgs, Mode},
+};
+
+use crate::{
+    messages::{IdMessage, NvimEnveloppe, NvimMessage, Suggest},
+    notify::NotifyExtV2 as _,
+    nvim::{
+        model::{
+            BufferData, Cursor, Locker as _, SharedState, Suggestion,
+            config::{FimBackend, SuggestionKeys},
+            state::{post_process, suggestion::Accept},
+        },
+        vue::suggestion::dismiss,
+    },
+};
+
+static GROUP: LazyLock<u32> =
+    LazyLock::new(|| api::create_augroup("MistralSuggestion", &Default::default()).unwrap_or(0));
+
+/// `backend` is the one of the post process of the candidate.
+fn send_request(
+    state: &SharedState,
+    data: BufferData,
+    id: usize,
+    candidate: usize,
+    backend: FimBackend,
+) -> crate::Result<()> {
+    let buffer = api::Buffer::current();
+    let envelop = NvimEnveloppe {
+        id: IdMessage::Suggestion(buffer.handle(), id),
+        message: NvimMessage::FimSuggestion(Suggest {
+            data,
+            backend,
+            candidate,
+        }),
+    };
+    state.lock().tx_mistral.send(envelop).unwrap();
+    Ok(())
+}
+
+/// Ask a completion for the cursor's position, shown as ghost text.
+pub fn suggest(state: &SharedState) -> crate::Result<()> {
+    let (data, buffer) = BufferData::from_current_buffer()?;
+    let backend = FimBackend::read();
+    let id = {
+        let mut s = state.lock();
+        dismiss(&buffer, &mut s)?;
+        let id = s.next_suggestion_id();
+        let output = post_process(&buffer, &data.cursor, &backend)?;
+        let suggestion = Suggestion::new(buffer.clone(), data.cursor.clone(), id, output);
+        s.suggestions.insert(buffer, suggestion);
+        id
+    };
+    send_request(state, data, id, 0, backend)
+}
+
+pub fn accept(state: &SharedState, accept: Accept) -> crate::Result<()> {
+    let buffer = api::Buffer::current();
+    let mut s = state.lock();
+    let Some(suggestion) = s.suggestions.get_mut(&buffer) else {
+        return Ok(());
+    };
+    let running = suggestion.running_ids();
+    let done = suggestion.accept(accept, &mut api::Window::current())?;
+    let kept = suggestion.running_ids();
+    for id in running.into_iter().filter(|id| !kept.contains(id)) {
+        let envelop = NvimEnveloppe {
+            id: IdMessage::Suggestion(buffer.handle(), id),
+            message: NvimMessage::Abort,
+        };
+        s.tx_mistral.send(envelop).unwrap();
+    }
+    if done && kept.is_empty() {
+        s.suggestions.remove(&buffer);
+    }
+    Ok(())
+}
+
+pub fn cycle(state: &SharedState, forward: bool) -> crate::Result<()> {
+    let buffer = api::Buffer::current();
+    let mut s = state.lock();
+    let Some(suggestion) = s.suggestions.get_mut(&buffer) else {
+        return Ok(());
+    };
+    if suggestion.cycle(forward) {
+        return suggestion.render();
+    }
+    let id = s.next_suggestion_id();
+    let Some(suggestion) = s.suggestions.get_mut(&buffer) else {
+        return Ok(());
+    };
+    let candidate = suggestion.candidates.len();
+    let anchor = suggestion.anchor.clone();
+    let backend = FimBackend::read();
+    suggestion.push_candidate(id, post_process(&buffer, &anchor, &backend)?);
+    suggestion.render()?;
+    drop(s);
+    let (mut data, _) = BufferData::from_current_buffer()?;
+    // The user may have accepted a part since the first request.
+    data.cursor = anchor;
+    send_request(state, data, id, candidate, backend)
+}
+
+pub fn dismiss_current(state: &SharedState) -> crate::Result<()> {
+    let buffer = api::Buffer::current();
+    dismiss(&buffer, &mut state.lock())
+}
+
+/// Any cursor move outside of the anchor (typing, leaving the line) dismisses the ghost text.
+fn cursor_moved(state: &SharedState, buffer: &api::Buffer) -> crate::Result<()> {
+    let mut s = state.lock();
+    let Some(suggestion) = s.suggestions.get(buffer) else {
+        return Ok(());
+    };
+    if Cursor::from_window_current().as_ref() != Some(&suggestion.anchor) {
+        dismiss(buffer, &mut s)?;
+    }
+    Ok(())
+}
+
+/// An empty key is not mapped, nor a key the user already mapped in insert mode.
+fn imap<F>(state: &SharedState, keys: &str, desc: &str, f: F) -> crate::Result<()>
+where
+    F: Fn(&SharedState) -> crate::Result<()> + 'static,
+{
+    if keys.is_empty() {
+        return Ok(());
+    }
+    let mapped: String = api::call_function("maparg", (keys, "i"))?;
+    if !mapped.is_empty() {
+        crate::log_libuv!(Debug, "`{keys}` is already mapped in insert mode, kept as is.");
+        return Ok(());
+    }
+    let state = SharedState::clone(state);
+    let opts = SetKeymapOpts::builder()
+        .desc(desc)
+        .noremap(true)
+        .callback(move |_| f(&state).notify())
+        .build();
+    api::set_keymap(Mode::Insert, keys, "", &opts)?;
+    Ok(())
+}
+
+pub fn setup(s: &SharedState) -> crate::Result<()> {
+    use api::create_user_command as cmd;
+
+    let d = "Affiche une suggestion FIM en texte fantôme à la position du curseur.";
+    let state = SharedState::clone(s);
+    let opts = CreateCommandOpts::builder().desc(d).build();
+    cmd("MistralFIMSuggest", move |_| suggest(&state).notify(), &opts)?;
+
+    let d = "Accepte la suggestion : `all` (défaut), `word` ou `line`.";
+    let state = SharedState::clone(s);
+    let opts = CreateCommandOpts::builder()
+        .desc(d)
+        .nargs(CommandNArgs::ZeroOrOne)
+        .build();
+    let accept_args = move |args: CommandArgs| -> crate::Result<()> {
+        let kind = args.args.unwrap_or_default().parse::<Accept>()?;
+        accept(&state, kind)
+    };
+    cmd("MistralSuggestionAccept", move |args| accept_args(args).notify(), &opts)?;
+
+    let opts = CreateCommandOpts::builder().build();
+    let state = SharedState::clone(s);
+    cmd("MistralSuggestionNext", move |_| cycle(&state, true).notify(), &opts)?;
+    let state = SharedState::clone(s);
+    cmd("MistralSuggestionPrev", move |_| cycle(&state, false).notify(), &opts)?;
+    let state = SharedState::clone(s);
+    cmd(
+        "MistralSuggestionDismiss",
+        move |_| dismiss_current(&state).notify(),
+        &opts,
+    )?;
+
+    if let Some(keys) = SuggestionKeys::read() {
+        imap(s, &keys.suggest, "Suggestion FIM.", suggest)?;
+        imap(s, &keys.accept, "Accepte la suggestion.", |s| accept(s, Accept::All))?;
+        imap(s, &keys.accept_word, "Accepte le mot suivant.", |s| {
+            accept(s, Accept::Word)
+        })?;
+        imap(s, &keys.accept_line, "Accepte la ligne suivante.", |s| {
+            accept(s, Accept::Line)
+        })?;
+        imap(s, &keys.next, "Suggestion suivante.", |s| cycle(s, true))?;
+        imap(s, &keys.prev, "Suggestion précédente.", |s| cycle(s, false))?;
+        imap(s, &keys.dismiss, "Rejette la suggestion.", dismiss_current)?;
+    }
+    let d = "Affiche une suggestion FIM en texte fantôme à la position du curseur.";
+    let state = SharedState::clone(s);
+    let opts = SetKeymapOpts::builder()
+        .desc(d)
+        .noremap(true)
+        .callback(move |_| suggest(&state).notify())
+        .build();
+    api::set_keymap(Mode::Normal, "<Leader>mfg", "", &opts)?;
+
+    let state = SharedState::clone(s);
+    let opts = CreateAutocmdOpts::builder()
+        .group(*GROUP)
+        .desc("Dismiss the suggestion when the cursor leaves it.")
+        .callback(move |args: api::types::AutocmdCallbackArgs| -> bool {
+            cursor_moved(&state, &args.buffer).notify();
+            false
+        })
+        .build();
+    api::create_autocmd(["CursorMoved", "CursorMovedI"], &opts)?;
+    let state = SharedState::clone(s);
+    let opts = CreateAutocmdOpts::builder()
+        .group(*GROUP)
+        .desc("Dismiss the suggestion.")
+        .callback(move |args: api::types::AutocmdCallbackArgs| -> bool {
+            dismiss(&args.buffer, &mut state.lock()).notify();
+            false
+        })
+        .build();
+    api::create_autocmd(["InsertLeave", "BufLeave"], &opts)?;
+    Ok(())
+}
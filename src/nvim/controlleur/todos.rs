@@ -0,0 +1,261 @@
+use std::path::PathBuf;
+
+use code_modifier::{
+    LanguageExt as _,
+    langs::rust::{Rust, context::Todo},
+};
+use nvim_oxi::{
+    Array, Dictionary, Object,
+    api::{self, types::CommandArgs},
+};
+
+use crate::{
+    messages::{IdMessage, NvimEnveloppe, NvimMessage, Placeholder},
+    notify::IntoNotification as _,
+    nvim::model::{self, BufferData, Col, Cursor, Locker as _, Row, SharedState, config::FimBackend},
+};
+
+/// A placeholder removed from the buffer, the completion is inserted at `cursor`.
+#[derive(Debug, PartialEq)]
+struct Target {
+    cursor: Cursor,
+    prefix: String,
+    suffix: String,
+}
+
+/// Positions in the content without the placeholders, the context is the enclosing function.
+fn targets(content: &str, todos: &[Todo]) -> Vec<Target> {
+    let mut kept = String::with_capacity(content.len());
+    let mut last = 0;
+    for todo in todos {
+        kept.push_str(&content[last..todo.range.start_byte]);
+        last = todo.range.end_byte;
+    }
+    kept.push_str(&content[last..]);
+    let shift = |offset: usize| {
+        let removed: usize = todos
+            .iter()
+            .filter(|todo| todo.range.end_byte <= offset)
+            .map(|todo| todo.range.end_byte - todo.range.start_byte)
+            .sum();
+        offset - removed
+    };
+    todos
+        .iter()
+        .map(|todo| {
+            let offset = shift(todo.range.start_byte);
+            let (start, end) = todo
+                .function
+                .map_or((0, kept.len()), |f| (shift(f.start_byte), shift(f.end_byte)));
+            let line_start = kept[..offset].rfind('\n').map_or(0, |i| i + 1);
+            Target {
+                cursor: Cursor {
+                    row: Row(kept[..offset].matches('\n').count()),
+                    col: Col(offset - line_start),
+                },
+                prefix: kept[start..offset].to_string(),
+                suffix: kept[offset..end].to_string(),
+            }
+        })
+        .collect()
+}
+
+/// Remove the placeholders of the buffer and start a FIM for each one, returns the quickfix items. A placeholder
+/// is put back if its completion fails.
+fn fill_buffer(state: &SharedState, mut buffer: api::Buffer, data: BufferData) -> crate::Result<Vec<Object>> {
+    let content = data.content.join("\n");
+    let Some(mut parser) = Rust::new_parser(content.as_bytes()) else {
+        return Err("Can't parse with tree-sitter.".into_error());
+    };
+    let todos = parser.list_todos();
+    let targets = targets(&content, &todos);
+    let backend = FimBackend::read();
+    // From the end, so the positions of the previous ones stay valid.
+    for todo in todos.iter().rev() {
+        let start: Cursor = todo.range.start_point.into();
+        let end: Cursor = todo.range.end_point.into();
+        let (rows, cols) = Cursor::join_set_text(&start, &end);
+        model::set_text(&mut buffer, rows, cols, [""])?;
+    }
+
+    let mut items = Vec::with_capacity(targets.len());
+    for (todo, target) in todos.iter().zip(targets) {
+        let text = &content[todo.range.start_byte..todo.range.end_byte];
+        let id = {
+            let mut s = state.lock();
+            let id = s.add_fim(&buffer);
+            s.start_fim_insertion(&buffer, id, target.cursor.clone(), &backend)?;
+            s.placeholders.insert((buffer.clone(), id), text.to_string());
+            id
+        };
+        items.push(Object::from(Dictionary::from_iter([
+            ("bufnr", Object::from(buffer.handle())),
+            ("lnum", Object::from(*target.cursor.row as i64 + 1)),
+            ("col", Object::from(*target.cursor.col as i64 + 1)),
+            ("text", Object::from(format!("Mistral : {text}"))),
+        ])));
+        let placeholder = Placeholder {
+            data: BufferData {
+                cursor: target.cursor,
+                ..data.clone()
+            },
+            prefix: target.prefix,
+            suffix: target.suffix,
+            backend: backend.clone(),
+        };
+        let envelop = NvimEnveloppe {
+            id: IdMessage::FIM(buffer.handle(), id),
+            message: NvimMessage::FimPlaceholder(placeholder),
+        };
+        state.lock().tx_mistral.send(envelop).unwrap();
+    }
+    Ok(items)
+}
+
+/// Rust files of the git index which contain a placeholder, loaded in a buffer. The repository is searched
+/// from the working directory up, the paths of the index are relative to its work tree.
+fn project_buffers() -> crate::Result<Vec<(api::Buffer, BufferData)>> {
+    let repo = gix::discover(std::env::current_dir()?).map_err(|e| e.to_string().into_warn())?;
+    let Some(work_dir) = repo.workdir().map(PathBuf::from) else {
+        return Err("The repository has no work tree.".into_warn());
+    };
+    let index = repo.index().map_err(|e| e.to_string().into_warn())?;
+    let mut buffers = Vec::new();
+    for entry in index.entries() {
+        let path = work_dir.join(entry.path(&index).to_string());
+        if path.extension().is_none_or(|ext| ext != "rs") {
+            continue;
+        }
+        let Ok(code) = std::fs::read_to_string(&path) else {
+            continue;
+        };
+        if !code.contains("todo!") && !code.contains("unimplemented!") {
+            continue;
+        }
+        let handle: i32 = api::call_function("bufadd", (path.to_string_lossy().to_string(),))?;
+        api::call_function::<_, ()>("bufload", (handle,))?;
+        let buffer = api::Buffer::from(handle);
+        let content = buffer
+            .get_lines(0.., false)?
+            .map(|line| line.to_string())
+            .collect();
+        let data = BufferData {
+            filepath: Some(path),
+            filetype: "rust".to_string(),
+            content,
+            cursor: Cursor::zero(),
+            modified: false,
+            readonly: false,
+        };
+        buffers.push((buffer, data));
+    }
+    Ok(buffers)
+}
+
+/// `:MistralFIMTodos[!]` : Fill the `todo!()` of the buffer (of the project with `!`), reviewed in the quickfix list.
+pub fn fill_todos(state: &SharedState, args: CommandArgs) -> crate::Result<()> {
+    let buffers = if args.bang {
+        project_buffers()?
+    } else {
+        let (data, buffer) = BufferData::from_current_buffer()?;
+        if data.filetype != "rust" {
+            return Err("`:MistralFIMTodos` only supports Rust.".into_warn());
+        }
+        vec![(buffer, data)]
+    };
+    let mut items = Vec::new();
+    for (buffer, data) in buffers {
+        items.extend(fill_buffer(state, buffer, data)?);
+    }
+    if items.is_empty() {
+        crate::notify::info("No `todo!()` found.");
+        return Ok(());
+    }
+    crate::notify::info(format!("Filling {} placeholder(s).", items.len()));
+    let what = Dictionary::from_iter([
+        ("title", Object::from("Mistral todos")),
+        ("items", Object::from(Array::from_iter(items))),
+    ]);
+    api::call_function::<_, i64>("setqflist", (Array::new(), " ", what))?;
+    api::command("copen")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn targets_without_placeholders() {
+        let content = "fn f() {\n    let a = todo!();\n    todo!()\n}";
+        let mut parser = Rust::new_parser(content.as_bytes()).unwrap();
+        let todos = parser.list_todos();
+        let targets = targets(content, &todos);
+        assert_eq!(
+            targets[0],
+            Target {
+                cursor: Cursor {
+                    row: Row(1),
+                    col: Col(12),
+                },
+                prefix: "fn f() {\n    let a = ".to_string(),
+                suffix: ";\n    \n}".to_string(),
+            }
+        );
+        assert_eq!(
+            targets[1].cursor,
+            Cursor {
+                row: Row(2),
+                col: Col(4)
+            }
+        );
+        assert_eq!(targets[1].prefix, "fn f() {\n    let a = ;\n    ");
+    }
+}
+
+#[cfg(not(feature = "prod_mode"))]
+#[nvim_oxi::test]
+#[track_caller]
+fn fill_many_placeholders() -> crate::Result<()> {
+    use tokio::sync::mpsc;
+
+    use crate::{
+        messages::MistralMessage,
+        mistral::model::stream::{ErrorMessageType, Status, StreamResponse},
+        nvim::model::State,
+    };
+
+    let (mistral_tx, _mistral_rx) = mpsc::unbounded_channel();
+    let state = &State::new(mistral_tx);
+    let buffer = api::Buffer::current();
+    let content: Vec<String> = (0..70).map(|i| format!("fn f{i}() -> u32 {{ todo!() }}")).collect();
+    let data = BufferData {
+        filepath: None,
+        filetype: "rust".to_string(),
+        content: content.clone(),
+        cursor: Cursor::zero(),
+        modified: false,
+        readonly: false,
+    };
+    buffer.clone().set_lines(.., false, content)?;
+    // In tests, we must force the activation of the undotree
+    api::exec2("undo", &Default::default())?;
+    api::exec2("redo", &Default::default())?;
+
+    let items = fill_buffer(state, buffer.clone(), data)?;
+    assert_eq!(items.len(), 70);
+    // One running FIM per placeholder, the buffer is cleared once all of them are done.
+    assert_eq!(state.lock().fim.get(&buffer), Some(&70));
+    // A failed completion puts its placeholder back.
+    let mut failed = StreamResponse::new();
+    failed.status = Status::Failed("Timeout".to_string(), ErrorMessageType::default());
+    let finalize = MistralMessage::FinalizeTask(failed);
+    crate::nvim::vue::fim::handle_nvim_message(buffer.handle(), 1, finalize, state)?;
+    let first = buffer.get_lines(0..1, false)?.next().unwrap().to_string();
+    assert_eq!(first, "fn f0() -> u32 { todo!() }");
+    for _ in 1..70 {
+        state.lock().remove_fim(&buffer);
+    }
+    assert!(state.lock().fim.get(&buffer).is_none());
+    Ok(())
+}
@@ -0,0 +1,65 @@
+use code_modifier::{LanguageExt as _, langs::rust::Rust};
+
+use crate::{
+    messages::{GenerateTests, IdMessage, NvimEnveloppe, NvimMessage},
+    notify::IntoNotification as _,
+    nvim::{
+        controlleur::document::cursor_byte,
+        model::{BufferData, Locker as _, SharedState, config::FimBackend, state::GeneratedTests},
+    },
+};
+
+/// `:MistralGenerateTests` : Ask the tests of the Rust function under the cursor. They are merged in the
+/// `mod tests` of the file once the answer is complete, then checked by `cargo test`.
+pub fn generate_tests(state: &SharedState) -> crate::Result<()> {
+    let (data, buffer) = BufferData::from_current_buffer()?;
+    if data.filetype != "rust" {
+        return Err("`:MistralGenerateTests` only supports Rust.".into_warn());
+    }
+    if data.filepath.is_none() {
+        return Err("The buffer must be a file, cargo tests it from the disk.".into_warn());
+    }
+    // The merge is written for cargo, it must not save other changes with it.
+    if data.modified {
+        return Err("Save the buffer first, the merged tests are written to the file.".into_warn());
+    }
+    let code = data.content.join("\n");
+    let Some(parser) = Rust::new_parser(code.as_bytes()) else {
+        return Err("Can't parse with tree-sitter.".into_error());
+    };
+    let Some(item) = parser
+        .documented_at(cursor_byte(&data))
+        .filter(|item| item.kind == "function_item")
+    else {
+        return Err("No function under the cursor.".into_warn());
+    };
+    let start = item
+        .docs
+        .first()
+        .map_or(item.item.start_byte, |doc| doc.start_byte.min(item.item.start_byte));
+    let start = code[..start].rfind('\n').map_or(0, |i| i + 1);
+    let function = code[start..item.item.end_byte].to_string();
+
+    let id = {
+        let mut s = state.lock();
+        let id = s.next_tests_id();
+        let generated = GeneratedTests {
+            name: item.name.clone(),
+            merged: None,
+        };
+        s.generated_tests.insert((buffer.clone(), id), generated);
+        id
+    };
+    let envelop = NvimEnveloppe {
+        id: IdMessage::Tests(buffer.handle(), id),
+        message: NvimMessage::GenerateTests(GenerateTests {
+            function,
+            name: item.name,
+            existing: parser.list_tests(),
+            backend: FimBackend::read(),
+        }),
+    };
+    state.lock().tx_mistral.send(envelop).unwrap();
+    crate::notify::info("Generating the tests…");
+    Ok(())
+}
@@ -16,6 +16,7 @@ pub struct Selection {
 //     pub column: usize,
 // }
 
+#[derive(Clone)]
 pub struct BufferData {
     pub filepath: Option<PathBuf>,
     pub filetype: String,
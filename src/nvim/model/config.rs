@@ -0,0 +1,181 @@
+use std::{collections::HashMap, time::Duration};
+
+use nvim_oxi::{api, conversion::FromObject};
+use serde::Deserialize;
+
+use crate::mistral::model::completion::Model;
+
+/// Read `vim.g.mistral_{name}` each time it is needed, so options can change at runtime.
+pub fn var<T: FromObject>(name: &str) -> Option<T> {
+    api::get_var::<T>(&format!("mistral_{name}")).ok()
+}
+
+pub fn set_var(name: &str, value: impl nvim_oxi::conversion::ToObject) -> crate::Result<()> {
+    api::set_var(&format!("mistral_{name}"), value)?;
+    Ok(())
+}
+
+/// `vim.g.mistral_auto_fim = true` shows a suggestion after a pause in insert mode.
+pub struct AutoFim {
+    pub enabled: bool,
+    /// `vim.g.mistral_auto_fim_delay`, in milliseconds.
+    pub delay: Duration,
+    /// `vim.g.mistral_auto_fim_filetypes`, all filetypes when not set.
+    pub filetypes: Option<Vec<String>>,
+}
+
+impl AutoFim {
+    pub const DEFAULT_DELAY: i64 = 400;
+
+    pub fn read() -> Self {
+        Self {
+            enabled: var("auto_fim").unwrap_or(false),
+            delay: Duration::from_millis(var("auto_fim_delay").unwrap_or(Self::DEFAULT_DELAY).max(0) as u64),
+            filetypes: var("auto_fim_filetypes"),
+        }
+    }
+    pub fn allows(&self, filetype: &str) -> bool {
+        self.enabled
+            && self
+                .filetypes
+                .as_ref()
+                .is_none_or(|filetypes| filetypes.iter().any(|f| f == filetype))
+    }
+}
+
+/// `vim.g.mistral_fim_model`, Codestral by default. Models without a `fim/completions` endpoint
+/// complete through a chat prompt, set per model by `vim.g.mistral_fim_templates`.
+#[derive(Clone, Debug)]
+pub struct FimBackend {
+    pub model: String,
+    /// `None` when the model has a FIM endpoint.
+    pub template: Option<FimTemplate>,
+    pub cache: FimCache,
+}
+
+/// `vim.g.mistral_fim_cache = false` always requests a new completion.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FimCache {
+    /// A cached completion of the same request is replayed.
+    Use,
+    /// Request a new completion, which replaces the cached one.
+    Refresh,
+    Off,
+}
+
+impl FimBackend {
+    pub const DEFAULT_MODEL: &'static str = "codestral-latest";
+
+    pub fn read() -> Self {
+        let model: String = var("fim_model").unwrap_or_else(|| Self::DEFAULT_MODEL.to_string());
+        let template = var::<FimTemplates>("fim_templates").and_then(|mut templates| templates.0.remove(&model));
+        let mut backend = Self::new(model, template);
+        // A misspelled Codestral would be sent to the FIM endpoint, which only knows Mistral's models.
+        if backend.template.is_none() && Model::from_name(&backend.model).is_none() {
+            crate::notify::warn(format!(
+                "Unknown FIM model `{}` in `vim.g.mistral_fim_model`, `{}` is used.",
+                backend.model,
+                Self::DEFAULT_MODEL
+            ));
+            backend.model = Self::DEFAULT_MODEL.to_string();
+        }
+        if !var("fim_cache").unwrap_or(true) {
+            backend.cache = FimCache::Off;
+        }
+        backend
+    }
+    /// Only Codestral has a FIM endpoint, the other models get the default template.
+    pub fn new(model: String, template: Option<FimTemplate>) -> Self {
+        let template = template.or_else(|| (!model.starts_with("codestral")).then(FimTemplate::default));
+        Self {
+            model,
+            template,
+            cache: FimCache::Use,
+        }
+    }
+    /// `fresh` skips the cached completion (`:MistralFIMFunction!`).
+    pub fn fresh(mut self, fresh: bool) -> Self {
+        if fresh && self.cache == FimCache::Use {
+            self.cache = FimCache::Refresh;
+        }
+        self
+    }
+}
+
+/// Chat prompt of a FIM, `{prefix}`, `{suffix}`, `{hole}` and `{filetype}` are replaced.
+/// Missing fields keep their default value.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct FimTemplate {
+    pub system: String,
+    pub prompt: String,
+    /// Marks where the completion goes.
+    pub hole: String,
+}
+
+impl Default for FimTemplate {
+    fn default() -> Self {
+        Self {
+            system: "You are a code completion engine. Write the {filetype} code replacing {hole} in the user's \
+                     code. Answer with this code only, without explanation nor markdown."
+                .to_string(),
+            prompt: "{prefix}{hole}{suffix}".to_string(),
+            hole: "<FILL>".to_string(),
+        }
+    }
+}
+
+/// `vim.g.mistral_fim_templates = { ["model-name"] = { system = "…", prompt = "…", hole = "…" } }`
+struct FimTemplates(HashMap<String, FimTemplate>);
+
+impl FromObject for FimTemplates {
+    fn from_object(object: nvim_oxi::Object) -> Result<Self, nvim_oxi::conversion::Error> {
+        HashMap::deserialize(nvim_oxi::serde::Deserializer::new(object))
+            .map(Self)
+            .map_err(Into::into)
+    }
+}
+
+/// `vim.g.mistral_suggestion_keys = { accept = "<Tab>", dismiss = "" }`, the insert mode keys of the ghost text.
+/// A key set to `""` is not mapped, and `vim.g.mistral_suggestion_keys = false` maps none of them.
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct SuggestionKeys {
+    pub suggest: String,
+    pub accept: String,
+    pub accept_word: String,
+    pub accept_line: String,
+    pub next: String,
+    pub prev: String,
+    pub dismiss: String,
+}
+
+impl Default for SuggestionKeys {
+    fn default() -> Self {
+        Self {
+            suggest: "<M-\\>".to_string(),
+            accept: "<M-l>".to_string(),
+            accept_word: "<M-w>".to_string(),
+            accept_line: "<M-j>".to_string(),
+            next: "<M-]>".to_string(),
+            prev: "<M-[>".to_string(),
+            dismiss: "<M-e>".to_string(),
+        }
+    }
+}
+
+impl SuggestionKeys {
+    /// `None` when the keys are disabled.
+    pub fn read() -> Option<Self> {
+        if var::<bool>("suggestion_keys") == Some(false) {
+            return None;
+        }
+        Some(var("suggestion_keys").unwrap_or_default())
+    }
+}
+
+impl FromObject for SuggestionKeys {
+    fn from_object(object: nvim_oxi::Object) -> Result<Self, nvim_oxi::conversion::Error> {
+        Self::deserialize(nvim_oxi::serde::Deserializer::new(object)).map_err(Into::into)
+    }
+}
@@ -1,4 +1,5 @@
 mod buffer;
+pub mod config;
 pub mod cursor;
 pub mod state;
 pub mod tool_mode;
@@ -10,6 +11,9 @@ pub use cursor::{
     NvimBound, NvimRange, OneIndexed, Row, RowRange, StartExclusive, ZeroIndexed, get_cursor, get_lines, get_mark,
     get_text, set_cursor, set_text,
 };
-pub use state::{BufferModifierGroupedUndo, Chat, ChatForm, ChatState, Locker, SharedState, State};
+pub use state::{
+    BufferModifierGroupedUndo, Chat, ChatForm, ChatState, InlineEdit, Locker, ReplacedDocs, SharedState, State,
+    Suggestion,
+};
 pub use tool_mode::Mode;
 pub use undotree::UndotreeData;
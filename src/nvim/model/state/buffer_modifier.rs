@@ -238,6 +238,8 @@ pub struct BufferModifierGroupedUndo {
     running_insertions_ids: HashSet<usize>,
     running_replacements_ids: HashSet<usize>,
     modifications: Vec<Modification>,
+    /// Changed tick after the last `replace_rows`, the next one joins its undo step while it is unchanged.
+    changedtick: Option<i32>,
 }
 
 impl BufferModifierGroupedUndo {
@@ -249,6 +251,7 @@ impl BufferModifierGroupedUndo {
             running_insertions_ids: Default::default(),
             running_replacements_ids: Default::default(),
             modifications: Default::default(),
+            changedtick: None,
         })
     }
     fn parse_max_cursor(&self, cursor: &mut Cursor) -> BMResult<()> {
@@ -433,6 +436,17 @@ impl BufferModifierGroupedUndo {
         }
         Err(BMError::IdNotInitialised)
     }
+    /// Replace the rows `start..end` (empty to insert before `start`) by `lines`. Successive replacements
+    /// are a single undo step, until the buffer is changed by something else (ex: the user, an undo).
+    pub fn replace_rows(&mut self, start: usize, end: usize, lines: Vec<String>) -> BMResult<()> {
+        let tick = self.buffer.get_changedtick()?;
+        if self.changedtick == Some(tick) {
+            self.buffer.call(|_| api::command("undojoin"))??;
+        }
+        self.buffer.set_lines(start..end, true, lines)?;
+        self.changedtick = Some(self.buffer.get_changedtick()?);
+        Ok(())
+    }
     fn insertion_successive(&mut self, id: usize, nb_rows: usize, nb_column: usize) -> Option<model::Cursor> {
         let mut it_cursors = self.modifications.iter_mut();
         let mut target = None;
@@ -472,6 +486,9 @@ impl BufferModifierGroupedUndo {
         //     false
         // })
     }
+    pub fn is_running(&self, id: usize) -> bool {
+        self.running_insertions_ids.contains(&id) || self.running_replacements_ids.contains(&id)
+    }
     pub fn id_finished(&mut self, id: &usize) -> bool {
         self.running_insertions_ids.remove(id);
         self.running_replacements_ids.remove(id);
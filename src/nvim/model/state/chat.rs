@@ -1,4 +1,4 @@
-use std::{path::PathBuf, str::FromStr as _};
+use std::{collections::HashSet, path::PathBuf, str::FromStr as _};
 
 use mistral_nvim_derive::Form;
 use nvim_oxi::api;
@@ -15,8 +15,17 @@ pub type MsgIndex = usize;
 type Position = usize;
 
 pub mod bar;
+mod export;
 mod highlight;
+mod import;
+pub mod index;
 mod parser;
+mod search;
+
+pub use export::ExportFormat;
+pub use import::Imported;
+pub use index::ChatFile;
+pub use search::SearchQuery;
 
 use parser::*;
 
@@ -85,6 +94,25 @@ pub struct ChatState {
     pub metadata: ChatMetadata,
     pub messages: Vec<MessageState>,
     pub positions: MessagesPositions,
+    /// Documents sent to the OCR endpoint, the prompt waits for them.
+    pub ocr_running: HashSet<mistral::model::ocr::OcrSource>,
+    /// The message whose `<THINKING>` section is being streamed.
+    pub thinking_open: Option<MsgIndex>,
+    /// Escapes the streamed lines looking like a tag.
+    escaper: ContentEscaper,
+    /// The answer being regenerated, it is not streamed in the buffer.
+    pub regenerating: Option<Regeneration>,
+    /// The window the prompt was sent from, its cursor moves to the next prompt.
+    pub prompt_window: Option<api::Window>,
+}
+
+/// A new version asked for an answer.
+#[derive(Clone)]
+pub struct Regeneration {
+    pub index: MsgIndex,
+    pub model: mistral::model::completion::Model,
+    /// Ended by an error, `:MistralChatRegenerate!` or the chat closed : the rest of its stream is ignored.
+    pub stopped: bool,
 }
 
 #[derive(Clone)]
@@ -172,6 +200,30 @@ pub struct ChatMetadata {
     pub name: String,
     pub description: String,
     pub usage: mistral::model::stream::Usage,
+    /// Send the reasoning traces back to the model (`thinking="keep"`).
+    pub keep_thinking: bool,
+    /// File name of the chat this one was forked from (`fork="review.chat"`), in the same directory.
+    pub fork: Option<String>,
+}
+
+impl ChatMetadata {
+    /// From the `<CHAT/>` tag line of a file, migrated if it is an older version.
+    pub fn from_tag_line(line: &String) -> Option<Self> {
+        if !is_self_tag_line(line, TAG_CHAT) {
+            return None;
+        }
+        let line = migrate_chat(line, &mut []).unwrap_or_else(|| line.clone());
+        let mut metadata = Self::default();
+        parse_tag_line(&line, |key, val, _cols| metadata_setter(key, val, &mut metadata));
+        Some(metadata)
+    }
+    /// From the first line of a `.chat` file.
+    pub fn from_file(path: &std::path::Path) -> Option<Self> {
+        use std::io::BufRead as _;
+        let file = std::fs::File::open(path).ok()?;
+        let line = std::io::BufReader::new(file).lines().next()?.ok()?;
+        Self::from_tag_line(&line)
+    }
 }
 
 #[derive(Default, Clone, Debug)]
@@ -183,6 +235,71 @@ pub struct MessageState {
     pub params: mistral::model::completion::CompletionParams,
     pub status: mistral::model::stream::Status,
     pub tool_calls_positions: Option<Vec<RowRange>>,
+    /// `<OCR/>` tags whose result is not cached yet.
+    pub ocr_pending: Vec<mistral::model::ocr::OcrSource>,
+    /// Reasoning trace of Magistral models, written in a folded `<THINKING>` section.
+    pub thinking: Option<String>,
+    pub thinking_position: Option<RowRange>,
+    /// The other answers to the same conversation, from `:MistralChatRegenerate` (`<VERSION>` sections).
+    pub versions: Vec<MessageVersion>,
+    /// Index of this answer among all the versions (`version="1"`).
+    pub version: usize,
+    /// `YYYY-MM-DD HH:MM` when the answer was received, on the prompt and the answer (`date="…"`). `None` in the
+    /// files written before.
+    pub date: Option<String>,
+}
+
+/// An answer which is not the selected one, with its own reasoning trace.
+#[derive(Default, Clone, Debug)]
+pub struct MessageVersion {
+    pub model: mistral::model::completion::Model,
+    pub usage: mistral::model::stream::Usage,
+    pub content: String,
+    pub thinking: Option<String>,
+}
+
+impl MessageState {
+    /// An empty User prompt following this message, with its model, mode and parameters.
+    pub fn next_prompt(&self) -> Self {
+        let mut prompt = Self {
+            mode: self.mode.clone(),
+            model: self.model.clone(),
+            params: self.params.clone(),
+            ..Default::default()
+        };
+        prompt.message.role = mistral::model::Role::User;
+        prompt
+    }
+    pub fn nb_versions(&self) -> usize {
+        self.versions.len() + 1
+    }
+    /// Replace the current answer by `version`, returns the previous one.
+    fn replace_version(&mut self, version: MessageVersion) -> MessageVersion {
+        MessageVersion {
+            model: std::mem::replace(&mut self.model, version.model),
+            usage: std::mem::replace(&mut self.usage, version.usage),
+            content: std::mem::replace(&mut self.message.content, version.content),
+            thinking: std::mem::replace(&mut self.thinking, version.thinking),
+        }
+    }
+    /// A regenerated answer, it is selected.
+    pub fn push_version(&mut self, version: MessageVersion) {
+        let previous = self.replace_version(version);
+        self.versions.insert(self.version, previous);
+        self.version = self.versions.len();
+    }
+    /// Select the n-th version (0 based).
+    pub fn select_version(&mut self, index: usize) {
+        if index == self.version || index >= self.nb_versions() {
+            return;
+        }
+        // Once the current one is inserted back, the versions hold all the answers in order.
+        let previous = self.replace_version(MessageVersion::default());
+        self.versions.insert(self.version, previous);
+        let selected = self.versions.remove(index);
+        self.replace_version(selected);
+        self.version = index;
+    }
 }
 
 /// This form serves to setup a Chat
@@ -220,6 +337,11 @@ impl ChatState {
             },
             messages: Vec::default(),
             positions: MessagesPositions::default(),
+            ocr_running: HashSet::default(),
+            thinking_open: None,
+            escaper: ContentEscaper::default(),
+            regenerating: None,
+            prompt_window: None,
         };
         chat_state.write_config_line();
         if !desc.is_empty() {
@@ -251,10 +373,43 @@ impl ChatState {
             metadata: Default::default(),
             messages,
             positions: MessagesPositions::default(),
+            ocr_running: HashSet::default(),
+            thinking_open: None,
+            escaper: ContentEscaper::default(),
+            regenerating: None,
+            prompt_window: None,
         };
+        chat_state.migrate()?;
         chat_state.init_buffer(state)?;
         Ok(chat_state)
     }
+    /// Upgrade a file written by an older version of the plugin.
+    fn migrate(&mut self) -> crate::Result<()> {
+        let buf = &mut self.buffer.clone();
+        let line = model::cursor::get_line(buf, Row(0), false)?;
+        if !is_self_tag_line(&line, TAG_CHAT) {
+            // Reported by `update_buffer`.
+            return Ok(());
+        }
+        let version = chat_version(&line);
+        if version > CHAT_VERSION {
+            crate::notify::warn(format!(
+                "Chat file of version {version}, newer than the plugin : unknown attributes are ignored."
+            ));
+        } else {
+            let mut lines: Vec<String> = model::get_lines(buf, RowRange::FULL, false)?
+                .skip(1)
+                .map(|line| line.to_string())
+                .collect();
+            let Some(line) = migrate_chat(&line, &mut lines) else {
+                return Ok(());
+            };
+            let lines = std::iter::once(line).chain(lines);
+            model::cursor::set_lines(buf, RowRange::FULL, false, lines)?;
+            crate::notify::info(format!("Chat file migrated from version {version} to {CHAT_VERSION}."));
+        }
+        Ok(())
+    }
     #[track_caller]
     pub fn buffer_modifier_get_or_create<'bm>(
         &'bm mut self,
@@ -345,6 +500,36 @@ impl ChatState {
             self.update_buffer(self.positions.last().clone())
         }
     }
+    /// Stream the content of a message, the lines looking like a tag are escaped.
+    pub fn insert_content(&mut self, lines: Vec<String>, id: MsgIndex) -> crate::Result<()> {
+        let lines = self.escaper.push(lines);
+        self.insert(lines, Some(id))
+    }
+    /// Write the held start of the last streamed line.
+    pub fn flush_content(&mut self, id: MsgIndex) -> crate::Result<()> {
+        let rest = self.escaper.flush();
+        if rest.is_empty() {
+            return Ok(());
+        }
+        self.insert(vec![rest], Some(id))
+    }
+    /// Stream the reasoning trace in its own section, before the content.
+    pub fn insert_thinking(&mut self, lines: Vec<String>, id: MsgIndex) -> crate::Result<()> {
+        if self.thinking_open != Some(id) {
+            self.thinking_open = Some(id);
+            self.insert(vec![format!("<{TAG_THINKING}>"), String::new()], Some(id))?;
+        }
+        self.insert_content(lines, id)
+    }
+    pub fn close_thinking(&mut self) -> crate::Result<()> {
+        let Some(id) = self.thinking_open.take() else {
+            return Ok(());
+        };
+        self.insert(
+            vec![self.escaper.flush(), format!("</{TAG_THINKING}>"), String::new()],
+            Some(id),
+        )
+    }
     pub fn buffer_modifier_ids_finished(&mut self, ids: Vec<usize>) {
         match &mut self.buffer_modifier {
             None => self.is_running = None,
@@ -378,16 +563,7 @@ impl ChatState {
         self.insert(lines, id)
     }
     fn write_config_line(&mut self) {
-        let ChatMetadata {
-            name,
-            description,
-            usage,
-        } = &self.metadata;
-        let mut args = String::new();
-        args.push_str(&format!(r#" name="{name}""#));
-        args.push_str(&format!(r#" usage="{usage}""#));
-        args.push_str(&format!(r#" description="{description}""#));
-        let lines = format!(r#"<{TAG_CHAT}{args}/>"#);
+        let lines = build_tag_chat_line(&self.metadata);
         // Erase whole buffer (this function is used only during chat's creation).
         model::cursor::set_lines(&mut self.buffer, RowRange::FULL, false, [lines]).notify_error();
     }
@@ -415,6 +591,10 @@ impl ChatState {
                 let range = model::FromNvimRange::<model::EndExclusive, model::ZeroIndexed>::into_nvim(cols);
                 line.replace_range(range, &value);
             }
+            // Missing from the tags written before the answer.
+            if let Some(date) = &message.date {
+                line = set_tag_args(&line, &[("date", date.clone())]);
+            }
             self.replace_line(row, line, Some(message_index))
                 .unwrap();
             bar::StatusLineChatCache::outdate_page(&self.buffer, message_index + 1);
@@ -433,7 +613,10 @@ impl ChatState {
         };
         let buf = &mut self.buffer;
         let mut pos = pos.clone();
-        pos.start += 1; // Start is Tag line, and we just modify the content
+        pos.start = match &message.thinking_position {
+            Some(thinking) => thinking.end + 1,
+            None => pos.start + 1, // Start is Tag line, and we just modify the content
+        };
         let prev_len = *pos.end - *pos.start;
         let new_len = message.message.content.len() + 1;
         crate::log_libuv!(
@@ -441,11 +624,7 @@ impl ChatState {
             "Updated message content ({message_index}) : `{:?}`",
             message.message.content
         );
-        let lines = message
-            .message
-            .content
-            .split("\n")
-            .chain(std::iter::once(""));
+        let lines = escape_content(&message.message.content).chain(std::iter::once(String::new()));
         model::cursor::set_lines(buf, pos.clone(), false, lines)?;
         if prev_len != new_len {
             self.update_buffer(RowRange::FULL)?;
@@ -456,8 +635,11 @@ impl ChatState {
         let buf = &mut self.buffer.clone();
         let row = Row(0);
         let line = model::cursor::get_line(buf, row, false)?;
+        let line = line.to_string();
         let mut updates = Vec::new();
-        parse_tag_line(&line.to_string(), |key, current_val, cols| {
+        let mut has_thinking = false;
+        parse_tag_line(&line, |key, current_val, cols| {
+            has_thinking |= key == "thinking";
             if let Some(new_value) = config_getter(key, self) {
                 if new_value != current_val {
                     updates.push((cols, new_value))
@@ -467,6 +649,14 @@ impl ChatState {
         for (cols, value) in updates.into_iter().rev() {
             model::set_text(buf, row..=row, cols, [value]).notify_error();
         }
+        // Optional argument, only written once enabled.
+        if !has_thinking && self.metadata.keep_thinking {
+            let line = model::cursor::get_line(buf, row, false)?;
+            if let Some(head) = line.strip_suffix("/>") {
+                let line = format!(r#"{} thinking="keep"/>"#, head.trim_end());
+                model::cursor::set_lines(buf, row..=row, false, [line])?;
+            }
+        }
         Ok(())
     }
 
@@ -570,19 +760,57 @@ impl ChatState {
         self.update_buffer(self.positions.last().clone())
     }
 
+    pub fn ocr_pending(&self) -> Vec<mistral::model::ocr::OcrSource> {
+        self.messages
+            .iter()
+            .flat_map(|m| m.ocr_pending.iter().cloned())
+            .collect()
+    }
+    /// Send the `<OCR/>` documents not cached yet, the prompt is sent again once they are all done.
+    fn run_ocr(&mut self, state: &super::SharedState, pending: Vec<mistral::model::ocr::OcrSource>) -> crate::Result<()> {
+        if !self.ocr_running.is_empty() {
+            return Err("Waiting for the OCR of the documents.".into_warn());
+        }
+        let id = crate::messages::IdMessage::Chat(self.buffer.handle(), self.messages.len() - 1);
+        crate::notify::info(format!("OCR of {} document(s), the prompt will be sent after.", pending.len()));
+        for source in pending {
+            self.ocr_running.insert(source.clone());
+            let message = crate::messages::NvimMessage::Ocr(crate::messages::Ocr { source, cursor: None });
+            state
+                .lock()
+                .tx_mistral
+                .send(crate::messages::NvimEnveloppe { id, message })
+                .unwrap();
+        }
+        Ok(())
+    }
     pub fn send_prompt(&mut self, state: &super::SharedState) -> crate::Result<()> {
         self.update_prompt()?;
+        let pending = self.ocr_pending();
+        if !pending.is_empty() {
+            return self.run_ocr(state, pending);
+        }
         let envelop = self.build_request_envelop()?;
         state.lock().tx_mistral.send(envelop).unwrap();
         Ok(())
     }
+    /// The messages of the chat, as sent to the API.
+    pub fn request_messages(&self) -> Vec<mistral::model::Message> {
+        let keep_thinking = self.metadata.keep_thinking;
+        self.messages
+            .iter()
+            .map(|m| {
+                let mut message = m.message.clone();
+                if keep_thinking && let Some(thinking) = &m.thinking {
+                    message.content = format!("<think>\n{thinking}\n</think>\n{}", message.content);
+                }
+                message
+            })
+            .collect()
+    }
     pub fn build_request_envelop(&mut self) -> crate::Result<crate::messages::NvimEnveloppe> {
         use crate::mistral::model::completion::{ChatCompletion, ChatRequest};
-        let messages = self
-            .messages
-            .iter()
-            .map(|m| m.message.clone())
-            .collect();
+        let messages = self.request_messages();
         let Some(last) = self.messages.last() else {
             return Err("No message stored in this Chat.".into_error());
         };
@@ -603,6 +831,178 @@ impl ChatState {
         };
         Ok(envelop)
     }
+    /// Content of a new chat holding the conversation up to the message under the cursor, continued by a prompt.
+    /// The lines are copied as they are (the `<FILE/>` and `<OCR/>` tags stay tags), only the `usage` and the
+    /// `fork` of the `<CHAT/>` tag change. `parent` is the file name of this chat.
+    pub fn fork(&self, win: &api::Window, parent: String) -> crate::Result<String> {
+        let position = match self.get_position_index(win) {
+            None | Some(0) => return Err("Place the cursor on the message to fork from.".into_warn()),
+            Some(position) => position.min(self.messages.len()),
+        };
+        let Some(end) = self.positions.0.get(position).map(|rows| rows.end) else {
+            return Err("Can't find the rows of the message.".into_error());
+        };
+        let mut lines: Vec<String> = model::get_lines(&self.buffer, 0..=*end, false)?
+            .map(|line| line.to_string())
+            .collect();
+        let messages = &self.messages[..position];
+        let mut usage = mistral::model::stream::Usage::default();
+        for message in messages {
+            usage += &message.usage;
+        }
+        lines[0] = set_tag_args(&lines[0], &[("usage", usage.to_string()), ("fork", parent)]);
+        if let Some(last) = messages.last()
+            && !matches!(last.message.role, mistral::model::Role::User)
+        {
+            lines.extend(build_tag_message_lines(last.next_prompt()));
+        }
+        Ok(lines.join("\n") + "\n")
+    }
+    /// The answer under the cursor, regenerated from the conversation before it, by `model` or by its own.
+    pub fn regenerate(
+        &mut self,
+        win: &api::Window,
+        model: Option<mistral::model::completion::Model>,
+    ) -> crate::Result<crate::messages::NvimEnveloppe> {
+        use crate::mistral::model::completion::{ChatCompletion, ChatRequest};
+        if self.is_running.is_some() || self.is_regenerating() {
+            return Err("Chat is running.".into_warn());
+        }
+        // The first message has nothing to answer.
+        let Some(position @ 2..) = self.get_position_index(win) else {
+            return Err("Place the cursor on an answer.".into_warn());
+        };
+        let index = position - 1;
+        let Some(target) = self.messages.get(index) else {
+            return Err("Place the cursor on an answer.".into_warn());
+        };
+        if !matches!(target.message.role, mistral::model::Role::Assistant) || target.message.tool_calls.is_some() {
+            return Err("Only an answer without tool calls can be regenerated.".into_warn());
+        }
+        let model = model.unwrap_or_else(|| target.model.clone());
+        let mut params = target.params.clone();
+        // The answer can't be followed by tool results.
+        params.tools = None;
+        let mut messages = self.request_messages();
+        messages.truncate(index);
+        let request = ChatRequest {
+            completion: ChatCompletion {
+                model: model.clone(),
+                messages,
+            },
+            params,
+        };
+        self.regenerating = Some(Regeneration {
+            index,
+            model,
+            stopped: false,
+        });
+        // Answered as the message following `index - 1`.
+        Ok(crate::messages::NvimEnveloppe {
+            id: crate::messages::IdMessage::Chat(self.buffer.handle(), index - 1),
+            message: crate::messages::NvimMessage::Chat(request),
+        })
+    }
+    /// Store the regenerated answer as a new version of the message, and select it.
+    pub fn finish_regeneration(
+        &mut self,
+        content: String,
+        thinking: Option<String>,
+        usage: mistral::model::stream::Usage,
+    ) -> crate::Result<()> {
+        let Some(Regeneration {
+            index,
+            model,
+            stopped: false,
+        }) = self.regenerating.take()
+        else {
+            return Ok(());
+        };
+        let Some(message) = self.messages.get_mut(index) else {
+            return Err(format!("Can't find message by index ({index}).").into_error());
+        };
+        let version = MessageVersion {
+            model,
+            usage,
+            content,
+            thinking,
+        };
+        message.push_version(version);
+        let nb_versions = message.nb_versions();
+        self.rewrite_message(index)?;
+        crate::notify::info(format!("Version {nb_versions}/{nb_versions}."));
+        Ok(())
+    }
+    /// A regeneration is waiting for its answer.
+    pub fn is_regenerating(&self) -> bool {
+        self.regenerating.as_ref().is_some_and(|regeneration| !regeneration.stopped)
+    }
+    /// Stop the pending regeneration, its answer is dropped. Returns the abort to send.
+    pub fn stop_regeneration(&mut self) -> Option<crate::messages::NvimEnveloppe> {
+        let regeneration = self.regenerating.as_mut().filter(|regeneration| !regeneration.stopped)?;
+        regeneration.stopped = true;
+        Some(crate::messages::NvimEnveloppe {
+            id: crate::messages::IdMessage::Chat(self.buffer.handle(), regeneration.index - 1),
+            message: crate::messages::NvimMessage::Abort,
+        })
+    }
+    /// Select the next (or previous with a negative `inc`) version of the message under the cursor.
+    pub fn cycle_version(&mut self, win: &api::Window, inc: isize) -> crate::Result<()> {
+        if self.is_running.is_some() || self.is_regenerating() {
+            return Err("Chat is running.".into_warn());
+        }
+        let Some(position @ 1..) = self.get_position_index(win) else {
+            return Ok(());
+        };
+        let index = position - 1;
+        let Some(message) = self.messages.get_mut(index) else {
+            return Ok(());
+        };
+        let nb_versions = message.nb_versions();
+        if nb_versions == 1 {
+            crate::notify::info("This message has only one version.");
+            return Ok(());
+        }
+        let selected = (message.version as isize + inc).rem_euclid(nb_versions as isize) as usize;
+        message.select_version(selected);
+        self.rewrite_message(index)?;
+        crate::notify::info(format!("Version {}/{nb_versions}.", selected + 1));
+        Ok(())
+    }
+    /// Write the whole message again, when its sections changed.
+    fn rewrite_message(&mut self, message_index: MsgIndex) -> crate::Result<()> {
+        let Some(message) = self.messages.get(message_index).cloned() else {
+            return Err(format!("Can't find message by index ({message_index}).").into_error());
+        };
+        let Some(pos) = self.positions.get_by_msg_index(message_index).cloned() else {
+            return Err("Can't get position, but message exists for the same index.".into_error());
+        };
+        let buf = &mut self.buffer.clone();
+        // The blank lines before the next message are kept.
+        let current: Vec<String> = model::cursor::get_lines(buf, pos.clone(), false)?
+            .map(|line| line.to_string())
+            .collect();
+        let blank_lines = current
+            .iter()
+            .rev()
+            .take_while(|line| line.is_empty())
+            .count();
+        // Without the blank lines before the tag.
+        let mut lines = build_tag_message_lines(message).split_off(2);
+        while lines.last().is_some_and(|line| line.is_empty()) {
+            lines.pop();
+        }
+        lines.extend(std::iter::repeat_n(String::new(), blank_lines));
+        model::cursor::set_lines(buf, pos, false, lines)?;
+        self.update_buffer(RowRange::FULL)
+    }
+    pub fn export(&self, format: ExportFormat) -> crate::Result<String> {
+        match format {
+            ExportFormat::Markdown => Ok(export::markdown(&self.metadata, &self.messages)),
+            ExportFormat::Html => Ok(export::html(&self.metadata, &self.messages)),
+            ExportFormat::Json => export::json(&self.request_messages()),
+        }
+    }
     fn goto_message(&mut self, inc_position: isize, win: &mut api::Window) {
         if inc_position == 0 {
             return;
@@ -851,6 +1251,11 @@ Peux-tu modifier la fonction main gràce aux outils, pour qu'elle affiche plop ?
         metadata: ChatMetadata::default(),
         messages: Vec::default(),
         positions: MessagesPositions::default(),
+        ocr_running: HashSet::default(),
+        thinking_open: None,
+        escaper: ContentEscaper::default(),
+        regenerating: None,
+        prompt_window: None,
     };
     chat.update_buffer(RowRange::FULL)?;
     show(buffer);
@@ -971,3 +1376,286 @@ Peux-tu modifier la fonction main gràce aux outils, pour qu'elle affiche plop ?
     assert_eq!(chat.messages.len(), 4);
     Ok(())
 }
+
+#[cfg(not(feature = "prod_mode"))]
+#[nvim_oxi::test]
+#[track_caller]
+fn chat_thinking() -> crate::Result<()> {
+    const BUFFER_CONTENT: &'static str = r###"<CHAT name="Thinking" usage="0;0;0" description=""/>
+<MESSAGE  role="User" model="Magistral Medium Latest" status="Completed" usage="0;0;0"/>
+Combien font 2 + 2 ?
+<MESSAGE  role="Assistant" model="Magistral Medium Latest" status="Completed" usage="0;0;0"/>
+<THINKING>
+Une addition simple.
+
+</THINKING>
+4"###;
+
+    let buffer = &mut api::Buffer::current();
+    buffer.set_lines(.., false, BUFFER_CONTENT.split('\n'))?;
+    let mut chat = ChatState {
+        is_running: None,
+        path: Default::default(),
+        buffer: buffer.clone(),
+        buffer_modifier: None,
+        metadata: ChatMetadata::default(),
+        messages: Vec::default(),
+        positions: MessagesPositions::default(),
+        ocr_running: HashSet::default(),
+        thinking_open: None,
+        escaper: ContentEscaper::default(),
+        regenerating: None,
+        prompt_window: None,
+    };
+    chat.update_buffer(RowRange::FULL)?;
+    let assistant = &chat.messages[1];
+    assert_eq!(assistant.message.content, "4");
+    assert_eq!(assistant.thinking.as_deref(), Some("Une addition simple."));
+    assert_eq!(assistant.thinking_position, Some((4..7).into()));
+
+    let request_content = |chat: &mut ChatState| match chat.build_request_envelop().map(|e| e.message) {
+        Ok(crate::messages::NvimMessage::Chat(request)) => request.completion.messages[1].content.clone(),
+        _ => String::new(),
+    };
+    assert_eq!(request_content(&mut chat), "4");
+    chat.mut_chat(|chat| chat.metadata.keep_thinking = true)?;
+    assert_eq!(request_content(&mut chat), "<think>\nUne addition simple.\n</think>\n4");
+    assert_eq!(
+        model::cursor::get_line(buffer, Row(0), false)?,
+        r#"<CHAT name="Thinking" usage="0;0;0" description="" thinking="keep"/>"#
+    );
+    Ok(())
+}
+
+#[cfg(not(feature = "prod_mode"))]
+#[nvim_oxi::test]
+#[track_caller]
+fn chat_versions() -> crate::Result<()> {
+    const BUFFER_CONTENT: &'static str = r###"<CHAT version="3" name="Versions" usage="0;0;0" description=""/>
+<MESSAGE  role="User" model="Large Latest" status="Completed" usage="0;0;0"/>
+Une couleur ?
+
+<MESSAGE  role="Assistant" model="Large Latest" status="Completed" usage="0;0;0" version="1"/>
+<VERSION model="Medium Latest" usage="1;2;3">
+Rouge.
+\<VERSION> n'est pas une balise.
+</VERSION>
+Bleu.
+
+<MESSAGE  role="User" model="Large Latest" status="Completed" usage="0;0;0"/>
+Pourquoi ?"###;
+
+    let buffer = &mut api::Buffer::current();
+    buffer.set_lines(.., false, BUFFER_CONTENT.split('\n'))?;
+    let mut chat = ChatState {
+        is_running: None,
+        path: Default::default(),
+        buffer: buffer.clone(),
+        buffer_modifier: None,
+        metadata: ChatMetadata::default(),
+        messages: Vec::default(),
+        positions: MessagesPositions::default(),
+        ocr_running: HashSet::default(),
+        thinking_open: None,
+        escaper: ContentEscaper::default(),
+        regenerating: None,
+        prompt_window: None,
+    };
+    chat.update_buffer(RowRange::FULL)?;
+    let contents = |chat: &ChatState| -> Vec<String> {
+        let answer = &chat.messages[1];
+        let mut contents = vec![answer.message.content.clone()];
+        contents.extend(answer.versions.iter().map(|version| version.content.clone()));
+        contents
+    };
+    let red = "Rouge.\n<VERSION> n'est pas une balise.";
+    assert_eq!(contents(&chat), ["Bleu.", red]);
+    assert_eq!(chat.messages[1].version, 1);
+    assert_eq!(chat.messages[1].versions[0].model.to_string(), "Medium Latest");
+    // Only the selected version is sent.
+    assert_eq!(chat.request_messages()[1].content, "Bleu.");
+
+    chat.messages[1].select_version(0);
+    chat.rewrite_message(1)?;
+    assert_eq!(contents(&chat), [red, "Bleu."]);
+    assert_eq!(chat.messages[1].model.to_string(), "Medium Latest");
+    assert_eq!(chat.messages[1].usage.to_string(), "1;2;3");
+    assert_eq!(chat.request_messages()[1].content, red);
+    assert_eq!(chat.messages[2].message.content, "Pourquoi ?");
+
+    let green = MessageVersion {
+        content: "Vert.".to_string(),
+        thinking: Some("Une couleur au hasard.".to_string()),
+        ..Default::default()
+    };
+    chat.messages[1].push_version(green);
+    chat.rewrite_message(1)?;
+    assert_eq!(contents(&chat), ["Vert.", red, "Bleu."]);
+    assert_eq!(chat.messages[1].version, 2);
+    assert_eq!(chat.messages[1].thinking.as_deref(), Some("Une couleur au hasard."));
+    // The trace of an answer is kept in its version, and back when it is selected again.
+    chat.messages[1].select_version(0);
+    chat.rewrite_message(1)?;
+    assert_eq!(chat.messages[1].thinking, None);
+    assert_eq!(
+        chat.messages[1].versions[1].thinking.as_deref(),
+        Some("Une couleur au hasard.")
+    );
+    chat.messages[1].select_version(2);
+    chat.rewrite_message(1)?;
+    assert_eq!(chat.messages[1].thinking.as_deref(), Some("Une couleur au hasard."));
+    assert_eq!(chat.messages.len(), 3);
+    assert_eq!(chat.messages[2].message.content, "Pourquoi ?");
+    Ok(())
+}
+
+#[cfg(not(feature = "prod_mode"))]
+#[nvim_oxi::test]
+#[track_caller]
+fn chat_round_trip() -> crate::Result<()> {
+    use mistral::model::{FunctionCall, Role, ToolCall, stream::Usage};
+    use proptest::{
+        prelude::*,
+        test_runner::{Config, TestCaseError, TestRunner},
+    };
+
+    // Content lines, some of them look like the structure of the chat.
+    let line = prop_oneof![
+        "[a-z0-9 \"\\\\<>/=`]{0,16}",
+        Just(r#"<MESSAGE role="User"/>"#.to_string()),
+        Just("</TOOLCALL>".to_string()),
+        Just("<THINKING>".to_string()),
+        Just("</THINKING>".to_string()),
+        Just(r#"\<FILE path="a"/>"#.to_string()),
+    ];
+    // Any text, with blank lines and spaces at its end.
+    let text = prop::collection::vec(line, 0..5).prop_map(|lines| lines.join("\n"));
+    // Attribute values, with quotes and backslashes.
+    let value = "[a-z0-9 \"\\\\/>=]{1,10}";
+    let tool_call = (proptest::option::of(value), proptest::option::of(0u32..4), value, value).prop_map(
+        |(id, index, name, argument)| ToolCall {
+            id,
+            index,
+            function: FunctionCall {
+                name,
+                arguments: serde_json::json!({ "value": argument }).to_string(),
+            },
+        },
+    );
+    let role = prop_oneof![
+        Just(Role::User),
+        Just(Role::System),
+        Just(Role::Assistant),
+        Just(Role::Tool)
+    ];
+    let usage = (0u32..1000, 0u32..1000).prop_map(|(prompt_tokens, completion_tokens)| Usage {
+        prompt_tokens,
+        completion_tokens,
+        total_tokens: prompt_tokens + completion_tokens,
+    });
+    let message = (
+        role,
+        text.clone(),
+        proptest::option::of(text),
+        proptest::option::of(prop::collection::vec(tool_call, 1..3)),
+        proptest::option::of(value),
+        proptest::option::of(value),
+        usage,
+        proptest::option::of(0u32..4096),
+    )
+        .prop_map(
+            |(role, content, thinking, tool_calls, name, tool_call_id, usage, max_tokens)| {
+                let mut message = MessageState::default();
+                message.message = mistral::model::Message {
+                    role,
+                    content,
+                    tool_calls,
+                    name,
+                    tool_call_id,
+                    ..Default::default()
+                };
+                message.thinking = thinking;
+                message.usage = usage;
+                message.params.max_tokens = max_tokens;
+                message
+            },
+        );
+    let messages = prop::collection::vec(message, 1..6);
+
+    let buffer = &api::Buffer::current();
+    let mut runner = TestRunner::new(Config::with_cases(64));
+    let result = runner.run(&messages, |messages| {
+        let fail = |message: String| TestCaseError::fail(message);
+        let mut lines = vec![format!(
+            r#"<{TAG_CHAT} version="{CHAT_VERSION}" name="" usage="0;0;0" description=""/>"#
+        )];
+        // Rows of the tags.
+        let mut expected_rows = vec![0];
+        for message in messages.iter().cloned() {
+            let message_lines = build_tag_message_lines(message);
+            // After two empty lines.
+            expected_rows.push(lines.len() + 2);
+            lines.extend(message_lines);
+        }
+        let mut buffer = buffer.clone();
+        buffer
+            .set_lines(.., false, lines)
+            .map_err(|err| fail(err.to_string()))?;
+        let mut chat = ChatState {
+            is_running: None,
+            path: Default::default(),
+            buffer: buffer.clone(),
+            buffer_modifier: None,
+            metadata: ChatMetadata::default(),
+            messages: Vec::default(),
+            positions: MessagesPositions::default(),
+            ocr_running: HashSet::default(),
+            thinking_open: None,
+            escaper: ContentEscaper::default(),
+            regenerating: None,
+            prompt_window: None,
+        };
+        chat.update_buffer(RowRange::FULL)
+            .map_err(|err| fail(err.message))?;
+
+        prop_assert_eq!(chat.messages.len(), messages.len());
+        let tool_calls = |tool_calls: &Option<Vec<ToolCall>>| {
+            tool_calls.as_ref().map(|tool_calls| {
+                tool_calls
+                    .iter()
+                    .map(|tc| {
+                        (
+                            tc.id.clone(),
+                            tc.index,
+                            tc.function.name.clone(),
+                            tc.function.arguments.clone(),
+                        )
+                    })
+                    .collect::<Vec<_>>()
+            })
+        };
+        for (parsed, message) in chat.messages.iter().zip(&messages) {
+            let (parsed_message, message_written) = (&parsed.message, &message.message);
+            prop_assert_eq!(parsed_message.role.to_string(), message_written.role.to_string());
+            prop_assert_eq!(&parsed_message.content, &message_written.content);
+            prop_assert_eq!(&parsed_message.name, &message_written.name);
+            prop_assert_eq!(&parsed_message.tool_call_id, &message_written.tool_call_id);
+            prop_assert_eq!(
+                tool_calls(&parsed_message.tool_calls),
+                tool_calls(&message_written.tool_calls)
+            );
+            prop_assert_eq!(&parsed.thinking, &message.thinking);
+            prop_assert_eq!(parsed.usage.to_string(), message.usage.to_string());
+            prop_assert_eq!(parsed.params.max_tokens, message.params.max_tokens);
+        }
+        let rows: Vec<usize> = chat
+            .positions
+            .iter()
+            .map(|range| range.start.to_usize())
+            .collect();
+        prop_assert_eq!(rows, expected_rows);
+        prop_assert_eq!(chat.positions.last().end, Row::MAX);
+        Ok(())
+    });
+    result.map_err(|err| err.to_string().into_error())
+}
@@ -0,0 +1,225 @@
+use super::{ChatMetadata, MessageState};
+use crate::mistral::model::{Message, Role};
+
+/// Format of `:MistralChatExport`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    Markdown,
+    Html,
+    /// The `messages` of the request sent to the API.
+    Json,
+}
+
+impl std::str::FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "md" | "markdown" => Ok(Self::Markdown),
+            "html" => Ok(Self::Html),
+            "json" => Ok(Self::Json),
+            _ => Err(format!("Unknown export format `{value}`, expected md, html or json.")),
+        }
+    }
+}
+
+impl ExportFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Markdown => "md",
+            Self::Html => "html",
+            Self::Json => "json",
+        }
+    }
+}
+
+/// Nothing to show : the empty prompt waiting at the end of a chat.
+fn is_empty(message: &MessageState) -> bool {
+    message.message.content.trim().is_empty() && message.message.tool_calls.is_none() && message.thinking.is_none()
+}
+
+/// A code fence longer than any run of backticks in `content`.
+fn fence(content: &str) -> String {
+    let longest = content.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+    "`".repeat(longest.max(2) + 1)
+}
+
+fn heading(message: &MessageState) -> String {
+    let Message { role, name, .. } = &message.message;
+    match (role, name) {
+        (Role::Tool, Some(name)) => format!("{role} `{name}`"),
+        (Role::Assistant, _) => format!("{role} ({})", message.model),
+        _ => role.to_string(),
+    }
+}
+
+/// Role headings, the reasoning traces in quotes and the tool calls in JSON blocks.
+pub fn markdown(metadata: &ChatMetadata, messages: &[MessageState]) -> String {
+    let mut md = format!("# {}\n", metadata.name);
+    if !metadata.description.is_empty() {
+        md.push('\n');
+        for line in metadata.description.lines() {
+            md.push_str(&format!("> {line}\n"));
+        }
+    }
+    for message in messages.iter().filter(|message| !is_empty(message)) {
+        md.push_str(&format!("\n## {}\n\n", heading(message)));
+        if let Some(thinking) = &message.thinking {
+            md.push_str("> *Reasoning*\n>\n");
+            for line in thinking.lines() {
+                md.push_str(&format!("> {line}\n"));
+            }
+            md.push('\n');
+        }
+        for tool_call in message.message.tool_calls.iter().flatten() {
+            let arguments = &tool_call.function.arguments;
+            let fence = fence(arguments);
+            md.push_str(&format!(
+                "**Tool call** `{}`\n\n{fence}json\n{arguments}\n{fence}\n\n",
+                tool_call.function.name
+            ));
+        }
+        let content = message.message.content.trim_end();
+        if matches!(message.message.role, Role::Tool) {
+            // Often code or logs.
+            let fence = fence(content);
+            md.push_str(&format!("{fence}\n{content}\n{fence}\n"));
+        } else if !content.is_empty() {
+            md.push_str(&format!("{content}\n"));
+        }
+    }
+    md
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+const STYLE: &'static str = "body { max-width: 60rem; margin: auto; padding: 1rem; font-family: sans-serif; }
+.message { border-left: 4px solid #89b4fa; margin: 1rem 0; padding: 0 1rem; }
+.user { border-color: #f9e2af; }
+.system { border-color: #a6adc8; }
+.tool { border-color: #a6e3a1; }
+.content { white-space: pre-wrap; }
+details { margin: 0.5rem 0; }
+pre { background: #1e1e2e; color: #cdd6f4; padding: 0.5rem; overflow-x: auto; }";
+
+/// A standalone page, the reasoning traces and the tool calls are collapsed.
+pub fn html(metadata: &ChatMetadata, messages: &[MessageState]) -> String {
+    let title = escape_html(&metadata.name);
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n<style>\n{STYLE}\n</style>\n\
+         </head>\n<body>\n<h1>{title}</h1>\n"
+    );
+    if !metadata.description.is_empty() {
+        html.push_str(&format!(
+            "<p class=\"content\">{}</p>\n",
+            escape_html(&metadata.description)
+        ));
+    }
+    for message in messages.iter().filter(|message| !is_empty(message)) {
+        let class = message.message.role.to_string().to_lowercase();
+        html.push_str(&format!(
+            "<section class=\"message {class}\">\n<h2>{}</h2>\n",
+            escape_html(&heading(message))
+        ));
+        if let Some(thinking) = &message.thinking {
+            html.push_str(&format!(
+                "<details>\n<summary>Reasoning</summary>\n<div class=\"content\">{}</div>\n</details>\n",
+                escape_html(thinking)
+            ));
+        }
+        for tool_call in message.message.tool_calls.iter().flatten() {
+            html.push_str(&format!(
+                "<details>\n<summary>Tool call <code>{}</code></summary>\n<pre>{}</pre>\n</details>\n",
+                escape_html(&tool_call.function.name),
+                escape_html(&tool_call.function.arguments)
+            ));
+        }
+        let content = escape_html(message.message.content.trim_end());
+        if matches!(message.message.role, Role::Tool) {
+            html.push_str(&format!(
+                "<details>\n<summary>Result</summary>\n<pre>{content}</pre>\n</details>\n"
+            ));
+        } else if !content.is_empty() {
+            html.push_str(&format!("<div class=\"content\">{content}</div>\n"));
+        }
+        html.push_str("</section>\n");
+    }
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+/// The `messages` array of the request, as sent by `:MistralChatSendPrompt`.
+pub fn json(messages: &[Message]) -> crate::Result<String> {
+    Ok(serde_json::to_string_pretty(messages)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mistral::model::{FunctionCall, ToolCall};
+
+    fn messages() -> Vec<MessageState> {
+        let message = |role, content: &str| {
+            let mut message = MessageState::default();
+            message.message.role = role;
+            message.message.content = content.to_string();
+            message
+        };
+        let mut assistant = message(Role::Assistant, "");
+        assistant.thinking = Some("Lire le fichier.".to_string());
+        assistant.message.tool_calls = Some(vec![ToolCall {
+            function: FunctionCall {
+                name: "CodeRetriever".to_string(),
+                arguments: r#"{"file": "src/main.rs"}"#.to_string(),
+            },
+            id: Some("1".to_string()),
+            index: Some(0),
+        }]);
+        let mut tool = message(Role::Tool, "fn main() {\n    println!(\"```\");\n}");
+        tool.message.name = Some("CodeRetriever".to_string());
+        vec![
+            message(Role::User, "Que fait `main` ?"),
+            assistant,
+            tool,
+            message(Role::Assistant, "Elle affiche <b>```</b>."),
+            message(Role::User, ""),
+        ]
+    }
+
+    #[test]
+    fn export_markdown() {
+        let metadata = ChatMetadata {
+            name: "Revue".to_string(),
+            description: "Relire le code.".to_string(),
+            ..Default::default()
+        };
+        let md = markdown(&metadata, &messages());
+        assert!(md.starts_with("# Revue\n\n> Relire le code.\n\n## User\n\nQue fait `main` ?\n"));
+        assert!(md.contains("> *Reasoning*\n>\n> Lire le fichier.\n\n**Tool call** `CodeRetriever`\n\n```json\n"));
+        // The fence of the tool result is longer than its backticks.
+        assert!(md.contains("## Tool `CodeRetriever`\n\n````\nfn main() {"));
+        // The empty prompt is not exported.
+        assert!(md.ends_with("Elle affiche <b>```</b>.\n"));
+    }
+
+    #[test]
+    fn export_html() {
+        let page = html(&ChatMetadata::default(), &messages());
+        assert!(page.contains("<div class=\"content\">Elle affiche &lt;b&gt;```&lt;/b&gt;.</div>"));
+        assert!(page.contains("<summary>Tool call <code>CodeRetriever</code></summary>\n<pre>{&quot;file&quot;"));
+        assert_eq!(page.matches("<section").count(), 4);
+        assert!(page.ends_with("</body>\n</html>\n"));
+    }
+}
@@ -0,0 +1,415 @@
+use serde_json::Value;
+
+use super::{ChatMetadata, MessageState, parser::build_chat};
+use crate::{
+    mistral::model::{Role, ToolCall, completion::Model, stream::Status, tools::FunctionCall},
+    notify::IntoNotification as _,
+};
+
+/// A conversation read from another format.
+#[derive(Default)]
+pub struct Imported {
+    pub metadata: ChatMetadata,
+    pub messages: Vec<MessageState>,
+}
+
+impl Imported {
+    /// From the content of the file, its format is given by the extension : `json`, `jsonl` or `md`.
+    pub fn parse(extension: &str, text: &str) -> crate::Result<Self> {
+        let mut imported = match extension {
+            "json" => from_json(text)?,
+            "jsonl" => from_jsonl(text)?,
+            "md" | "markdown" => from_markdown(text),
+            _ => return Err(format!("Can't import a `.{extension}` file, expected json, jsonl or md.").into_warn()),
+        };
+        if imported.messages.is_empty() {
+            return Err("No message found.".into_warn());
+        }
+        // The conversation continues with a prompt.
+        let last = imported.messages.last().unwrap();
+        if !matches!(last.message.role, Role::User) {
+            let prompt = last.next_prompt();
+            imported.messages.push(prompt);
+        }
+        Ok(imported)
+    }
+    /// Content of the `.chat` file.
+    pub fn into_chat(self) -> String {
+        build_chat(&self.metadata, self.messages)
+    }
+}
+
+fn completed(role: Role, content: String, model: &Model) -> MessageState {
+    let mut message = MessageState::default();
+    message.message.role = role;
+    message.message.content = content;
+    message.model = model.clone();
+    message.status = Status::Completed;
+    message
+}
+
+fn role(name: &str) -> Option<Role> {
+    match name.to_lowercase().as_str() {
+        "user" => Some(Role::User),
+        "system" | "developer" => Some(Role::System),
+        "assistant" => Some(Role::Assistant),
+        "tool" => Some(Role::Tool),
+        _ => None,
+    }
+}
+
+fn string(value: &Value, key: &str) -> Option<String> {
+    value.get(key)?.as_str().map(str::to_string)
+}
+
+/// Text of a content : a string, or chunks (`text` and `thinking` ones).
+fn content(value: &Value) -> (String, Option<String>) {
+    let Some(chunks) = value.as_array() else {
+        return (value.as_str().unwrap_or_default().to_string(), None);
+    };
+    let mut text = Vec::new();
+    let mut thinking = Vec::new();
+    for chunk in chunks {
+        match chunk.get("type").and_then(Value::as_str) {
+            Some("thinking") => thinking.push(content(&chunk["thinking"]).0),
+            _ => text.extend(string(chunk, "text")),
+        }
+    }
+    let thinking = (!thinking.is_empty()).then(|| thinking.join("\n"));
+    (text.join("\n"), thinking)
+}
+
+/// A message of the API, `None` for an unknown role.
+fn message(value: &Value, model: &Model) -> Option<MessageState> {
+    let role = role(value.get("role")?.as_str()?)?;
+    let (text, thinking) = content(&value["content"]);
+    let mut message = completed(role, text, model);
+    message.thinking = thinking;
+    message.message.name = string(value, "name");
+    message.message.tool_call_id = string(value, "tool_call_id");
+    let tool_calls: Vec<ToolCall> = value["tool_calls"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|tool_call| {
+            let function = &tool_call["function"];
+            ToolCall {
+                id: string(tool_call, "id"),
+                index: tool_call["index"].as_u64().map(|index| index as u32),
+                function: FunctionCall {
+                    name: string(function, "name").unwrap_or_default(),
+                    // A string for OpenAI, an object for some others.
+                    arguments: match &function["arguments"] {
+                        Value::String(arguments) => arguments.clone(),
+                        arguments => arguments.to_string(),
+                    },
+                },
+            }
+        })
+        .collect();
+    if !tool_calls.is_empty() {
+        message.message.tool_calls = Some(tool_calls);
+    }
+    Some(message)
+}
+
+fn model(request: &Value) -> Model {
+    request
+        .get("model")
+        .and_then(Value::as_str)
+        .and_then(Model::from_name)
+        .unwrap_or_default()
+}
+
+fn messages(values: &Value, model: &Model) -> Vec<MessageState> {
+    values
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|value| message(value, model))
+        .collect()
+}
+
+/// A `messages` array, or a request holding it.
+fn from_json(text: &str) -> crate::Result<Imported> {
+    let value: Value = serde_json::from_str(text)?;
+    let messages = match value.get("messages") {
+        Some(values) => messages(values, &model(&value)),
+        None => messages(&value, &Model::default()),
+    };
+    Ok(Imported {
+        messages,
+        ..Default::default()
+    })
+}
+
+/// A log of requests (the last one holds the whole conversation) and its answers, or a message by line.
+fn from_jsonl(text: &str) -> crate::Result<Imported> {
+    let mut imported = Imported::default();
+    let mut model = Model::default();
+    for (index, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let value: Value =
+            serde_json::from_str(line).map_err(|err| format!("Line {} : {err}", index + 1).into_warn())?;
+        if let Some(values) = value.get("messages") {
+            model = self::model(&value);
+            imported.messages = messages(values, &model);
+        }
+        // The answer logged with the request, or alone.
+        let answer = value
+            .pointer("/choices/0/message")
+            .or(value.pointer("/response/choices/0/message"));
+        let answer = match answer {
+            Some(answer) => Some(answer),
+            None if value.get("role").is_some() => Some(&value),
+            None => None,
+        };
+        imported
+            .messages
+            .extend(answer.and_then(|answer| message(answer, &model)));
+    }
+    Ok(imported)
+}
+
+/// A role heading as written by `:MistralChatExport md` : `## User`, `## System`, `## Assistant (model)`, `## Tool`
+/// or ``## Tool `name` ``.
+fn markdown_heading(line: &str, model: &Model) -> Option<MessageState> {
+    let heading = line.strip_prefix("## ")?;
+    let (name, rest) = heading.split_once(' ').unwrap_or((heading, ""));
+    let role = match name {
+        "User" => Role::User,
+        "System" => Role::System,
+        "Assistant" => Role::Assistant,
+        "Tool" => Role::Tool,
+        _ => return None,
+    };
+    let mut message = completed(role, String::new(), model);
+    match (&message.message.role, rest) {
+        (Role::User | Role::System | Role::Tool, "") => {}
+        (Role::Tool, rest) => {
+            let name = rest
+                .strip_prefix('`')?
+                .strip_suffix('`')
+                .filter(|name| !name.is_empty() && !name.contains('`'))?;
+            message.message.name = Some(name.to_string());
+        }
+        (Role::Assistant, rest) => {
+            let name = rest.strip_prefix('(')?.strip_suffix(')')?;
+            message.model.replace_from_str(name);
+        }
+        _ => return None,
+    }
+    Some(message)
+}
+
+/// A transcript with a `## Role` heading by message, as written by `:MistralChatExport md`. A heading is only
+/// read after a blank line and outside of a code block, so a message may contain the same lines.
+fn from_markdown(text: &str) -> Imported {
+    let mut imported = Imported::default();
+    let model = Model::default();
+    let mut description = Vec::new();
+    // Lines of the current message.
+    let mut lines: Vec<&str> = Vec::new();
+    let flush = |lines: &mut Vec<&str>, messages: &mut Vec<MessageState>| {
+        if let Some(message) = messages.last_mut() {
+            markdown_message(message, std::mem::take(lines));
+        }
+    };
+    // Backticks of the opened code block.
+    let mut fence: Option<&str> = None;
+    let mut after_blank = true;
+    for line in text.lines() {
+        let heading = match fence {
+            None if after_blank => markdown_heading(line, &model),
+            _ => None,
+        };
+        after_blank = line.trim().is_empty();
+        let backticks = &line[..line.len() - line.trim_start_matches('`').len()];
+        match fence {
+            Some(opened) if backticks.len() >= opened.len() && line.trim_end() == backticks => fence = None,
+            None if backticks.len() >= 3 => fence = Some(backticks),
+            _ => (),
+        }
+        match heading {
+            Some(message) => {
+                flush(&mut lines, &mut imported.messages);
+                imported.messages.push(message);
+            }
+            None if imported.messages.is_empty() => match line.strip_prefix("# ") {
+                Some(name) => imported.metadata.name = name.trim().to_string(),
+                None => description.extend(line.strip_prefix('>').map(str::trim)),
+            },
+            None => lines.push(line),
+        }
+    }
+    flush(&mut lines, &mut imported.messages);
+    imported.metadata.description = description.join(" ");
+    link_tool_calls(&mut imported.messages);
+    imported
+}
+
+/// Reasoning quote, tool calls and fenced tool result of a message, the rest is its content.
+fn markdown_message(message: &mut MessageState, lines: Vec<&str>) {
+    let mut lines = lines.into_iter().peekable();
+    let mut content = Vec::new();
+    let mut tool_calls = Vec::new();
+    while let Some(line) = lines.next() {
+        if line == "> *Reasoning*" {
+            let mut thinking = Vec::new();
+            while let Some(line) = lines.next_if(|line| line.starts_with('>')) {
+                thinking.push(
+                    line.trim_start_matches('>')
+                        .strip_prefix(' ')
+                        .unwrap_or_default(),
+                );
+            }
+            message.thinking = Some(thinking.join("\n").trim().to_string());
+        } else if let Some(name) = line
+            .strip_prefix("**Tool call** `")
+            .and_then(|name| name.strip_suffix('`'))
+        {
+            while lines.next_if(|line| line.trim().is_empty()).is_some() {}
+            let arguments = match lines.next_if(|line| line.starts_with("```")) {
+                Some(fence) => {
+                    let fence = fence.trim_end_matches("json");
+                    let arguments: Vec<&str> = lines.by_ref().take_while(|line| *line != fence).collect();
+                    arguments.join("\n")
+                }
+                None => String::new(),
+            };
+            tool_calls.push(ToolCall {
+                function: FunctionCall {
+                    name: name.to_string(),
+                    arguments,
+                },
+                ..Default::default()
+            });
+        } else {
+            content.push(line);
+        }
+    }
+    let mut content = content.join("\n").trim().to_string();
+    if matches!(message.message.role, Role::Tool)
+        && let Some((fence, rest)) = content.split_once('\n')
+        && fence.starts_with("```")
+        && let Some(result) = rest.strip_suffix(fence)
+    {
+        content = result.trim_end_matches('\n').to_string();
+    }
+    message.message.content = content;
+    if !tool_calls.is_empty() {
+        message.message.tool_calls = Some(tool_calls);
+    }
+}
+
+/// A transcript has no tool call id : the tool results answer the calls in order.
+fn link_tool_calls(messages: &mut [MessageState]) {
+    let mut next_id = 0;
+    let mut pending = std::collections::VecDeque::new();
+    for message in messages {
+        for tool_call in message.message.tool_calls.iter_mut().flatten() {
+            next_id += 1;
+            // The API expects 9 alphanumeric chars.
+            let id = tool_call.id.get_or_insert(format!("{next_id:09}"));
+            pending.push_back(id.clone());
+        }
+        if matches!(message.message.role, Role::Tool) && message.message.tool_call_id.is_none() {
+            message.message.tool_call_id = pending.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn import_json() {
+        let text = r#"{"model": "codestral-latest", "messages": [
+            {"role": "system", "content": "Tu es un développeur."},
+            {"role": "user", "content": [{"type": "text", "text": "Lis main.rs"}]},
+            {"role": "assistant", "content": "Je lis le fichier.", "tool_calls": [
+                {"id": "abcdefghi", "function": {"name": "CodeRetriever", "arguments": {"file": "src/main.rs"}}}
+            ]},
+            {"role": "tool", "name": "CodeRetriever", "tool_call_id": "abcdefghi", "content": "fn main() {}"},
+            {"role": "critic", "content": "ignored"}
+        ]}"#;
+        let imported = Imported::parse("json", text).unwrap();
+        let messages = &imported.messages;
+        // With the prompt to continue.
+        assert_eq!(messages.len(), 5);
+        assert_eq!(messages[1].message.content, "Lis main.rs");
+        assert_eq!(messages[1].model.to_string(), "Codestral Latest");
+        assert_eq!(messages[2].message.content, "Je lis le fichier.");
+        let tool_calls = messages[2].message.tool_calls.as_ref().unwrap();
+        assert_eq!(tool_calls[0].function.arguments, r#"{"file":"src/main.rs"}"#);
+        assert_eq!(messages[3].message.tool_call_id.as_deref(), Some("abcdefghi"));
+        assert!(matches!(messages[4].message.role, Role::User));
+        assert!(messages[4].message.content.is_empty());
+    }
+
+    #[test]
+    fn import_jsonl() {
+        let text = concat!(
+            r#"{"model": "mistral-large-latest", "messages": [{"role": "user", "content": "2 + 2 ?"}]}"#,
+            "\n",
+            r#"{"messages": [{"role": "user", "content": "2 + 2 ?"}, {"role": "assistant", "content": "4"}, {"role": "user", "content": "Et 3 + 3 ?"}], "choices": [{"message": {"role": "assistant", "content": "6"}}]}"#,
+            "\n",
+        );
+        let imported = Imported::parse("jsonl", text).unwrap();
+        let contents: Vec<&str> = imported
+            .messages
+            .iter()
+            .map(|message| message.message.content.as_str())
+            .collect();
+        assert_eq!(contents, ["2 + 2 ?", "4", "Et 3 + 3 ?", "6", ""]);
+        assert!(Imported::parse("jsonl", "{\n").is_err());
+    }
+
+    #[test]
+    fn import_markdown() {
+        let text = "# Revue\n\n> Relire le code.\n\n## User\n\nQue fait `main` ?\n\n## Assistant (Large Latest)\n\n\
+                    > *Reasoning*\n>\n> Lire le fichier.\n\n**Tool call** `CodeRetriever`\n\n```json\n\
+                    {\"file\": \"src/main.rs\"}\n```\n\n## Tool `CodeRetriever`\n\n````\nprintln!(\"```\");\n````\n\n\
+                    ## Assistant (Large Latest)\n\n## Un titre de la réponse\n\nElle affiche.\n";
+        let imported = Imported::parse("md", text).unwrap();
+        assert_eq!(imported.metadata.name, "Revue");
+        assert_eq!(imported.metadata.description, "Relire le code.");
+        let messages = &imported.messages;
+        assert_eq!(messages.len(), 5);
+        assert_eq!(messages[0].message.content, "Que fait `main` ?");
+        assert_eq!(messages[1].model.to_string(), "Large Latest");
+        assert_eq!(messages[1].thinking.as_deref(), Some("Lire le fichier."));
+        let tool_call = &messages[1].message.tool_calls.as_ref().unwrap()[0];
+        assert_eq!(tool_call.function.arguments, "{\"file\": \"src/main.rs\"}");
+        assert_eq!(tool_call.id.as_deref(), Some("000000001"));
+        assert_eq!(messages[2].message.name.as_deref(), Some("CodeRetriever"));
+        assert_eq!(messages[2].message.tool_call_id.as_deref(), Some("000000001"));
+        assert_eq!(messages[2].message.content, "println!(\"```\");");
+        // A heading which is not a role stays in the answer.
+        assert_eq!(
+            messages[3].message.content,
+            "## Un titre de la réponse\n\nElle affiche."
+        );
+    }
+
+    #[test]
+    fn import_markdown_headings_in_content() {
+        let answer = "Voici :\n\n```markdown\n## User\n\nBonjour\n```\n\n## User guide\nTexte\n## Tool";
+        let text = format!(
+            "## User\n\nÉcris un transcript.\n\n## Assistant (Large Latest)\n\n{answer}\n\n\
+             **Tool call** `CodeRetriever`\n\n```json\n{{}}\n```\n\n## Tool `CodeRetriever`\n\n```\n## System\n```\n"
+        );
+        let imported = Imported::parse("md", &text).unwrap();
+        let messages = &imported.messages;
+        assert_eq!(messages.len(), 4);
+        // The text of the answer is kept with its tool call.
+        assert_eq!(messages[1].message.content, answer);
+        let tool_call = &messages[1].message.tool_calls.as_ref().unwrap()[0];
+        assert_eq!(tool_call.function.name, "CodeRetriever");
+        assert!(matches!(messages[2].message.role, Role::Tool));
+        assert_eq!(messages[2].message.content, "## System");
+    }
+}
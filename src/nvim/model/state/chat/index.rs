@@ -0,0 +1,194 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::{Arc, LazyLock, Mutex},
+    time::SystemTime,
+};
+
+use super::{
+    ChatMetadata, MessageState,
+    parser::{TAG_CHAT, migrate_chat, parse_chat, set_tag_args},
+};
+use crate::{
+    mistral::model::{Role, completion::Model},
+    notify::IntoNotification as _,
+    nvim::model::RowRange,
+};
+
+/// A `.chat` file of the project, read without opening it in a buffer.
+pub struct ChatFile {
+    pub path: PathBuf,
+    pub metadata: ChatMetadata,
+    pub messages: Vec<MessageState>,
+    /// Rows of the header, then of each message (like `ChatState::positions`).
+    pub positions: Vec<RowRange>,
+    pub modified: Option<std::time::SystemTime>,
+}
+
+impl ChatFile {
+    pub fn read(path: &Path) -> crate::Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+        let (metadata, messages, positions) = parse_chat(&content)?;
+        let modified = std::fs::metadata(path)
+            .and_then(|meta| meta.modified())
+            .ok();
+        Ok(Self {
+            path: path.to_path_buf(),
+            metadata,
+            messages,
+            positions,
+            modified,
+        })
+    }
+    /// Model of the last answer, or of the last message when nothing has been answered yet.
+    pub fn last_model(&self) -> Option<&Model> {
+        let is_answer = |msg: &&MessageState| matches!(msg.message.role, Role::Assistant);
+        self.messages
+            .iter()
+            .rev()
+            .find(is_answer)
+            .or(self.messages.last())
+            .map(|msg| &msg.model)
+    }
+    /// Seconds since the epoch of the last modification.
+    pub fn modified_secs(&self) -> Option<u64> {
+        let modified = self.modified?.duration_since(std::time::UNIX_EPOCH).ok()?;
+        Some(modified.as_secs())
+    }
+    /// Changes the `name` of the `<CHAT/>` tag line in place, its other attributes and the messages are kept as
+    /// they are (migrated if the file is an older version).
+    pub fn rename(path: &Path, name: &str) -> crate::Result<()> {
+        let content = std::fs::read_to_string(path)?;
+        let mut lines: Vec<String> = content.lines().map(String::from).collect();
+        let Some(tag_line) = lines.first().cloned() else {
+            return Err(format!("`{}` is empty.", path.display()).into_warn());
+        };
+        let tag_line = migrate_chat(&tag_line, &mut lines[1..]).unwrap_or(tag_line);
+        if ChatMetadata::from_tag_line(&tag_line).is_none() {
+            return Err(format!("`{}` does not start by the {TAG_CHAT} tag.", path.display()).into_warn());
+        }
+        lines[0] = set_tag_args(&tag_line, &[("name", name.to_string())]);
+        std::fs::write(path, lines.join("\n") + "\n")?;
+        Ok(())
+    }
+}
+
+/// Directories of dependencies and build outputs, not walked.
+const SKIPPED_DIRS: [&str; 3] = ["target", "node_modules", "vendor"];
+
+/// The `.chat` files under `root`. The hidden directories, `SKIPPED_DIRS` and the symbolic links to directories
+/// (which could loop) are skipped.
+pub fn chat_files(root: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut dirs = vec![root.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            let path = entry.path();
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            if file_type.is_dir() {
+                if !name.starts_with('.') && !SKIPPED_DIRS.contains(&name.as_ref()) {
+                    dirs.push(path);
+                }
+            } else if file_type.is_symlink() && path.is_dir() {
+                continue;
+            } else if path.extension().is_some_and(|ext| ext == "chat") {
+                files.push(path);
+            }
+        }
+    }
+    files
+}
+
+/// The chats already parsed by `index`, by path, with the modification time and the size of the file read.
+static CACHE: LazyLock<Mutex<HashMap<PathBuf, (SystemTime, u64, Arc<ChatFile>)>>> = LazyLock::new(Default::default);
+
+/// The readable chats under `root`, the most recently modified first. Only the files modified since the last
+/// call are parsed again.
+pub fn index(root: &Path) -> Vec<Arc<ChatFile>> {
+    let files = chat_files(root);
+    let mut cache = CACHE.lock().unwrap();
+    let mut chats: Vec<Arc<ChatFile>> = files
+        .iter()
+        .filter_map(|path| {
+            let meta = std::fs::metadata(path).ok()?;
+            let key = (meta.modified().ok()?, meta.len());
+            if let Some((modified, len, chat)) = cache.get(path)
+                && (*modified, *len) == key
+            {
+                return Some(Arc::clone(chat));
+            }
+            cache.remove(path);
+            let chat = Arc::new(ChatFile::read(path).ok()?);
+            cache.insert(path.clone(), (key.0, key.1, Arc::clone(&chat)));
+            Some(chat)
+        })
+        .collect();
+    // The files removed from the project.
+    let files: HashSet<&PathBuf> = files.iter().collect();
+    cache.retain(|path, _| !path.starts_with(root) || files.contains(path));
+    chats.sort_by(|a, b| b.modified.cmp(&a.modified));
+    chats
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{super::parser::build_chat, *};
+
+    #[test]
+    fn chat_index() {
+        let root = std::env::temp_dir().join(format!("mistral_chat_index_{}", std::process::id()));
+        let hidden = root.join(".git");
+        std::fs::create_dir_all(&hidden).unwrap();
+        std::fs::create_dir_all(root.join("notes")).unwrap();
+        let metadata = ChatMetadata {
+            name: "Revue".to_string(),
+            ..Default::default()
+        };
+        let mut question = MessageState::default();
+        question.model = Model::MistralTinyLatest;
+        let mut answer = question.clone();
+        answer.message.role = Role::Assistant;
+        answer.model = Model::MistralLargeLatest;
+        let content = build_chat(&metadata, [question.clone(), answer, question.next_prompt()]);
+        // An attribute of a newer version.
+        let content = content.replacen("/>", r#" color="bleu"/>"#, 1);
+        let path = root.join("notes").join("revue.chat");
+        std::fs::write(&path, &content).unwrap();
+        std::fs::write(hidden.join("ignored.chat"), &content).unwrap();
+        std::fs::write(root.join("broken.chat"), "Pas un chat").unwrap();
+        std::fs::create_dir_all(root.join("node_modules")).unwrap();
+        std::fs::write(root.join("node_modules").join("ignored.chat"), &content).unwrap();
+        // A link to a parent directory is not followed.
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(&root, root.join("notes").join("boucle")).unwrap();
+
+        assert_eq!(chat_files(&root).len(), 2);
+        let chats = index(&root);
+        assert_eq!(chats.len(), 1);
+        let chat = &chats[0];
+        assert_eq!(chat.path, path);
+        assert_eq!(chat.messages.len(), 3);
+        assert_eq!(chat.last_model().map(Model::to_string).as_deref(), Some("Large Latest"));
+        assert!(chat.modified_secs().is_some());
+        // Read again only once modified.
+        assert!(Arc::ptr_eq(chat, &index(&root)[0]));
+
+        ChatFile::rename(&path, "Revue \"finale\"").unwrap();
+        let renamed = &index(&root)[0];
+        assert_eq!(renamed.metadata.name, "Revue \"finale\"");
+        assert_eq!(renamed.messages.len(), 3);
+        let header = std::fs::read_to_string(&path).unwrap();
+        assert!(header.lines().next().unwrap().ends_with(r#" color="bleu"/>"#));
+        std::fs::remove_file(&path).unwrap();
+        assert!(index(&root).is_empty());
+        assert!(!CACHE.lock().unwrap().contains_key(&path));
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
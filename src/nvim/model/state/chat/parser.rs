@@ -1,9 +1,174 @@
 use super::*;
+pub(super) use chat_format::{
+    ESCAPE, escape_quote_arg, escape_whitespace, unescape_quote_arg, unescape_whitespace, write_arg,
+};
 
 pub(super) const TAG_CHAT: &'static str = "CHAT";
 const TAG_MESSAGE: &'static str = "MESSAGE";
 const TAG_TOOL_CALL: &'static str = "TOOLCALL";
 const TAG_FILE: &'static str = "FILE";
+const TAG_OCR: &'static str = "OCR";
+pub(super) const TAG_THINKING: &'static str = "THINKING";
+const TAG_VERSION: &'static str = "VERSION";
+
+/// Version of the `.chat` format written by the plugin, the grammar is in `DEV.md`.
+pub(super) const CHAT_VERSION: u32 = 3;
+/// Upgrades of the `<CHAT/>` attributes and of the following lines, the n-th one migrates from the version n
+/// to n + 1.
+const MIGRATIONS: [fn(&mut Vec<(String, String)>, &mut [String]); CHAT_VERSION as usize] =
+    [migrate_v0, migrate_v1, migrate_v2];
+
+/// Version of a `<CHAT/>` tag line, the files written before the versioning are the version 0.
+pub(super) fn chat_version(tag_line: &String) -> u32 {
+    let mut version = 0;
+    parse_tag_line(tag_line, |key, val, _cols| {
+        if key == "version" {
+            version = str::parse(&unescape_quote_arg(&val)).unwrap_or(0);
+        }
+    });
+    version
+}
+/// Upgrade a chat to `CHAT_VERSION` : returns the new `<CHAT/>` tag line and migrates the `lines` following it,
+/// `None` when it is already up to date (or newer). Unknown attributes are kept as is.
+pub(super) fn migrate_chat(tag_line: &String, lines: &mut [String]) -> Option<String> {
+    let version = chat_version(tag_line) as usize;
+    if version >= MIGRATIONS.len() {
+        return None;
+    }
+    let mut args = Vec::new();
+    parse_tag_line(tag_line, |key, val, _cols| {
+        if key != "version" {
+            args.push((key, val))
+        }
+    });
+    for migration in &MIGRATIONS[version..] {
+        migration(&mut args, lines);
+    }
+    let args: String = args
+        .into_iter()
+        .map(|(key, val)| format!(r#" {key}="{val}""#))
+        .collect();
+    Some(format!(r#"<{TAG_CHAT} version="{CHAT_VERSION}"{args}/>"#))
+}
+/// The first chats named their attributes `role`, `status` and `model` instead of `name`, `usage` and
+/// `description`.
+fn migrate_v0(args: &mut Vec<(String, String)>, _lines: &mut [String]) {
+    for (legacy, key) in [("role", "name"), ("status", "usage"), ("model", "description")] {
+        if args.iter().any(|(k, _)| k == key) {
+            continue;
+        }
+        if let Some(arg) = args.iter_mut().find(|(k, _)| k == legacy) {
+            arg.0 = key.to_string();
+        }
+    }
+}
+/// Content lines looking like a tag are escaped since the version 2, the ones which already started by a `\`
+/// keep it.
+fn migrate_v1(_args: &mut Vec<(String, String)>, lines: &mut [String]) {
+    for line in lines {
+        // The tags of the version 2.
+        if line.starts_with(ESCAPE) && is_tag_like_among(line, &TAGS[..6]) {
+            line.insert(0, ESCAPE);
+        }
+    }
+}
+/// The `<VERSION>` sections came with the version 3, the content lines looking like them are escaped.
+fn migrate_v2(_args: &mut Vec<(String, String)>, lines: &mut [String]) {
+    for line in lines {
+        if is_tag_like_among(line, &[TAG_VERSION]) {
+            line.insert(0, ESCAPE);
+        }
+    }
+}
+
+/// Tags read at the start of a line.
+const TAGS: [&'static str; 7] = [
+    TAG_CHAT,
+    TAG_MESSAGE,
+    TAG_TOOL_CALL,
+    TAG_FILE,
+    TAG_OCR,
+    TAG_THINKING,
+    TAG_VERSION,
+];
+
+/// Starts by `<TAG` or `</TAG`, after any number of `\`.
+fn is_tag_like(line: &str) -> bool {
+    is_tag_like_among(line, &TAGS)
+}
+fn is_tag_like_among(line: &str, tags: &[&str]) -> bool {
+    let line = line.trim_start_matches(ESCAPE);
+    let name = line.strip_prefix("</").or(line.strip_prefix('<'));
+    name.is_some_and(|name| tags.iter().any(|tag| name.starts_with(tag)))
+}
+/// The start of a line which could still become tag like.
+fn may_be_tag_like(start: &str) -> bool {
+    let start = start.trim_start_matches(ESCAPE);
+    TAGS.iter()
+        .any(|tag| format!("<{tag}").starts_with(start) || format!("</{tag}").starts_with(start))
+}
+/// A content line looking like a tag gets one more leading `\`, so that it's not read as the structure of the chat.
+pub(super) fn escape_content_line(line: &str) -> String {
+    if is_tag_like(line) {
+        format!("{ESCAPE}{line}")
+    } else {
+        line.to_string()
+    }
+}
+/// Reverse of `escape_content_line`.
+pub(super) fn unescape_content_line(line: &str) -> &str {
+    match line.strip_prefix(ESCAPE) {
+        Some(unescaped) if is_tag_like(unescaped) => unescaped,
+        _ => line,
+    }
+}
+pub(super) fn escape_content(content: &str) -> impl Iterator<Item = String> {
+    content.split('\n').map(escape_content_line)
+}
+
+/// Escape the lines of a streamed content, the start of a line is held until it can't look like a tag anymore.
+#[derive(Default)]
+pub(super) struct ContentEscaper {
+    /// Start of the current line, not written yet.
+    pending: String,
+    /// The current line is written as it comes.
+    decided: bool,
+}
+impl ContentEscaper {
+    /// Same shape as the streamed chunk : the first line continues the current one.
+    pub(super) fn push(&mut self, chunk: Vec<String>) -> Vec<String> {
+        let mut lines = Vec::with_capacity(chunk.len());
+        for (i, part) in chunk.into_iter().enumerate() {
+            if i > 0 {
+                // The previous line is complete.
+                if let Some(last) = lines.last_mut() {
+                    *last += self.flush().as_str();
+                }
+                self.decided = false;
+            }
+            if self.decided {
+                lines.push(part);
+                continue;
+            }
+            self.pending.push_str(&part);
+            if is_tag_like(&self.pending) {
+                self.decided = true;
+                lines.push(format!("{ESCAPE}{}", std::mem::take(&mut self.pending)));
+            } else if may_be_tag_like(&self.pending) {
+                lines.push(String::new());
+            } else {
+                self.decided = true;
+                lines.push(std::mem::take(&mut self.pending));
+            }
+        }
+        lines
+    }
+    /// The held start of the line, once the content is complete.
+    pub(super) fn flush(&mut self) -> String {
+        self.decided = false;
+        std::mem::take(&mut self.pending)
+    }
+}
 
 pub(super) fn is_self_tag_line(line: &String, tag: &'static str) -> bool {
     line.starts_with(&format!("<{tag}")) && line.ends_with("/>")
@@ -19,46 +184,88 @@ pub(super) fn option_to_arg<T: ToString>(value: &Option<T>) -> String {
         .as_ref()
         .map_or("".to_string(), |n| n.to_string())
 }
-const ESCAPE: char = '\\';
-pub(super) fn escape_quote_arg(value: String) -> String {
-    let acc = String::with_capacity(value.len());
-    value.chars().fold(acc, |mut acc, c| {
-        match c {
-            c if matches!(c, ESCAPE | '"') => {
-                acc.push(ESCAPE);
-                acc.push(c)
-            }
-            c => acc.push(c),
-        }
-        acc
-    })
-}
-pub(super) fn unescape_quote_arg(value: &String) -> String {
-    let acc = String::with_capacity(value.len());
-    let mut last_is_escape_char = false;
-    value.chars().fold(acc, |mut acc, c| {
-        if last_is_escape_char {
-            if matches!(c, ESCAPE | '"') {
-                acc.push(c)
-            } else {
-                // Act like it was, previously, not escaped correctly
-                acc.push(ESCAPE);
-                acc.push(c)
-            }
-            last_is_escape_char = false;
-        } else {
-            match c {
-                ESCAPE => last_is_escape_char = true,
-                c => acc.push(c),
-            }
+/// The tag line with the given attributes replaced in place, or added at the end when missing. The other
+/// attributes, even the unknown ones, are kept as they are.
+pub(super) fn set_tag_args(tag_line: &String, args: &[(&str, String)]) -> String {
+    let mut missing: Vec<&(&str, String)> = args.iter().collect();
+    let mut updates = Vec::new();
+    parse_tag_line(tag_line, |key, _val, cols| {
+        if let Some(index) = missing.iter().position(|(k, _)| *k == key) {
+            let (_, value) = missing.remove(index);
+            updates.push((cols, escape_quote_arg(value.clone())));
+        }
+    });
+    // The columns count chars.
+    let byte = |col: model::Col| {
+        tag_line
+            .char_indices()
+            .nth(col.0)
+            .map_or(tag_line.len(), |(i, _)| i)
+    };
+    let mut line = tag_line.clone();
+    for (cols, value) in updates.into_iter().rev() {
+        line.replace_range(byte(cols.start)..byte(cols.end), &value);
+    }
+    if let Some(head) = line.strip_suffix("/>") {
+        let mut head = head.trim_end().to_string();
+        for (key, value) in missing {
+            write_arg(&mut head, key, value);
         }
-        acc
-    })
+        line = head + "/>";
+    }
+    line
 }
-
-pub(super) fn write_arg(args: &mut String, key: &str, value: impl std::fmt::Display) {
-    let value = escape_quote_arg(value.to_string());
-    args.push_str(&format!(r#" {key}="{value}""#));
+pub(super) fn build_tag_chat_line(metadata: &ChatMetadata) -> String {
+    let args = &mut String::new();
+    write_arg(args, "version", CHAT_VERSION);
+    write_arg(args, "name", &metadata.name);
+    write_arg(args, "usage", &metadata.usage);
+    write_arg(args, "description", &metadata.description);
+    if metadata.keep_thinking {
+        write_arg(args, "thinking", "keep");
+    }
+    if let Some(fork) = &metadata.fork {
+        write_arg(args, "fork", fork);
+    }
+    format!("<{TAG_CHAT}{args}/>")
+}
+/// Content of a whole `.chat` file.
+pub(super) fn build_chat(metadata: &ChatMetadata, messages: impl IntoIterator<Item = MessageState>) -> String {
+    let mut lines = vec![build_tag_chat_line(metadata)];
+    for message in messages {
+        lines.extend(build_tag_message_lines(message));
+    }
+    lines.join("\n") + "\n"
+}
+/// Metadata, messages and positions of a whole `.chat` file, migrated if it is an older version. The `<FILE/>`
+/// and `<OCR/>` tags are skipped, neither read nor added to the messages.
+pub(super) fn parse_chat(content: &str) -> crate::Result<(ChatMetadata, Vec<MessageState>, Vec<RowRange>)> {
+    let mut lines: Vec<String> = content.lines().map(String::from).collect();
+    let Some(first_line) = lines.first().cloned() else {
+        return Err(format!("Empty file : A Chat file must start by the {TAG_CHAT} tag.").into_warn());
+    };
+    if !is_self_tag_line(&first_line, TAG_CHAT) {
+        return Err(format!("A Chat file must start by the {TAG_CHAT} tag.").into_warn());
+    }
+    let tag_line = migrate_chat(&first_line, &mut lines[1..]).unwrap_or(first_line);
+    let mut metadata = ChatMetadata::default();
+    parse_tag_line(&tag_line, |key, val, _cols| metadata_setter(key, val, &mut metadata));
+    let args = GeneratorArgs::full(Row(lines.len() - 1));
+    let mut messages_generator = MsgGen::new(&args, 0);
+    let mut positions_generator = PosGen::new(&args, 0);
+    let mut lines = lines.into_iter().enumerate().skip(1);
+    loop {
+        let line = lines.next().map(|(row, line)| (Row(row), line));
+        messages_generator.next_line_option(&line)?;
+        positions_generator.next_line_option(&line)?;
+        if line.is_none() {
+            break;
+        }
+    }
+    let (mut messages, mut positions) = (Vec::new(), Vec::new());
+    messages_generator.replace(&mut messages, 1)?;
+    positions_generator.replace(&mut positions, 0)?;
+    Ok((metadata, messages, positions))
 }
 pub(super) fn build_tag_message_lines(message: MessageState) -> Vec<String> {
     let MessageState {
@@ -66,6 +273,10 @@ pub(super) fn build_tag_message_lines(message: MessageState) -> Vec<String> {
         status,
         usage,
         mode,
+        thinking,
+        versions,
+        version,
+        date,
         message:
             mistral::model::Message {
                 role,
@@ -98,7 +309,44 @@ pub(super) fn build_tag_message_lines(message: MessageState) -> Vec<String> {
     if let Some(max_tokens) = max_tokens {
         write_arg(args, "max_tokens", max_tokens);
     }
+    if !versions.is_empty() {
+        write_arg(args, "version", version);
+    }
+    if let Some(date) = date {
+        write_arg(args, "date", date);
+    }
+    // The blank lines and spaces ending the content would be read as the ones before the next tag.
+    let text = content.trim_end();
+    if text.len() < content.len() {
+        write_arg(args, "trailing", escape_whitespace(&content[text.len()..]));
+    }
     let mut lines = vec!["".to_string(), "".to_string(), format!(r#"<{TAG_MESSAGE}{args}/>"#)];
+    if let Some(thinking) = thinking {
+        lines.push(format!("<{TAG_THINKING}>"));
+        lines.extend(escape_content(&thinking));
+        lines.push(format!("</{TAG_THINKING}>"));
+    }
+    for MessageVersion {
+        model,
+        usage,
+        content,
+        thinking,
+    } in versions
+    {
+        let args = &mut String::new();
+        write_arg(args, "model", model);
+        write_arg(args, "usage", usage);
+        lines.push(format!("<{TAG_VERSION}{args}>"));
+        if let Some(thinking) = thinking {
+            lines.push(format!("<{TAG_THINKING}>"));
+            lines.extend(escape_content(&thinking));
+            lines.push(format!("</{TAG_THINKING}>"));
+        }
+        lines.extend(escape_content(&content));
+        lines.push(format!("</{TAG_VERSION}>"));
+    }
+    // The tool calls follow the content, as when they are streamed.
+    lines.extend(escape_content(text));
     if let Some(tool_calls) = tool_calls {
         lines.extend(
             tool_calls
@@ -106,7 +354,6 @@ pub(super) fn build_tag_message_lines(message: MessageState) -> Vec<String> {
                 .flat_map(|tc| build_tag_tool_call_lines(&tc)),
         );
     }
-    lines.extend(content.split('\n').map(|s| s.to_string()));
     lines
 }
 pub(super) fn build_tag_tool_call_lines(tool_call: &mistral::model::ToolCall) -> Vec<String> {
@@ -129,7 +376,7 @@ pub(super) fn build_tag_tool_call_lines(tool_call: &mistral::model::ToolCall) ->
         "".to_string(),
         "```json".to_string(),
     ]);
-    lines.extend(function.arguments.split('\n').map(|s| s.to_string()));
+    lines.extend(escape_content(&function.arguments));
     lines.extend(["```".to_string(), format!("</{TAG_TOOL_CALL}>")]);
     lines
 }
@@ -141,6 +388,8 @@ pub(super) struct GeneratorArgs {
     pub until_end: bool,
     pub rows_range: RowRange,
     pub current_len: usize,
+    /// `<FILE/>` and `<OCR/>` add their content to the message, otherwise they are skipped.
+    pub expand_includes: bool,
 }
 impl GeneratorArgs {
     pub(super) fn new(
@@ -177,8 +426,22 @@ impl GeneratorArgs {
             until_end,
             current_len,
             rows_range,
+            expand_includes: true,
         })
     }
+    /// The whole content of a file, read without a buffer. Its includes are not read : only the lines of the
+    /// chat are listed and searched.
+    pub(super) fn full(last_row: Row) -> Self {
+        Self {
+            start: (0, Row::MIN),
+            end: (0, last_row),
+            from_begin: true,
+            until_end: true,
+            rows_range: RowRange::FULL,
+            current_len: 0,
+            expand_includes: false,
+        }
+    }
     #[inline]
     fn is_full_range(&self) -> bool {
         self.from_begin && self.until_end
@@ -315,6 +578,12 @@ pub(super) struct MsgGen<'a> {
     nb_messages: usize,
     current_message: MessageState,
     tool_call_generator: Option<ToolCallGen<'a>>,
+    /// End of the content of the current message, from its `trailing` attribute.
+    trailing: String,
+    in_thinking: bool,
+    in_version: bool,
+    /// In the `<THINKING>` section of a `<VERSION>`.
+    in_version_thinking: bool,
 }
 
 impl<'a> MsgGen<'a> {
@@ -326,10 +595,49 @@ impl<'a> MsgGen<'a> {
             nb_messages: 0,
             current_message: MessageState::default(),
             tool_call_generator: None,
+            trailing: String::new(),
+            in_thinking: false,
+            in_version: false,
+            in_version_thinking: false,
         }
     }
 }
+/// Each line of a section is read with its line break, the one before the closing tag is not in the text.
+fn strip_line_break(text: &mut String) {
+    if text.ends_with('\n') {
+        text.pop();
+    }
+}
+
 impl<'a> MsgGen<'a> {
+    /// Push the current message, without the blank lines at the end of its content : the ones written by the
+    /// plugin are in its `trailing` attribute. The sections not closed (still streaming, or edited by hand) are
+    /// trimmed. A `version` edited by hand is kept among the versions.
+    fn finish_message(&mut self) {
+        let mut message = std::mem::take(&mut self.current_message);
+        let trailing = std::mem::take(&mut self.trailing);
+        let in_thinking = std::mem::take(&mut self.in_thinking);
+        let in_version = std::mem::take(&mut self.in_version);
+        self.in_version_thinking = false;
+        // Lines before the first message tag.
+        if self.nb_messages == 0 {
+            return;
+        }
+        let content = &mut message.message.content;
+        content.truncate(content.trim_end().len());
+        content.push_str(&trailing);
+        if let Some(thinking) = message.thinking.as_mut().filter(|_| in_thinking) {
+            *thinking = thinking.trim_end().to_string();
+        }
+        if let Some(version) = message.versions.last_mut().filter(|_| in_version) {
+            version.content = version.content.trim_end().to_string();
+            if let Some(thinking) = version.thinking.as_mut() {
+                *thinking = thinking.trim_end().to_string();
+            }
+        }
+        message.version = message.version.min(message.versions.len());
+        self.messages.push(message);
+    }
     fn take_tool_calls(&mut self, line_nb: &Row, line: &String) -> crate::Result<()> {
         let Some(mut tc_gen) = self.tool_call_generator.take() else {
             return Ok(());
@@ -343,8 +651,6 @@ impl<'a> MsgGen<'a> {
                 if !tool_calls.is_empty() {
                     self.current_message.message.tool_calls = Some(tool_calls);
                     self.current_message.tool_calls_positions = Some(positions);
-                    // Can't have both tool calls and content, so let's clear it up.
-                    self.current_message.message.content.clear();
                 }
                 Ok(())
             }
@@ -360,6 +666,55 @@ impl<'a> Generator for MsgGen<'a> {
     }
     fn next_line_state(&mut self, line_nb: &Row, line: &String) -> crate::Result<GeneratorState> {
         let is_message_tag = is_self_tag_line(&line, TAG_MESSAGE);
+        if self.in_thinking {
+            if is_close_tag_line(&line, TAG_THINKING) {
+                self.in_thinking = false;
+                if let Some(position) = self.current_message.thinking_position.as_mut() {
+                    position.end = line_nb.clone();
+                }
+                if let Some(thinking) = self.current_message.thinking.as_mut() {
+                    strip_line_break(thinking);
+                }
+                return Ok(GeneratorState::TagClosed);
+            } else if !is_message_tag {
+                let thinking = self.current_message.thinking.get_or_insert_default();
+                thinking.push_str(&format!("{}\n", unescape_content_line(line)));
+                return Ok(GeneratorState::TagClosed);
+            }
+            // Not closed, it is still streaming, or the user removed the closing tag : trimmed with the message.
+        }
+        if self.in_version {
+            if is_close_tag_line(&line, TAG_VERSION) {
+                self.in_version = false;
+                self.in_version_thinking = false;
+                if let Some(version) = self.current_message.versions.last_mut() {
+                    strip_line_break(&mut version.content);
+                }
+                return Ok(GeneratorState::TagClosed);
+            } else if !is_message_tag {
+                if let Some(version) = self.current_message.versions.last_mut() {
+                    if self.in_version_thinking {
+                        if is_close_tag_line(&line, TAG_THINKING) {
+                            self.in_version_thinking = false;
+                            if let Some(thinking) = version.thinking.as_mut() {
+                                strip_line_break(thinking);
+                            }
+                        } else {
+                            let thinking = version.thinking.get_or_insert_default();
+                            thinking.push_str(&format!("{}\n", unescape_content_line(line)));
+                        }
+                    } else if is_open_tag_line(&line, TAG_THINKING) && version.thinking.is_none() {
+                        self.in_version_thinking = true;
+                        version.thinking = Some(String::new());
+                    } else {
+                        version
+                            .content
+                            .push_str(&format!("{}\n", unescape_content_line(line)));
+                    }
+                }
+                return Ok(GeneratorState::TagClosed);
+            }
+        }
         if let Some(tc_gen) = self.tool_call_generator.as_mut() {
             crate::log_libuv!(Trace, "TC GEN exist at line {line_nb}");
             if !is_message_tag {
@@ -369,17 +724,21 @@ impl<'a> Generator for MsgGen<'a> {
         }
         if is_message_tag {
             crate::log_libuv!(Trace, "Found tag {TAG_MESSAGE};");
-            let mut prev_message = std::mem::take(&mut self.current_message);
-            if self.nb_messages > 0 {
-                prev_message.message.content = prev_message.message.content.trim_end().to_string();
-                self.messages.push(prev_message);
-            }
+            self.finish_message();
             self.nb_messages += 1;
+            let (message, trailing) = (&mut self.current_message, &mut self.trailing);
             parse_tag_line(&line, |key, val, _cols| {
                 crate::log_libuv!(Trace, "PARSE : {key} ; {val} ");
-                message_setter(key, val, &mut self.current_message)
+                match key.as_str() {
+                    "trailing" => *trailing = unescape_whitespace(&unescape_quote_arg(&val)),
+                    _ => message_setter(key, val, message),
+                }
             });
             Ok(GeneratorState::TagClosed)
+        } else if !self.args.expand_includes
+            && (is_self_tag_line(&line, TAG_FILE) || is_self_tag_line(&line, TAG_OCR))
+        {
+            Ok(*self.state())
         } else if is_self_tag_line(&line, TAG_FILE) {
             let mut path = String::new();
             parse_tag_line(&line, |key, val, _cols| {
@@ -407,6 +766,56 @@ impl<'a> Generator for MsgGen<'a> {
                 crate::notify::error("No path found in <FILE />.");
             }
             Ok(*self.state())
+        } else if is_self_tag_line(&line, TAG_OCR) {
+            let mut path = String::new();
+            let mut pages = None;
+            parse_tag_line(&line, |key, val, _cols| {
+                crate::log_libuv!(Trace, "PARSE : {key} ; {val} ");
+                match key.as_str() {
+                    "path" => path = unescape_quote_arg(&val),
+                    "pages" => pages = Some(unescape_quote_arg(&val)),
+                    _ => (),
+                }
+            });
+            if path == "" {
+                crate::notify::error(format!("No path found in <{TAG_OCR} />."));
+                return Ok(*self.state());
+            }
+            let source = match mistral::model::ocr::OcrSource::new(path, pages.as_deref()) {
+                Ok(source) => source,
+                Err(err) => {
+                    crate::notify::error(format!("<{TAG_OCR} /> : {}", err.message));
+                    return Ok(*self.state());
+                }
+            };
+            match source.cached() {
+                Ok(Some(markdown)) => {
+                    let content = &mut self.current_message.message.content;
+                    content.push_str(&markdown);
+                    content.push('\n');
+                }
+                Ok(None) => self.current_message.ocr_pending.push(source),
+                Err(err) => crate::notify::error(format!("<{TAG_OCR} /> `{source}` : {}", err.message)),
+            }
+            Ok(*self.state())
+        } else if is_open_tag_line(&line, TAG_THINKING) && self.current_message.thinking.is_none() {
+            self.in_thinking = true;
+            self.current_message.thinking = Some(String::new());
+            self.current_message.thinking_position = Some((line_nb.clone()..Row::MAX).into());
+            Ok(GeneratorState::TagClosed)
+        } else if is_open_tag_line(&line, TAG_VERSION) {
+            self.in_version = true;
+            let mut version = MessageVersion::default();
+            parse_tag_line(&line, |key, val, _cols| {
+                let val = unescape_quote_arg(&val);
+                match key.as_str() {
+                    "model" => version.model.replace_from_str(&val),
+                    "usage" => version.usage = val.into(),
+                    _ => (),
+                }
+            });
+            self.current_message.versions.push(version);
+            Ok(GeneratorState::TagClosed)
         } else if is_open_tag_line(&line, TAG_TOOL_CALL) {
             crate::log_libuv!(Trace, "TOOL_CALL Line found.");
             let mut tc_gen = ToolCallGen::new(self.args);
@@ -417,16 +826,14 @@ impl<'a> Generator for MsgGen<'a> {
             self.current_message
                 .message
                 .content
-                .push_str(&format!("{line}\n"));
+                .push_str(&format!("{}\n", unescape_content_line(line)));
             Ok(GeneratorState::TagClosed)
         }
     }
     fn finalise(&mut self) -> crate::Result<()> {
         if self.nb_messages > 0 {
             self.take_tool_calls(&Row::MAX, &"<FINALISE>".to_string())?;
-            let mut prev_message = std::mem::take(&mut self.current_message);
-            prev_message.message.content = prev_message.message.content.trim_end().to_string();
-            self.messages.push(prev_message);
+            self.finish_message();
         }
         let empty = self.nb_messages == 0;
         if self.args.is_full_range() && empty {
@@ -489,7 +896,7 @@ impl<'a> Generator for ToolCallGen<'a> {
             if !args.is_empty() {
                 args.push('\n');
             }
-            args.push_str(line);
+            args.push_str(unescape_content_line(line));
         }
         Ok(self.state)
     }
@@ -509,58 +916,20 @@ pub(super) fn parse_tag_line<Callback>(tag_line: &String, mut parse_arg: Callbac
 where
     Callback: FnMut(String, String, model::ColRange),
 {
-    let mut in_quote = false;
-    let mut key = String::new();
-    let mut value = String::new();
-    let mut column = model::Col(0);
-    let mut cols = model::ColRange::default();
-    let mut chars = tag_line.chars();
-    // Skip Tag
-    while let Some(c) = chars.next() {
-        column += 1;
-        if c == ' ' {
-            break;
-        }
-    }
-    let chars = " ".chars().chain(chars);
-    for c in chars.collect::<Vec<char>>().windows(2) {
-        let c = (c[0], c[1]);
-        match c {
-            ('>', _) if !in_quote => return,
-            ('"', '/') | ('"', ' ') | ('"', '>') if !in_quote => {
-                let k = std::mem::take(&mut key);
-                let v = std::mem::take(&mut value);
-                let c = std::mem::take(&mut cols);
-                parse_arg(k, v, c);
-            }
-            // Detect quotes
-            (c, '"') if c != '\\' => {
-                if in_quote {
-                    cols.end = column;
-                } else {
-                    cols.start = column + 1;
-                }
-                in_quote = !in_quote;
-            }
-            // Fill value
-            // ('\\', '"') if in_quote => value.push('"'),
-            (_, c) if in_quote => value.push(c),
-            // Fill key
-            (_, ' ') => (),
-            (_, '=') => (),
-            (_, c) => key.push(c),
-        }
-        column += 1;
-    }
+    chat_format::parse_tag_line(tag_line, |key, val, cols| parse_arg(key, val, cols.into()))
 }
 
 pub(super) fn config_setter(key: String, val: String, chat: &mut ChatState) {
-    let metadata = &mut chat.metadata;
+    metadata_setter(key, val, &mut chat.metadata)
+}
+pub(super) fn metadata_setter(key: String, val: String, metadata: &mut ChatMetadata) {
     let val = unescape_quote_arg(&val);
     match key.as_str() {
         "name" => metadata.name = val,
         "usage" => metadata.usage = val.into(),
         "description" => metadata.description = val,
+        "thinking" => metadata.keep_thinking = val == "keep",
+        "fork" => metadata.fork = Some(val).filter(|fork| !fork.is_empty()),
         _ => (),
     }
 }
@@ -570,6 +939,8 @@ pub(super) fn config_getter(key: String, chat: &mut ChatState) -> Option<String>
         "name" => metadata.name.to_string(),
         "usage" => metadata.usage.to_string(),
         "description" => metadata.description.to_string(),
+        "thinking" => if metadata.keep_thinking { "keep" } else { "" }.to_string(),
+        "fork" => metadata.fork.clone().unwrap_or_default(),
         _ => return None,
     }))
 }
@@ -588,6 +959,8 @@ fn message_setter(key: String, val: String, msg: &mut MessageState) {
         // params
         "min_tokens" => msg.params.min_tokens = str::parse(&val).ok(),
         "max_tokens" => msg.params.max_tokens = str::parse(&val).ok(),
+        "version" => msg.version = str::parse(&val).unwrap_or_default(),
+        "date" => msg.date = if val != "" { Some(val) } else { None },
         _ => (),
     }
 }
@@ -604,6 +977,8 @@ pub(super) fn message_getter(key: String, msg: &MessageState) -> Option<String>
         // params
         "min_tokens" => option_to_arg(&msg.params.min_tokens),
         "max_tokens" => option_to_arg(&msg.params.max_tokens),
+        "version" => msg.version.to_string(),
+        "date" => option_to_arg(&msg.date),
         _ => return None,
     }))
 }
@@ -628,3 +1003,192 @@ fn tool_call_getter(key: String, tool_call: &mistral::model::ToolCall) -> Option
         _ => return None,
     }))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<String> {
+        text.split('\n').map(String::from).collect()
+    }
+
+    #[test]
+    fn chat_migration() {
+        let legacy = r#"<CHAT  role="Refactorisation" status="0;0;0" model="Un \"outil\"." id="0-0"/>"#.to_string();
+        assert_eq!(chat_version(&legacy), 0);
+        let migrated = migrate_chat(&legacy, &mut []).unwrap();
+        assert_eq!(
+            migrated,
+            r#"<CHAT version="3" name="Refactorisation" usage="0;0;0" description="Un \"outil\"." id="0-0"/>"#
+        );
+        assert_eq!(chat_version(&migrated), CHAT_VERSION);
+        assert_eq!(migrate_chat(&migrated, &mut []), None);
+
+        let unversioned = r#"<CHAT name="Thinking" usage="0;0;0" description="" thinking="keep"/>"#.to_string();
+        let mut content = lines("<MESSAGE role=\"User\"/>\n\\<MESSAGE/> was escaped by hand\n\\n");
+        assert_eq!(
+            migrate_chat(&unversioned, &mut content).unwrap(),
+            r#"<CHAT version="3" name="Thinking" usage="0;0;0" description="" thinking="keep"/>"#
+        );
+        assert_eq!(
+            content,
+            lines("<MESSAGE role=\"User\"/>\n\\\\<MESSAGE/> was escaped by hand\n\\n")
+        );
+        assert_eq!(unescape_content_line(&content[1]), "\\<MESSAGE/> was escaped by hand");
+        // `<VERSION>` was not a tag of the version 2.
+        let v2 = r#"<CHAT version="2" name="" usage="0;0;0" description=""/>"#.to_string();
+        let mut content = lines("<VERSION> est un mot\n\\</VERSION>\n\\<MESSAGE/>");
+        migrate_chat(&v2, &mut content).unwrap();
+        assert_eq!(content, lines("\\<VERSION> est un mot\n\\\\</VERSION>\n\\<MESSAGE/>"));
+        assert_eq!(unescape_content_line(&content[0]), "<VERSION> est un mot");
+        assert_eq!(unescape_content_line(&content[1]), "\\</VERSION>");
+        // A newer file is read as is, its unknown attributes are ignored.
+        let newer = r#"<CHAT version="7" name="Futur" branch="main"/>"#.to_string();
+        assert_eq!(chat_version(&newer), 7);
+        assert_eq!(migrate_chat(&newer, &mut []), None);
+    }
+
+    #[test]
+    fn chat_metadata() {
+        let metadata = ChatMetadata {
+            name: "Revue \"v2\"".to_string(),
+            fork: Some("revue.chat".to_string()),
+            ..Default::default()
+        };
+        let line = build_tag_chat_line(&metadata);
+        assert_eq!(
+            line,
+            r#"<CHAT version="3" name="Revue \"v2\"" usage="0;0;0" description="" fork="revue.chat"/>"#
+        );
+        let parsed = ChatMetadata::from_tag_line(&line).unwrap();
+        assert_eq!(parsed.name, metadata.name);
+        assert_eq!(parsed.fork, metadata.fork);
+        // Older headers are migrated, a chat which is not a fork has no `fork` attribute.
+        let legacy = r#"<CHAT role="Refactorisation" status="1;2;3" model=""/>"#.to_string();
+        let parsed = ChatMetadata::from_tag_line(&legacy).unwrap();
+        assert_eq!(parsed.name, "Refactorisation");
+        assert_eq!(parsed.usage.to_string(), "1;2;3");
+        assert_eq!(parsed.fork, None);
+        assert!(ChatMetadata::from_tag_line(&"<MESSAGE/>".to_string()).is_none());
+    }
+
+    #[test]
+    fn chat_file() {
+        let metadata = ChatMetadata {
+            name: "Revue".to_string(),
+            ..Default::default()
+        };
+        let mut question = MessageState::default();
+        question.message.content = "Une question ?".to_string();
+        let mut answer = MessageState::default();
+        answer.message.role = mistral::model::Role::Assistant;
+        answer.message.content = "Une réponse\n<MESSAGE role=\"User\"/>".to_string();
+        answer.date = Some("2026-01-02 14:03".to_string());
+        let content = build_chat(&metadata, [question, answer]);
+        let (parsed, messages, positions) = parse_chat(&content).unwrap();
+        assert_eq!(parsed.name, "Revue");
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[1].message.content, "Une réponse\n<MESSAGE role=\"User\"/>");
+        assert_eq!(messages[0].date, None);
+        assert_eq!(messages[1].date.as_deref(), Some("2026-01-02 14:03"));
+        // The header, then the messages after two empty lines.
+        assert_eq!(positions.len(), 3);
+        assert_eq!(positions[1].start, Row(3));
+        assert_eq!(positions[2].start, Row(7));
+
+        let legacy = "<CHAT role=\"Ancien\" status=\"1;2;3\" model=\"\"/>\n<MESSAGE role=\"User\"/>\nBonjour";
+        let (parsed, messages, _) = parse_chat(legacy).unwrap();
+        assert_eq!(parsed.name, "Ancien");
+        assert_eq!(messages[0].message.content, "Bonjour");
+        // The includes are not read.
+        let included = r#"<CHAT version="3"/>
+<MESSAGE role="User"/>
+Relis
+<FILE path="absent.rs"/>
+<OCR path="absent.pdf"/>"#;
+        let (_, messages, _) = parse_chat(included).unwrap();
+        assert_eq!(messages[0].message.content, "Relis");
+        assert!(messages[0].ocr_pending.is_empty());
+        assert!(parse_chat("").is_err());
+        assert!(parse_chat("<MESSAGE role=\"User\"/>").is_err());
+    }
+
+    #[test]
+    fn hand_edited_version() {
+        let content = r#"<CHAT version="3" name="" usage="0;0;0" description=""/>
+
+<MESSAGE role="Assistant" version="5"/>
+<VERSION model="Medium Latest" usage="1;2;3">
+<THINKING>
+Le rouge d'abord.
+</THINKING>
+Rouge.
+</VERSION>
+Bleu."#;
+        let (_, mut messages, _) = parse_chat(content).unwrap();
+        let answer = &mut messages[0];
+        // `version="5"` with a single other version : the selected one is the last.
+        assert_eq!(answer.version, 1);
+        assert_eq!(answer.versions[0].content, "Rouge.");
+        assert_eq!(answer.versions[0].thinking.as_deref(), Some("Le rouge d'abord."));
+        answer.select_version(0);
+        assert_eq!(answer.message.content, "Rouge.");
+        assert_eq!(answer.thinking.as_deref(), Some("Le rouge d'abord."));
+        answer.push_version(MessageVersion {
+            content: "Vert.".to_string(),
+            ..Default::default()
+        });
+        assert_eq!(answer.version, 2);
+        assert_eq!(answer.versions[0].content, "Rouge.");
+        assert_eq!(answer.versions[1].content, "Bleu.");
+    }
+
+    #[test]
+    fn tag_args() {
+        let line = r#"<CHAT version="3" name="Revue" usage="1;2;3" color="bleu"/>"#.to_string();
+        assert_eq!(
+            set_tag_args(
+                &line,
+                &[
+                    ("name", "Révision \"finale\"".to_string()),
+                    ("fork", "revue.chat".to_string())
+                ]
+            ),
+            r#"<CHAT version="3" name="Révision \"finale\"" usage="1;2;3" color="bleu" fork="revue.chat"/>"#
+        );
+        let line = r#"<CHAT name="Été" usage="1;2;3"/>"#.to_string();
+        assert_eq!(
+            set_tag_args(&line, &[("usage", "4;5;9".to_string())]),
+            r#"<CHAT name="Été" usage="4;5;9"/>"#
+        );
+    }
+
+    #[test]
+    fn content_escaping() {
+        for line in [
+            "<MESSAGE role=\"User\"/>",
+            "</TOOLCALL>",
+            "\\<FILE path=\"a\"/>",
+            "<OCRE",
+            "a <CHAT/>",
+            "<",
+        ] {
+            let escaped = escape_content_line(line);
+            assert!(!is_self_tag_line(&escaped, TAG_MESSAGE) && !is_close_tag_line(&escaped, TAG_TOOL_CALL));
+            assert_eq!(unescape_content_line(&escaped), line);
+        }
+        assert_eq!(escape_content_line("</THINKING>"), "\\</THINKING>");
+        assert_eq!(escape_content_line("a <CHAT/>"), "a <CHAT/>");
+        assert_eq!(unescape_content_line("\\n"), "\\n");
+
+        // Streamed : the start of a line is held while it could become a tag.
+        let mut escaper = ContentEscaper::default();
+        let chunk = |parts: &[&str]| parts.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert_eq!(escaper.push(chunk(&["Voici :", "<MES"])), chunk(&["Voici :", ""]));
+        assert_eq!(escaper.push(chunk(&["SAGE/>", "</T"])), chunk(&["\\<MESSAGE/>", ""]));
+        assert_eq!(escaper.push(chunk(&["ext", "<"])), chunk(&["</Text", ""]));
+        assert_eq!(escaper.push(chunk(&["/TOOLCALL>"])), chunk(&["\\</TOOLCALL>"]));
+        assert_eq!(escaper.push(chunk(&["", "<TOOL"])), chunk(&["", ""]));
+        assert_eq!(escaper.flush(), "<TOOL");
+    }
+}
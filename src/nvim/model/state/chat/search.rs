@@ -0,0 +1,227 @@
+use super::{ChatFile, MessageState, MsgIndex};
+use crate::{mistral::model::completion::Model, notify::IntoNotification as _, nvim::model::Row};
+
+/// Characters of a message kept in a search result.
+const SNIPPET_LEN: usize = 80;
+
+/// The arguments of `:MistralChatSearch`, `role:`, `model:`, `since:` and `until:` words are filters, the
+/// others are the text searched (case insensitive) in the lines of the chat, not in its includes.
+#[derive(Debug, Default, PartialEq)]
+pub struct SearchQuery {
+    pub text: String,
+    /// Lowercase `user`, `assistant`, `system` or `tool`.
+    pub role: Option<String>,
+    /// Part of the name of the model, either `large` or `mistral-large-latest`.
+    pub model: Option<String>,
+    /// `YYYY-MM-DD`, compared with the `date` of each message, or with the last modification of the file for the
+    /// messages written without it.
+    pub since: Option<String>,
+    pub until: Option<String>,
+}
+
+/// A message of a chat file matching a `SearchQuery`.
+#[derive(Debug, PartialEq)]
+pub struct SearchMatch {
+    pub index: MsgIndex,
+    /// Row of the tag line of the message.
+    pub row: Row,
+    pub snippet: String,
+}
+
+impl SearchQuery {
+    pub fn parse(args: &str) -> crate::Result<Self> {
+        let mut query = Self::default();
+        let mut words = Vec::new();
+        for word in args.split_whitespace() {
+            let Some((key, value)) = word.split_once(':') else {
+                words.push(word);
+                continue;
+            };
+            let value = Some(value.to_lowercase());
+            match key {
+                "role" => query.role = value,
+                "model" => query.model = value,
+                "since" => query.since = value,
+                "until" => query.until = value,
+                _ => words.push(word),
+            }
+        }
+        query.text = words.join(" ").to_lowercase();
+        if let Some(role) = &query.role
+            && !["user", "assistant", "system", "tool"].contains(&role.as_str())
+        {
+            return Err(format!("Unknown role `{role}` : user, assistant, system or tool.").into_warn());
+        }
+        for date in [&query.since, &query.until].into_iter().flatten() {
+            if !is_date(date) {
+                return Err(format!("Invalid date `{date}`, expected YYYY-MM-DD.").into_warn());
+            }
+        }
+        if query.text.is_empty() && query.role.is_none() && query.model.is_none() {
+            return Err(
+                "Usage : `:MistralChatSearch [role:user] [model:large] [since:YYYY-MM-DD] [until:YYYY-MM-DD] text`."
+                    .into_warn(),
+            );
+        }
+        Ok(query)
+    }
+    /// The day of `date` (`YYYY-MM-DD`, followed by the time or not) is within `since..=until`.
+    pub fn matches_date(&self, date: &str) -> bool {
+        let date = date.get(..10).unwrap_or(date);
+        self.since
+            .as_ref()
+            .is_none_or(|since| date >= since.as_str())
+            && self
+                .until
+                .as_ref()
+                .is_none_or(|until| date <= until.as_str())
+    }
+    pub fn has_date(&self) -> bool {
+        self.since.is_some() || self.until.is_some()
+    }
+    fn matches_model(&self, model: &Model) -> bool {
+        let Some(filter) = &self.model else {
+            return true;
+        };
+        let api_name = serde_json::to_value(model)
+            .ok()
+            .and_then(|name| name.as_str().map(String::from))
+            .unwrap_or_default();
+        model.to_string().to_lowercase().contains(filter) || api_name.contains(filter)
+    }
+    /// The first line of the message containing the text, `None` when it does not match. `file_date` is used for
+    /// a message without `date`.
+    fn matches(&self, message: &MessageState, file_date: Option<&str>) -> Option<String> {
+        if self.has_date() && !self.matches_date(message.date.as_deref().or(file_date)?) {
+            return None;
+        }
+        if self
+            .role
+            .as_ref()
+            .is_some_and(|role| *role != message.message.role.to_string().to_lowercase())
+        {
+            return None;
+        }
+        if !self.matches_model(&message.model) {
+            return None;
+        }
+        let texts = std::iter::once(&message.message.content)
+            .chain(&message.thinking)
+            .chain(
+                message
+                    .versions
+                    .iter()
+                    .flat_map(|version| std::iter::once(&version.content).chain(&version.thinking)),
+            );
+        let line = texts
+            .flat_map(|text| text.lines())
+            .map(str::trim)
+            .find(|line| !line.is_empty() && line.to_lowercase().contains(&self.text));
+        match line {
+            Some(line) => Some(line.chars().take(SNIPPET_LEN).collect()),
+            // Only filtered : the messages without content (tool calls) match too.
+            None if self.text.is_empty() => Some(String::new()),
+            None => None,
+        }
+    }
+    /// The matching messages of a chat file, whatever its date.
+    /// The messages of `chat` matching the query, `file_date` is the day of its last modification.
+    pub fn search(&self, chat: &ChatFile, file_date: Option<&str>) -> Vec<SearchMatch> {
+        chat.messages
+            .iter()
+            .enumerate()
+            .filter_map(|(index, message)| {
+                let snippet = self.matches(message, file_date)?;
+                // The first position is the header.
+                let row = chat.positions.get(index + 1)?.start;
+                Some(SearchMatch { index, row, snippet })
+            })
+            .collect()
+    }
+}
+
+fn is_date(date: &str) -> bool {
+    let bytes = date.as_bytes();
+    bytes.len() == 10
+        && bytes.iter().enumerate().all(|(i, c)| {
+            if i == 4 || i == 7 {
+                *c == b'-'
+            } else {
+                c.is_ascii_digit()
+            }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mistral::model::Role;
+
+    #[test]
+    fn search_query() {
+        let query = SearchQuery::parse("role:Assistant model:large since:2026-01-01 Borrow checker").unwrap();
+        assert_eq!(
+            query,
+            SearchQuery {
+                text: "borrow checker".to_string(),
+                role: Some("assistant".to_string()),
+                model: Some("large".to_string()),
+                since: Some("2026-01-01".to_string()),
+                until: None,
+            }
+        );
+        assert!(query.matches_date("2026-03-02"));
+        assert!(!query.matches_date("2025-12-31"));
+        // An unknown filter is searched as text.
+        assert_eq!(SearchQuery::parse("http://localhost").unwrap().text, "http://localhost");
+        assert!(SearchQuery::parse("role:robot erreur").is_err());
+        assert!(SearchQuery::parse("until:demain erreur").is_err());
+        assert!(SearchQuery::parse("since:2026-01-01").is_err());
+        assert!(SearchQuery::parse("   ").is_err());
+    }
+
+    #[test]
+    fn search_messages() {
+        let mut question = MessageState::default();
+        question.message.role = Role::User;
+        question.message.content = "Pourquoi le borrow checker refuse ce code ?".to_string();
+        let mut answer = MessageState::default();
+        answer.model = Model::MistralLargeLatest;
+        answer.message.content = "Voici la raison.\n  Le Borrow Checker voit deux emprunts mutables.".to_string();
+        let chat = ChatFile {
+            path: "revue.chat".into(),
+            metadata: Default::default(),
+            messages: vec![question, answer],
+            positions: vec![(0..3).into(), (3..5).into(), (5..8).into()],
+            modified: None,
+        };
+
+        let query = SearchQuery::parse("borrow checker").unwrap();
+        let matches = query.search(&chat, None);
+        assert_eq!(matches.len(), 2);
+        assert_eq!(matches[1].index, 1);
+        assert_eq!(matches[1].row, Row(5));
+        assert_eq!(matches[1].snippet, "Le Borrow Checker voit deux emprunts mutables.");
+
+        let query = SearchQuery::parse("role:assistant model:mistral-large borrow").unwrap();
+        assert_eq!(query.search(&chat, None).len(), 1);
+        let query = SearchQuery::parse("model:tiny borrow").unwrap();
+        assert!(query.search(&chat, None).is_empty());
+        let query = SearchQuery::parse("role:user").unwrap();
+        assert_eq!(
+            query.search(&chat, None)[0].snippet,
+            "Pourquoi le borrow checker refuse ce code ?"
+        );
+
+        // The date of a message, else the one of the file.
+        let mut chat = chat;
+        chat.messages[1].date = Some("2026-03-02 09:30".to_string());
+        let query = SearchQuery::parse("since:2026-03-01 borrow").unwrap();
+        assert_eq!(query.search(&chat, None).len(), 1);
+        assert_eq!(query.search(&chat, Some("2026-01-15")).len(), 1);
+        assert_eq!(query.search(&chat, Some("2026-03-01")).len(), 2);
+        let query = SearchQuery::parse("until:2026-03-01 borrow").unwrap();
+        assert_eq!(query.search(&chat, Some("2026-01-15"))[0].index, 0);
+        assert_eq!(query.search(&chat, Some("2026-01-15")).len(), 1);
+    }
+}
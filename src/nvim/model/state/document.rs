@@ -0,0 +1,44 @@
+use std::sync::LazyLock;
+
+use nvim_oxi::api::{self, opts::SetExtmarkOpts};
+
+static NS: LazyLock<u32> = LazyLock::new(|| api::create_namespace("mistral_document"));
+
+/// The doc comment replaced by `:MistralDocument`, kept until the new one is written above the item. Its
+/// blocks are followed by extmarks, so their rows move with the insertion and the edits of the user.
+pub struct ReplacedDocs {
+    buffer: api::Buffer,
+    /// Extmark on the first row of each block, with its number of rows.
+    blocks: Vec<(u32, usize)>,
+}
+
+impl ReplacedDocs {
+    /// `rows` are the first and the last row of each block.
+    pub fn new(buffer: &api::Buffer, rows: &[(usize, usize)]) -> crate::Result<Self> {
+        let mut buffer = buffer.clone();
+        let mut blocks = Vec::with_capacity(rows.len());
+        for (start, end) in rows {
+            let id = buffer.set_extmark(*NS, *start, 0, &SetExtmarkOpts::builder().build())?;
+            blocks.push((id, end + 1 - start));
+        }
+        Ok(Self { buffer, blocks })
+    }
+    /// Delete the previous doc comment, in the undo step of the new one. Without a new one (ex: the request
+    /// failed), it is kept.
+    pub fn finish(mut self, written: bool) -> crate::Result<()> {
+        // From the end, so the rows of the previous ones stay valid.
+        for (id, len) in self.blocks.iter().rev() {
+            let (row, _, _) = self
+                .buffer
+                .get_extmark_by_id(*NS, *id, &Default::default())?;
+            self.buffer.del_extmark(*NS, *id)?;
+            if written {
+                // Not allowed right after an undo of the user, then it is its own undo step.
+                let _ = self.buffer.call(|_| api::command("undojoin"));
+                self.buffer
+                    .set_lines(row..row + len, true, Vec::<String>::new())?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,354 @@
+use std::sync::LazyLock;
+
+use nvim_oxi::api::{self, opts::SetExtmarkOpts};
+
+use crate::{notify::IntoNotification as _, nvim::model::BufferModifierGroupedUndo};
+
+static NS: LazyLock<u32> = LazyLock::new(|| api::create_namespace("mistral_edit"));
+static NS_ANCHOR: LazyLock<u32> = LazyLock::new(|| api::create_namespace("mistral_edit_anchor"));
+const HL_REMOVED: &'static str = "DiffDelete";
+const HL_ADDED: &'static str = "DiffAdd";
+
+/// Lines of the selection replaced by lines of the rewrite.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hunk {
+    /// Index of the first removed line in the original lines.
+    pub old_start: usize,
+    pub removed: Vec<String>,
+    /// Index of the first added line in the proposed lines.
+    pub new_start: usize,
+    pub added: Vec<String>,
+}
+
+/// Line diff of `old` and `new` (longest common subsequence), the hunks are sorted.
+pub fn diff(old: &[String], new: &[String]) -> Vec<Hunk> {
+    // The common ends are skipped, the table only covers the changed lines.
+    let prefix = old.iter().zip(new).take_while(|(old, new)| old == new).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(old, new)| old == new)
+        .count();
+    let mut hunks = diff_lcs(&old[prefix..old.len() - suffix], &new[prefix..new.len() - suffix]);
+    for hunk in hunks.iter_mut() {
+        hunk.old_start += prefix;
+        hunk.new_start += prefix;
+    }
+    hunks
+}
+
+fn diff_lcs(old: &[String], new: &[String]) -> Vec<Hunk> {
+    let (n, m) = (old.len(), new.len());
+    // common[i][j] : length of the longest common subsequence of old[i..] and new[j..].
+    let mut common = vec![vec![0usize; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            common[i][j] = if old[i] == new[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+    let mut hunks: Vec<Hunk> = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < m {
+        if i < n && j < m && old[i] == new[j] {
+            i += 1;
+            j += 1;
+            continue;
+        }
+        let hunk = match hunks.last_mut() {
+            Some(hunk) if hunk.old_start + hunk.removed.len() == i && hunk.new_start + hunk.added.len() == j => hunk,
+            _ => {
+                hunks.push(Hunk {
+                    old_start: i,
+                    removed: Vec::new(),
+                    new_start: j,
+                    added: Vec::new(),
+                });
+                hunks.last_mut().unwrap()
+            }
+        };
+        if j == m || (i < n && common[i + 1][j] >= common[i][j + 1]) {
+            hunk.removed.push(old[i].clone());
+            i += 1;
+        } else {
+            hunk.added.push(new[j].clone());
+            j += 1;
+        }
+    }
+    hunks
+}
+
+/// Code lines of a streamed answer : inside its first code block if any. While streaming, the last line
+/// is only kept once complete.
+pub fn proposed_lines(answer: &str, finished: bool) -> Vec<String> {
+    let mut lines: Vec<&str> = answer.split('\n').collect();
+    if !finished {
+        lines.pop();
+    }
+    let fenced = lines
+        .iter()
+        .position(|line| !line.trim().is_empty())
+        .filter(|&first| lines[first].trim_start().starts_with("```"));
+    if let Some(first) = fenced {
+        lines.drain(..=first);
+        if let Some(end) = lines
+            .iter()
+            .position(|line| line.trim_start().starts_with("```"))
+        {
+            lines.truncate(end);
+        }
+    } else if finished {
+        while lines.last().is_some_and(|line| line.trim().is_empty()) {
+            lines.pop();
+        }
+    }
+    lines.into_iter().map(String::from).collect()
+}
+
+/// A rewrite of the selected lines shown as a diff over the buffer, each hunk is accepted or rejected.
+pub struct InlineEdit {
+    pub buffer: api::Buffer,
+    /// Id of the request streaming the rewrite.
+    pub id: usize,
+    /// Extmark on the first selected line, it follows the edits above the selection.
+    anchor: u32,
+    /// The selected lines, with the accepted hunks.
+    original: Vec<String>,
+    /// Answer of the model.
+    answer: String,
+    /// The rewrite once the answer is complete, without the rejected hunks.
+    proposal: Option<Vec<String>>,
+    /// Diff of the original and the proposed lines, computed again only when one of them changes.
+    hunks: Vec<Hunk>,
+    /// The accepted hunks are a single undo step.
+    modifier: BufferModifierGroupedUndo,
+}
+
+impl InlineEdit {
+    pub fn new(mut buffer: api::Buffer, id: usize, row: usize, original: Vec<String>) -> crate::Result<Self> {
+        let modifier = BufferModifierGroupedUndo::new(&buffer)?;
+        let anchor = buffer.set_extmark(*NS_ANCHOR, row, 0, &SetExtmarkOpts::builder().build())?;
+        Ok(Self {
+            buffer,
+            id,
+            anchor,
+            original,
+            answer: String::new(),
+            proposal: None,
+            hunks: Vec::new(),
+            modifier,
+        })
+    }
+    pub fn is_finished(&self) -> bool {
+        self.proposal.is_some()
+    }
+    /// Add a chunk of the answer, true when the diff changed (a line is complete).
+    pub fn push(&mut self, chunk: &str) -> bool {
+        self.answer.push_str(chunk);
+        let new_line = chunk.contains('\n');
+        if new_line {
+            self.update_hunks();
+        }
+        new_line
+    }
+    pub fn finish(&mut self) {
+        self.proposal = Some(proposed_lines(&self.answer, true));
+        self.update_hunks();
+    }
+
+    fn update_hunks(&mut self) {
+        self.hunks = match &self.proposal {
+            Some(proposal) => diff(&self.original, proposal),
+            None => {
+                let mut hunks = diff(&self.original, &proposed_lines(&self.answer, false));
+                // The end of the selection is not rewritten yet.
+                if hunks.last().is_some_and(|hunk| {
+                    hunk.added.is_empty() && hunk.old_start + hunk.removed.len() == self.original.len()
+                }) {
+                    hunks.pop();
+                }
+                hunks
+            }
+        };
+    }
+    pub fn hunks(&self) -> &[Hunk] {
+        &self.hunks
+    }
+
+    /// Row of the first selected line.
+    fn row(&self) -> crate::Result<usize> {
+        let (row, _, _) = self
+            .buffer
+            .get_extmark_by_id(*NS_ANCHOR, self.anchor, &Default::default())?;
+        Ok(row)
+    }
+
+    /// Removed lines are highlighted, added lines are virtual lines under them.
+    pub fn render(&mut self) -> crate::Result<()> {
+        self.clear()?;
+        let first = self.row()?;
+        let last = first + self.original.len().saturating_sub(1);
+        for hunk in self.hunks.iter() {
+            let start = first + hunk.old_start;
+            for row in start..start + hunk.removed.len() {
+                let opts = SetExtmarkOpts::builder().line_hl_group(HL_REMOVED).build();
+                self.buffer.set_extmark(*NS, row, 0, &opts)?;
+            }
+            if hunk.added.is_empty() {
+                continue;
+            }
+            let virt_lines: Vec<Vec<(String, &str)>> = hunk
+                .added
+                .iter()
+                .map(|line| vec![(line.clone(), HL_ADDED)])
+                .collect();
+            // Under the removed lines, or above the next line for a pure insertion.
+            let (row, above) = match hunk.removed.len() {
+                0 if start <= last && !self.original.is_empty() => (start, true),
+                0 => (last, false),
+                len => (start + len - 1, false),
+            };
+            let opts = SetExtmarkOpts::builder()
+                .virt_lines(virt_lines)
+                .virt_lines_above(above)
+                .build();
+            self.buffer.set_extmark(*NS, row, 0, &opts)?;
+        }
+        Ok(())
+    }
+    pub fn clear(&mut self) -> crate::Result<()> {
+        self.buffer.clear_namespace(*NS, ..)?;
+        Ok(())
+    }
+    /// Remove the overlay and the anchor.
+    pub fn close(mut self) -> crate::Result<()> {
+        self.clear()?;
+        self.buffer.del_extmark(*NS_ANCHOR, self.anchor)?;
+        Ok(())
+    }
+
+    /// Index of the hunk under `row`, or the next one, or the last one.
+    pub fn hunk_at(&self, row: usize) -> crate::Result<Option<usize>> {
+        let first = self.row()?;
+        let end = |hunk: &Hunk| first + hunk.old_start + hunk.removed.len().max(1);
+        Ok(self
+            .hunks
+            .iter()
+            .position(|hunk| row < end(hunk))
+            .or_else(|| self.hunks.len().checked_sub(1)))
+    }
+
+    /// The user may have changed the selected lines since the request.
+    fn check_unchanged(&self, first: usize) -> crate::Result<()> {
+        let end = first + self.original.len();
+        let lines: Vec<String> = self
+            .buffer
+            .get_lines(first..end, true)?
+            .map(|line| line.to_string())
+            .collect();
+        if lines != self.original {
+            return Err("The selected lines have changed, the edit can't be applied.".into_warn());
+        }
+        Ok(())
+    }
+
+    /// Write the hunk in the buffer.
+    pub fn accept(&mut self, index: usize) -> crate::Result<()> {
+        let Some(hunk) = self.hunks.get(index).cloned() else {
+            return Ok(());
+        };
+        let first = self.row()?;
+        self.check_unchanged(first)?;
+        let start = first + hunk.old_start;
+        let end = start + hunk.removed.len();
+        self.modifier.replace_rows(start, end, hunk.added.clone())?;
+        if hunk.old_start == 0 {
+            // The first lines are replaced, the anchor goes back on the first one.
+            let opts = SetExtmarkOpts::builder().id(self.anchor).build();
+            self.buffer.set_extmark(*NS_ANCHOR, first, 0, &opts)?;
+        }
+        let old_end = hunk.old_start + hunk.removed.len();
+        self.original.splice(hunk.old_start..old_end, hunk.added);
+        self.update_hunks();
+        Ok(())
+    }
+    /// Keep the original lines of the hunk.
+    pub fn reject(&mut self, index: usize) {
+        let Some(hunk) = self.hunks.get(index).cloned() else {
+            return;
+        };
+        if let Some(proposal) = self.proposal.as_mut() {
+            let new_end = hunk.new_start + hunk.added.len();
+            proposal.splice(hunk.new_start..new_end, hunk.removed);
+        }
+        self.update_hunks();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<String> {
+        text.split('\n').map(String::from).collect()
+    }
+
+    #[test]
+    fn hunks() {
+        let old = lines("fn f() {\n    let a = 1;\n    a\n}");
+        let new = lines("fn f() {\n    let a = 2;\n    let b = a;\n    b\n}");
+        let hunks = diff(&old, &new);
+        assert_eq!(hunks.len(), 1);
+        assert_eq!(hunks[0].old_start, 1);
+        assert_eq!(hunks[0].removed, lines("    let a = 1;\n    a"));
+        assert_eq!(hunks[0].new_start, 1);
+        assert_eq!(hunks[0].added, lines("    let a = 2;\n    let b = a;\n    b"));
+        let hunks = diff(&lines("a\nb\nc\nd"), &lines("a\nc\nd\ne"));
+        assert_eq!(hunks.len(), 2);
+        assert_eq!(
+            (hunks[0].old_start, hunks[0].removed.len(), hunks[0].added.len()),
+            (1, 1, 0)
+        );
+        assert_eq!(
+            (hunks[1].old_start, hunks[1].new_start, hunks[1].added.clone()),
+            (4, 3, lines("e"))
+        );
+        assert!(diff(&old, &old).is_empty());
+    }
+
+    #[test]
+    fn streamed_proposal() {
+        let answer = "```rust\nfn f() {\n    1\n}\n```\nThe function returns 1.";
+        assert_eq!(proposed_lines(answer, true), lines("fn f() {\n    1\n}"));
+        assert_eq!(proposed_lines("```rust\nfn f() {\n    1", false), lines("fn f() {"));
+        assert_eq!(proposed_lines("fn f() {}\n\n", true), lines("fn f() {}"));
+    }
+}
+
+#[cfg(not(feature = "prod_mode"))]
+#[nvim_oxi::test]
+#[track_caller]
+fn accept_after_edit_above() -> crate::Result<()> {
+    let mut buffer = api::Buffer::current();
+    buffer.set_lines(.., false, ["fn f() {", "    1", "}"])?;
+    // Force undotree generation
+    api::exec2("undo", &Default::default())?;
+    api::exec2("redo", &Default::default())?;
+    let original = ["fn f() {", "    1", "}"].map(String::from).to_vec();
+    let mut edit = InlineEdit::new(buffer.clone(), 1, 0, original)?;
+    edit.push("```rust\nfn f() {\n    2\n}\n```");
+    edit.finish();
+    assert_eq!(edit.hunks().len(), 1);
+    // The user adds lines above the selection, the edit follows them.
+    buffer.set_lines(0..0, false, ["use std::io;", ""])?;
+    assert_eq!(edit.hunk_at(3)?, Some(0));
+    edit.accept(0)?;
+    let lines: Vec<String> = buffer.get_lines(.., true)?.map(|line| line.to_string()).collect();
+    assert_eq!(lines, ["use std::io;", "", "fn f() {", "    2", "}"]);
+    assert!(edit.hunks().is_empty());
+    edit.close()
+}
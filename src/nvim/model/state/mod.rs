@@ -6,13 +6,22 @@ use std::{
 
 use nvim_oxi::api;
 
-use crate::nvim::model;
+use crate::{
+    mistral::controlleur::fim::{post_process::PostProcess, split_at_cursor_col},
+    nvim::model::{self, config::FimBackend},
+};
 
 pub mod buffer_modifier;
 pub mod chat;
+pub mod document;
+pub mod edit;
+pub mod suggestion;
 
 pub use buffer_modifier::BufferModifierGroupedUndo;
 pub use chat::{Chat, ChatForm, ChatState};
+pub use document::ReplacedDocs;
+pub use edit::InlineEdit;
+pub use suggestion::Suggestion;
 
 pub trait Locker {
     type Locked;
@@ -89,11 +98,71 @@ impl Chats {
     }
 }
 
+/// Cleaning of a completion written at `cursor`, read before anything is written. `backend` is the one the
+/// request was sent with, a template answer is not cleaned like a native FIM.
+pub fn post_process(
+    buffer: &api::Buffer,
+    cursor: &model::Cursor,
+    backend: &FimBackend,
+) -> crate::Result<PostProcess> {
+    let opts = api::opts::OptionOpts::builder().buf(buffer.clone()).build();
+    let filetype: String = api::get_option_value("filetype", &opts).unwrap_or_default();
+    let content: Vec<String> = buffer
+        .get_lines(0.., false)?
+        .map(|line| line.to_string())
+        .collect();
+    let (prefix, suffix) = split_at_cursor_col(&content, cursor);
+    let post_process = PostProcess::new(&filetype, &prefix, &suffix);
+    Ok(match &backend.template {
+        Some(template) => post_process.infill(&template.hole),
+        None => post_process,
+    })
+}
+
 pub struct State {
     pub buffer_modifiers: HashMap<api::Buffer, BufferModifierGroupedUndo>,
     pub tx_mistral: tokio::sync::mpsc::UnboundedSender<crate::messages::NvimEnveloppe>,
     pub chats: Chats,
     pub fim: HashMap<api::Buffer, usize>,
+    /// Cleaning of each running FIM, before the insertion of its chunks.
+    pub fim_outputs: HashMap<(api::Buffer, usize), PostProcess>,
+    /// Placeholders removed by `:MistralFIMTodos`, put back when their completion fails.
+    pub placeholders: HashMap<(api::Buffer, usize), String>,
+    /// Doc comments of `:MistralDocument`, deleted once the new one is written.
+    pub replaced_docs: HashMap<(api::Buffer, usize), ReplacedDocs>,
+    /// Backend of each FIM request, until its insertion starts.
+    pub fim_backends: HashMap<(api::Buffer, usize), FimBackend>,
+    /// Ghost text shown in each buffer.
+    pub suggestions: HashMap<api::Buffer, Suggestion>,
+    suggestion_id: usize,
+    /// Rewrite of `:MistralEdit` shown over each buffer.
+    pub edits: HashMap<api::Buffer, InlineEdit>,
+    edit_id: usize,
+    /// Last keystroke of each buffer, older auto FIM timers are ignored.
+    pub auto_fim: HashMap<api::Buffer, usize>,
+    /// Running `:MistralGenerateTests`, until cargo has checked the merged tests.
+    pub generated_tests: HashMap<(api::Buffer, usize), GeneratedTests>,
+    tests_id: usize,
+}
+
+/// Tests asked for a function, merged in the buffer once the answer is complete.
+pub struct GeneratedTests {
+    /// Name of the function, filter of `cargo test`.
+    pub name: String,
+    /// The merge in the buffer, undone when the tests don't compile.
+    pub merged: Option<MergedTests>,
+}
+
+/// Rows changed by the merge of the tests, the rest of the buffer is untouched.
+pub struct MergedTests {
+    /// First changed row.
+    pub start: usize,
+    /// Rows written from `start` by the merge.
+    pub len: usize,
+    /// Lines replaced by the merge.
+    pub previous: Vec<String>,
+    /// `b:changedtick` once the merge is written, the buffer isn't restored after the user changed it.
+    pub changedtick: i32,
 }
 
 impl State {
@@ -104,11 +173,36 @@ impl State {
             buffer_modifiers: Default::default(),
             chats: Default::default(),
             fim: Default::default(),
+            fim_outputs: Default::default(),
+            placeholders: Default::default(),
+            fim_backends: Default::default(),
+            replaced_docs: Default::default(),
+            suggestions: Default::default(),
+            suggestion_id: 0,
+            edits: Default::default(),
+            edit_id: 0,
+            auto_fim: Default::default(),
+            generated_tests: Default::default(),
+            tests_id: 0,
         })))
     }
+    /// Each request gets its own id, a dismissed one may still be aborting.
+    pub fn next_suggestion_id(&mut self) -> usize {
+        self.suggestion_id = self.suggestion_id.wrapping_add(1);
+        self.suggestion_id
+    }
+    pub fn next_edit_id(&mut self) -> usize {
+        self.edit_id = self.edit_id.wrapping_add(1);
+        self.edit_id
+    }
+    pub fn next_tests_id(&mut self) -> usize {
+        self.tests_id = self.tests_id.wrapping_add(1);
+        self.tests_id
+    }
+    /// One more running FIM in the buffer, the count is its id.
     pub fn add_fim(&mut self, buffer: &api::Buffer) -> usize {
         let id = self.fim.entry(buffer.clone()).or_insert(0);
-        *id += id.saturating_add(1);
+        *id = id.wrapping_add(1);
         id.clone()
     }
     pub fn remove_fim(&mut self, buffer: &api::Buffer) {
@@ -135,6 +229,31 @@ impl State {
         }
         Ok(())
     }
+    /// Start the insertion of a FIM, its output is cleaned with the text around the cursor.
+    #[track_caller]
+    pub fn start_fim_insertion(
+        &mut self,
+        buffer: &api::Buffer,
+        id: usize,
+        cursor: model::Cursor,
+        backend: &FimBackend,
+    ) -> crate::Result<()> {
+        let post_process = post_process(buffer, &cursor, backend)?;
+        self.start_processed_insertion(buffer, id, cursor, post_process)
+    }
+    /// Start an insertion whose output goes through `post_process` (ex: a doc comment).
+    #[track_caller]
+    pub fn start_processed_insertion(
+        &mut self,
+        buffer: &api::Buffer,
+        id: usize,
+        cursor: model::Cursor,
+        post_process: PostProcess,
+    ) -> crate::Result<()> {
+        self.start_insertion_successive(buffer, id, cursor)?;
+        self.fim_outputs.insert((buffer.clone(), id), post_process);
+        Ok(())
+    }
     #[track_caller]
     pub fn start_replace_line(
         &mut self,
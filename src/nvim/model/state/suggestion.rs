@@ -0,0 +1,217 @@
+use std::sync::LazyLock;
+
+use nvim_oxi::api::{self, opts::SetExtmarkOpts, types::ExtmarkVirtTextPosition};
+
+use crate::{
+    mistral::controlleur::fim::post_process::PostProcess,
+    nvim::model::{self, Col, ColRange, Cursor, Row},
+};
+
+static NS: LazyLock<u32> = LazyLock::new(|| api::create_namespace("mistral_suggestion"));
+const HL_GHOST: &'static str = "Comment";
+/// Alternatives are only requested when the user cycles.
+pub const MAX_CANDIDATES: usize = 3;
+
+pub struct Candidate {
+    /// Id of the request streaming this candidate.
+    pub id: usize,
+    pub text: String,
+    pub finished: bool,
+    /// Cleans the streamed chunks before they are added to `text`.
+    pub output: PostProcess,
+}
+
+/// A completion displayed as ghost text, the buffer is only modified when accepted.
+pub struct Suggestion {
+    pub buffer: api::Buffer,
+    /// Where the ghost text starts, it moves forward when a part is accepted.
+    pub anchor: Cursor,
+    pub candidates: Vec<Candidate>,
+    pub current: usize,
+    extmark: Option<u32>,
+}
+
+/// What the user keeps from the current candidate.
+#[derive(Clone, Copy)]
+pub enum Accept {
+    All,
+    Word,
+    Line,
+}
+
+impl std::str::FromStr for Accept {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "" | "all" => Ok(Self::All),
+            "word" => Ok(Self::Word),
+            "line" => Ok(Self::Line),
+            _ => Err(format!("Unknown accept `{value}`, expected all, word or line.")),
+        }
+    }
+}
+
+impl Accept {
+    /// Split the text at the end of what is accepted.
+    pub fn split(self, text: &str) -> (&str, &str) {
+        let end = match self {
+            Self::All => text.len(),
+            Self::Word => {
+                let start = text.len() - text.trim_start().len();
+                text[start..]
+                    .find(char::is_whitespace)
+                    .map_or(text.len(), |end| start + end)
+            }
+            Self::Line => {
+                // On an empty line, the next one is accepted with its line break.
+                let start = if text.starts_with('\n') { 1 } else { 0 };
+                text[start..]
+                    .find('\n')
+                    .map_or(text.len(), |end| start + end)
+            }
+        };
+        text.split_at(end)
+    }
+}
+
+impl Suggestion {
+    pub fn new(buffer: api::Buffer, anchor: Cursor, id: usize, output: PostProcess) -> Self {
+        Self {
+            buffer,
+            anchor,
+            candidates: vec![Candidate::new(id, output)],
+            current: 0,
+            extmark: None,
+        }
+    }
+    pub fn candidate_by_id(&mut self, id: usize) -> Option<(usize, &mut Candidate)> {
+        self.candidates
+            .iter_mut()
+            .enumerate()
+            .find(|(_, candidate)| candidate.id == id)
+    }
+    pub fn running_ids(&self) -> Vec<usize> {
+        self.candidates
+            .iter()
+            .filter(|candidate| !candidate.finished)
+            .map(|candidate| candidate.id)
+            .collect()
+    }
+    pub fn current_text(&self) -> &str {
+        self.candidates
+            .get(self.current)
+            .map_or("", |candidate| candidate.text.as_str())
+    }
+    pub fn push_candidate(&mut self, id: usize, output: PostProcess) {
+        self.candidates.push(Candidate::new(id, output));
+        self.current = self.candidates.len() - 1;
+    }
+    /// Move to another candidate, `false` when the next one must be requested first.
+    pub fn cycle(&mut self, forward: bool) -> bool {
+        let len = self.candidates.len();
+        if forward && self.current + 1 == len && len < MAX_CANDIDATES {
+            return false;
+        }
+        self.current = if forward {
+            (self.current + 1) % len
+        } else {
+            (self.current + len - 1) % len
+        };
+        true
+    }
+
+    pub fn render(&mut self) -> crate::Result<()> {
+        let text = self.current_text().to_string();
+        if text.is_empty() {
+            return self.clear();
+        }
+        let mut lines = text.split('\n');
+        let first = lines.next().unwrap_or_default().to_string();
+        let virt_lines: Vec<Vec<(String, &str)>> = lines
+            .map(|line| vec![(line.to_string(), HL_GHOST)])
+            .collect();
+        let mut opts = SetExtmarkOpts::builder();
+        opts.virt_text([(first, HL_GHOST)])
+            .virt_text_pos(ExtmarkVirtTextPosition::Inline)
+            .virt_lines(virt_lines);
+        if let Some(id) = self.extmark {
+            opts.id(id);
+        }
+        let id = self
+            .buffer
+            .set_extmark(*NS, *self.anchor.row, *self.anchor.col, &opts.build())?;
+        self.extmark = Some(id);
+        Ok(())
+    }
+    pub fn clear(&mut self) -> crate::Result<()> {
+        if let Some(id) = self.extmark.take() {
+            self.buffer.del_extmark(*NS, id)?;
+        }
+        Ok(())
+    }
+
+    /// Write the accepted part in the buffer, returns `true` when nothing is left to accept.
+    pub fn accept(&mut self, accept: Accept, window: &mut api::Window) -> crate::Result<bool> {
+        let text = self.current_text().to_string();
+        let (accepted, rest) = accept.split(&text);
+        if accepted.is_empty() {
+            return Ok(rest.is_empty());
+        }
+        let lines: Vec<&str> = accepted.split('\n').collect();
+        let Cursor { row, col } = self.anchor.clone();
+        let cols = ColRange { start: col, end: col };
+        model::set_text(&mut self.buffer, row..=row, cols, lines.iter().copied())?;
+        let last = lines.last().map_or(0, |line| line.len());
+        self.anchor = if lines.len() == 1 {
+            Cursor {
+                row,
+                col: Col(*col + last),
+            }
+        } else {
+            Cursor {
+                row: Row(*row + lines.len() - 1),
+                col: Col(last),
+            }
+        };
+        model::set_cursor(window, self.anchor.row, self.anchor.col);
+        // Other candidates were sampled for the previous anchor.
+        let rest = rest.to_string();
+        let current = self.candidates.swap_remove(self.current);
+        self.candidates = vec![Candidate { text: rest, ..current }];
+        self.current = 0;
+        if self.current_text().is_empty() {
+            self.clear()?;
+            Ok(true)
+        } else {
+            self.render()?;
+            Ok(false)
+        }
+    }
+}
+
+impl Candidate {
+    fn new(id: usize, output: PostProcess) -> Self {
+        Self {
+            id,
+            text: String::new(),
+            finished: false,
+            output,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accept_split() {
+        let text = "  let x = 1;\n    x + 1\n}";
+        assert_eq!(Accept::Word.split(text), ("  let", " x = 1;\n    x + 1\n}"));
+        assert_eq!(Accept::Line.split(text), ("  let x = 1;", "\n    x + 1\n}"));
+        assert_eq!(Accept::Line.split("\n    x + 1\n}"), ("\n    x + 1", "\n}"));
+        assert_eq!(Accept::Word.split("\n}"), ("\n}", ""));
+        assert_eq!(Accept::All.split(text), (text, ""));
+    }
+}
@@ -19,6 +19,16 @@ fn stop<'lock>(buffer: &api::Buffer, message_index: chat::MsgIndex, state: std::
         .notify_error();
 }
 
+/// Moves the cursor to the end of the chat, in the window the prompt was sent from, if it still shows it.
+fn focus_last_row(buffer: &api::Buffer, win: Option<api::Window>) -> crate::Result<()> {
+    let Some(mut win) = win.filter(|win| win.get_buf().is_ok_and(|buf| &buf == buffer)) else {
+        return Ok(());
+    };
+    let row = model::Row::buf_last_row(buffer)?;
+    model::set_cursor(&mut win, row, 0);
+    Ok(())
+}
+
 pub fn handle_nvim_message(
     buf_handle: i32,
     message_index: chat::MsgIndex,
@@ -33,6 +43,57 @@ pub fn handle_nvim_message(
         stop(buffer, message_index, state.lock());
         return Err("Does not exist.".into());
     };
+    let is_regenerating = chat
+        .lock()
+        .regenerating
+        .as_ref()
+        .is_some_and(|regeneration| regeneration.index == assistant_index);
+    let message = match message {
+        // The new version is written once complete.
+        MistralMessage::FinalizeTask(stream_result) if is_regenerating => {
+            let crate::mistral::model::stream::StreamResponse {
+                message,
+                thinking,
+                status,
+                usage,
+            } = stream_result;
+            let mut chat = chat.lock();
+            let result = match status {
+                Status::Failed(_, _) if chat.is_regenerating() => {
+                    chat.regenerating = None;
+                    Err(format!("Regeneration failed : {status}").into_warn())
+                }
+                _ => chat.finish_regeneration(message.content, thinking, usage),
+            };
+            stop(buffer, message_index, state.lock());
+            return result;
+        }
+        // The stream may not end by itself after an error.
+        MistralMessage::Notify {
+            message,
+            level: notify::NotifyLevel::Error,
+        } if is_regenerating => {
+            notify::error(&message);
+            let Some(abort) = chat.lock().stop_regeneration() else {
+                return Ok(());
+            };
+            state.lock().tx_mistral.send(abort).notify_error();
+            return Err("Regeneration stopped.".into_warn());
+        }
+        MistralMessage::InitializeTask(_) if is_regenerating => {
+            crate::notify::info("Regenerating the answer…");
+            return Ok(());
+        }
+        MistralMessage::UpdateRole(_)
+        | MistralMessage::UpdateContent(_)
+        | MistralMessage::UpdateThinking(_)
+        | MistralMessage::RunTool(_)
+            if is_regenerating =>
+        {
+            return Ok(());
+        }
+        message => message,
+    };
     match message {
         MistralMessage::InitializeTask(_cursor) => {
             let mut chat = chat.lock();
@@ -82,7 +143,17 @@ pub fn handle_nvim_message(
         }
         MistralMessage::UpdateContent(chunk) => {
             // crate::log_libuv!(Off, "[index {message_index}] {chunk:?}");
-            if let Err(err) = chat.lock().insert(chunk, Some(assistant_index)) {
+            let mut chat = chat.lock();
+            let inserted = chat
+                .close_thinking()
+                .and_then(|_| chat.insert_content(chunk, assistant_index));
+            if let Err(err) = inserted {
+                err.notify();
+                stop(buffer, message_index, state.lock());
+            }
+        }
+        MistralMessage::UpdateThinking(chunk) => {
+            if let Err(err) = chat.lock().insert_thinking(chunk, assistant_index) {
                 err.notify();
                 stop(buffer, message_index, state.lock());
             }
@@ -90,6 +161,8 @@ pub fn handle_nvim_message(
         MistralMessage::RunTool(tool_calls) => {
             // crate::log_libuv!(Off, "[index {message_index}] {tool_calls:?}");
             let mut chat = chat.lock();
+            chat.close_thinking()?;
+            chat.flush_content(assistant_index)?;
             let Some(target_message) = chat.messages.last_mut() else {
                 return Err("No more messages in chat : Can't run tool.".into_error());
             };
@@ -127,7 +200,7 @@ pub fn handle_nvim_message(
                     usage: Default::default(),
                     params,
                     status: Status::Completed,
-                    tool_calls_positions: Default::default(),
+                    ..Default::default()
                 };
                 messages_tool.push(message_state);
             }
@@ -150,7 +223,14 @@ pub fn handle_nvim_message(
             // let row_tag_line = position.start;
             // let cols = model::ColRange::from_buffer_row(buffer, row_tag_line)?;
             // let _ = s.start_replace_line(buffer, assistant_index, row_tag_line, *cols.end);
-            let crate::mistral::model::stream::StreamResponse { message, status, usage } = &stream_result;
+            chat.close_thinking()?;
+            chat.flush_content(assistant_index)?;
+            let crate::mistral::model::stream::StreamResponse {
+                message,
+                thinking,
+                status,
+                usage,
+            } = &stream_result;
             crate::log_libuv!(Trace, "Response : {message:?}");
             match status {
                 Status::Failed(_, _) => {
@@ -158,18 +238,35 @@ pub fn handle_nvim_message(
                     chat.mut_message_by_index(assistant_index, |msg| msg.status = status.clone())?;
                 }
                 _ => {
+                    let date: String = api::call_function("strftime", ("%Y-%m-%d %H:%M",))?;
                     chat.mut_message_by_index(message_index, |msg| {
                         msg.usage = usage.clone();
                         msg.status = status.clone();
+                        msg.date = Some(date.clone());
                     })?;
                     chat.mut_message_by_index(assistant_index, |msg| {
+                        msg.date = Some(date);
                         msg.message = message.clone();
+                        if thinking.is_some() {
+                            msg.thinking = thinking.clone();
+                        }
                         msg.usage = usage.clone();
                         msg.status = status.clone();
                     })?;
                 }
             }
+            // The answer is final (no tool to run) : the next prompt is ready to be written.
+            let is_final = matches!(status, Status::Completed)
+                && message.tool_calls.is_none()
+                && assistant_index + 1 == chat.messages.len();
+            if is_final {
+                chat.push_new_message(Some(assistant_index))?;
+            }
             chat.buffer_modifier_ids_finished(vec![message_index, assistant_index]);
+            if is_final {
+                let win = chat.prompt_window.take();
+                focus_last_row(&chat.buffer, win)?;
+            }
             // if chat.(buffer, &message_index)?
             //     || s.buffer_modifier_id_finished(buffer, &assistant_index)?
             // {
@@ -177,6 +274,21 @@ pub fn handle_nvim_message(
             // }
             stop(buffer, message_index, state.lock());
         }
+        MistralMessage::OcrDone(source) => {
+            let mut chat = chat.lock();
+            chat.ocr_running.remove(&source);
+            if chat.ocr_running.is_empty() {
+                chat.update_buffer(model::RowRange::FULL)?;
+                if chat.ocr_pending().is_empty() {
+                    chat.send_prompt(state)?;
+                } else {
+                    return Err("Some documents could not be extracted, the prompt has not been sent.".into_warn());
+                }
+            }
+        }
+        MistralMessage::CargoTestDone(_) => {
+            crate::log_libuv!(Warn, "A chat should not run cargo.");
+        }
         MistralMessage::Notify { message, level } => {
             use notify::NotifyLevel::*;
             match level {
@@ -288,6 +400,7 @@ Peux-tu modifier la fonction main dans `tests_files/main.rs` grâce aux outils,
     ) -> crate::Result<()> {
         let message: MistralMessage = MistralMessage::FinalizeTask(StreamResponse {
             message,
+            thinking: None,
             status: Status::Completed,
             usage: Usage::default(),
         });
@@ -312,6 +425,8 @@ Peux-tu modifier la fonction main dans `tests_files/main.rs` grâce aux outils,
     api::exec2("undo", &Default::default())?;
     api::exec2("redo", &Default::default())?;
     crate::nvim::controlleur::chat::load_chat(state, buffer.clone());
+    let chat = state.lock().chats.get_by_buffer(buffer).cloned().unwrap();
+    chat.lock().prompt_window = Some(api::get_current_win());
 
     let message_index = 1;
     init(buffer, message_index, state)?;
@@ -398,15 +513,16 @@ Peux-tu modifier la fonction main dans `tests_files/main.rs` grâce aux outils,
     assert_eq!(sent_index, message_index);
     let is_abort = matches!(sent_message, NvimMessage::Abort);
     assert!(is_abort, "Expect finalise to sent Abort.");
+    let date_1 = chat.lock().messages[2].date.clone().unwrap();
 
     const BUFFER_CONTENT_3: &'static str = r##"<CHAT  role="Refactorisation" status="0;0;0" model="Tu es un développeur qui a des outils à ta disposition." id="00000000-0000-0000-0000-000000000000"/>
 <MESSAGE  role="System" model="Tiny Latest" status="Created" usage="0;0;0"/>
 Tu es un développeur qui a des outils à ta disposition.
 
-<MESSAGE  role="User" model="Tiny Latest" status="Completed" usage="0;0;0" mode="CodeRefactorisation"/>
+<MESSAGE  role="User" model="Tiny Latest" status="Completed" usage="0;0;0" mode="CodeRefactorisation" date="{date_1}"/>
 Peux-tu modifier la fonction main dans `tests_files/main.rs` grâce aux outils, pour qu'elle affiche "Salut\n" ?
 
-<MESSAGE role="Assistant" model="Tiny Latest" status="Completed" usage="0;0;0" mode="CodeRefactorisation"/>
+<MESSAGE role="Assistant" model="Tiny Latest" status="Completed" usage="0;0;0" mode="CodeRefactorisation" date="{date_1}"/>
 
 <TOOLCALL id="F7EJnRYyb" index="0" name="CodeRetriever">
 
@@ -421,7 +537,7 @@ Peux-tu modifier la fonction main dans `tests_files/main.rs` grâce aux outils,
 <MESSAGE role="Assistant" model="Tiny Latest" status="Initialised" usage="0;0;0" mode="CodeRefactorisation"/>
 
 "##;
-    chat::assert_content(buffer, BUFFER_CONTENT_3);
+    chat::assert_content(buffer, BUFFER_CONTENT_3.replace("{date_1}", &date_1));
 
     chunks(buffer, message_index_tool_call, &CHUNKS_3, state)?;
 
@@ -429,10 +545,10 @@ Peux-tu modifier la fonction main dans `tests_files/main.rs` grâce aux outils,
 <MESSAGE  role="System" model="Tiny Latest" status="Created" usage="0;0;0"/>
 Tu es un développeur qui a des outils à ta disposition.
 
-<MESSAGE  role="User" model="Tiny Latest" status="Completed" usage="0;0;0" mode="CodeRefactorisation"/>
+<MESSAGE  role="User" model="Tiny Latest" status="Completed" usage="0;0;0" mode="CodeRefactorisation" date="{date_1}"/>
 Peux-tu modifier la fonction main dans `tests_files/main.rs` grâce aux outils, pour qu'elle affiche "Salut\n" ?
 
-<MESSAGE role="Assistant" model="Tiny Latest" status="Completed" usage="0;0;0" mode="CodeRefactorisation"/>
+<MESSAGE role="Assistant" model="Tiny Latest" status="Completed" usage="0;0;0" mode="CodeRefactorisation" date="{date_1}"/>
 
 <TOOLCALL id="F7EJnRYyb" index="0" name="CodeRetriever">
 
@@ -447,7 +563,7 @@ Peux-tu modifier la fonction main dans `tests_files/main.rs` grâce aux outils,
 <MESSAGE role="Assistant" model="Tiny Latest" status="Initialised" usage="0;0;0" mode="CodeRefactorisation"/>
 Je suis désolé, mais la fonction main existe déjà et contient déjà le code que vous voulez ajouter.
 "##;
-    chat::assert_content(buffer, BUFFER_CONTENT_4);
+    chat::assert_content(buffer, BUFFER_CONTENT_4.replace("{date_1}", &date_1));
 
     let full_response = Message {
         role: Role::Assistant,
@@ -464,15 +580,16 @@ Je suis désolé, mais la fonction main existe déjà et contient déjà le code
     assert_eq!(sent_index, message_index_tool_call);
     let is_abort = matches!(sent_message, NvimMessage::Abort);
     assert!(is_abort, "Expect finalise to sent Abort.");
+    let date_2 = chat.lock().messages[4].date.clone().unwrap();
 
     const BUFFER_CONTENT_5: &'static str = r##"<CHAT  role="Refactorisation" status="0;0;0" model="Tu es un développeur qui a des outils à ta disposition." id="00000000-0000-0000-0000-000000000000"/>
 <MESSAGE  role="System" model="Tiny Latest" status="Created" usage="0;0;0"/>
 Tu es un développeur qui a des outils à ta disposition.
 
-<MESSAGE  role="User" model="Tiny Latest" status="Completed" usage="0;0;0" mode="CodeRefactorisation"/>
+<MESSAGE  role="User" model="Tiny Latest" status="Completed" usage="0;0;0" mode="CodeRefactorisation" date="{date_1}"/>
 Peux-tu modifier la fonction main dans `tests_files/main.rs` grâce aux outils, pour qu'elle affiche "Salut\n" ?
 
-<MESSAGE role="Assistant" model="Tiny Latest" status="Completed" usage="0;0;0" mode="CodeRefactorisation"/>
+<MESSAGE role="Assistant" model="Tiny Latest" status="Completed" usage="0;0;0" mode="CodeRefactorisation" date="{date_1}"/>
 
 <TOOLCALL id="F7EJnRYyb" index="0" name="CodeRetriever">
 
@@ -481,15 +598,116 @@ Peux-tu modifier la fonction main dans `tests_files/main.rs` grâce aux outils,
 ```
 </TOOLCALL>
 
-<MESSAGE role="Tool" model="Tiny Latest" status="Completed" usage="0;0;0" mode="CodeRefactorisation" name="CodeRetriever" tool_call_id="F7EJnRYyb"/>
+<MESSAGE role="Tool" model="Tiny Latest" status="Completed" usage="0;0;0" mode="CodeRefactorisation" name="CodeRetriever" tool_call_id="F7EJnRYyb" date="{date_2}"/>
 {"Ok":"fn main() {\n    println!(\"Salut\\n\");\n}\n"}
 
-<MESSAGE role="Assistant" model="Tiny Latest" status="Completed" usage="0;0;0" mode="CodeRefactorisation"/>
+<MESSAGE role="Assistant" model="Tiny Latest" status="Completed" usage="0;0;0" mode="CodeRefactorisation" date="{date_2}"/>
 Je suis désolé, mais la fonction main existe déjà et contient déjà le code que vous voulez ajouter.
+
+<MESSAGE role="User" model="Tiny Latest" status="Created" usage="0;0;0" mode="CodeRefactorisation"/>
 "##;
-    chat::assert_content(buffer, BUFFER_CONTENT_5);
+    let content_5 = BUFFER_CONTENT_5
+        .replace("{date_1}", &date_1)
+        .replace("{date_2}", &date_2);
+    chat::assert_content(buffer, content_5);
+    let (cursor_row, _) = model::get_cursor(&api::get_current_win()).unwrap();
+    assert_eq!(
+        cursor_row,
+        model::Row::buf_last_row(buffer)?,
+        "Expect the cursor in the new prompt."
+    );
     api::exec2("undo", &Default::default())?;
     chat::assert_content(buffer, BUFFER_CONTENT);
 
     Ok(())
 }
+
+#[cfg(not(feature = "prod_mode"))]
+#[nvim_oxi::test]
+#[track_caller]
+fn regeneration_error() -> crate::Result<()> {
+    use tokio::sync::mpsc;
+
+    use crate::{
+        messages::{IdMessage, NvimMessage},
+        mistral::model::{
+            Message, Role,
+            stream::{ErrorMessageType, StreamResponse, Usage},
+        },
+        nvim::model::State,
+    };
+
+    const BUFFER_CONTENT: &'static str = r##"<CHAT  role="Couleurs" status="0;0;0" model="" id="00000000-0000-0000-0000-000000000000"/>
+<MESSAGE  role="System" model="Tiny Latest" status="Created" usage="0;0;0"/>
+Tu réponds en un mot.
+
+<MESSAGE  role="User" model="Tiny Latest" status="Completed" usage="0;0;0"/>
+Une couleur ?
+
+<MESSAGE  role="Assistant" model="Tiny Latest" status="Completed" usage="0;0;0"/>
+Bleu.
+
+<MESSAGE  role="User" model="Tiny Latest" status="Created" usage="0;0;0"/>
+"##;
+
+    fn finalise(buffer: &api::Buffer, status: Status, state: &model::SharedState) -> crate::Result<()> {
+        let message = MistralMessage::FinalizeTask(StreamResponse {
+            message: Message {
+                role: Role::Assistant,
+                content: "Rouge.".to_string(),
+                prefix: None,
+                tool_calls: None,
+                tool_call_id: None,
+                name: None,
+            },
+            thinking: None,
+            status,
+            usage: Usage::default(),
+        });
+        handle_nvim_message(buffer.handle(), 1, message, state)
+    }
+
+    let (mistral_tx, mut mistral_rx) = mpsc::unbounded_channel();
+    let state = &State::new(mistral_tx);
+    let buffer = &mut api::Buffer::current();
+    buffer.set_lines(.., false, BUFFER_CONTENT.split('\n'))?;
+    // In tests, we must force the activation of the undotree
+    api::exec2("undo", &Default::default())?;
+    api::exec2("redo", &Default::default())?;
+    crate::nvim::controlleur::chat::load_chat(state, buffer.clone());
+    let chat = state.lock().chats.get_by_buffer(buffer).cloned().unwrap();
+    let mut win = api::get_current_win();
+    model::set_cursor(&mut win, model::Row(8), 0);
+
+    // An error in the stream stops the regeneration, and its last messages are ignored.
+    let envelop = chat.lock().regenerate(&win, None)?;
+    assert!(matches!(envelop.id, IdMessage::Chat(_, 1)));
+    let message = MistralMessage::Notify {
+        message: "Error: Chunk can't be retrieved.".to_string(),
+        level: notify::NotifyLevel::Error,
+    };
+    assert!(handle_nvim_message(buffer.handle(), 1, message, state).is_err());
+    let abort = mistral_rx.blocking_recv().unwrap();
+    assert!(matches!(abort.id, IdMessage::Chat(_, 1)));
+    assert!(matches!(abort.message, NvimMessage::Abort));
+    assert!(!chat.lock().is_regenerating(), "Expect the chat free again.");
+    finalise(buffer, Status::Completed, state)?;
+    assert!(chat.lock().regenerating.is_none());
+    assert_eq!(chat.lock().messages[2].nb_versions(), 1);
+    chat::assert_content(buffer, BUFFER_CONTENT);
+
+    // A failed request ends it too.
+    chat.lock().regenerate(&win, None)?;
+    let status = Status::Failed("Unauthorized".to_string(), ErrorMessageType::Empty);
+    assert!(finalise(buffer, status, state).is_err());
+    assert!(chat.lock().regenerating.is_none());
+    assert_eq!(chat.lock().messages[2].nb_versions(), 1);
+
+    // As closing the chat.
+    chat.lock().regenerate(&win, None)?;
+    let abort = chat.lock().stop_regeneration().unwrap();
+    assert!(matches!(abort.message, NvimMessage::Abort));
+    assert!(chat.lock().stop_regeneration().is_none());
+    chat.lock().regenerate(&win, None)?;
+    Ok(())
+}
@@ -0,0 +1,96 @@
+use nvim_oxi::api;
+
+use crate::{
+    messages::{IdMessage, MistralMessage, NvimEnveloppe, NvimMessage},
+    mistral::model::stream::Status,
+    notify::NotifyExt as _,
+    nvim::model::{self, Locker as _},
+    utils::notify,
+};
+
+#[track_caller]
+fn stop<'lock>(buffer: &api::Buffer, id: usize, state: &std::sync::MutexGuard<'lock, model::State>) {
+    state
+        .tx_mistral
+        .send(NvimEnveloppe {
+            id: IdMessage::Edit(buffer.handle(), id),
+            message: NvimMessage::Abort,
+        })
+        .notify_error();
+}
+
+/// Abort the request of the edit and remove its overlay, the accepted hunks stay.
+pub fn dismiss(buffer: &api::Buffer, state: &mut std::sync::MutexGuard<'_, model::State>) -> crate::Result<()> {
+    let Some(edit) = state.edits.remove(buffer) else {
+        return Ok(());
+    };
+    if !edit.is_finished() {
+        stop(buffer, edit.id, state);
+    }
+    edit.close()
+}
+
+pub fn handle_nvim_message(
+    buf_handle: i32,
+    id: usize,
+    message: MistralMessage,
+    state: &model::SharedState,
+) -> crate::Result<()> {
+    let buffer: api::Buffer = buf_handle.into();
+    let mut s = state.lock();
+    let Some(edit) = s.edits.get_mut(&buffer).filter(|edit| edit.id == id) else {
+        // Dismissed while streaming.
+        if let MistralMessage::FinalizeTask(_) = message {
+            stop(&buffer, id, &s);
+        }
+        return Ok(());
+    };
+    match message {
+        MistralMessage::InitializeTask(_) | MistralMessage::UpdateRole(_) => {}
+        MistralMessage::UpdateContent(chunk) => {
+            // The overlay only changes with a complete line.
+            if edit.push(&chunk.join("\n")) {
+                edit.render()?;
+            }
+        }
+        MistralMessage::FinalizeTask(stream_result) => {
+            edit.finish();
+            stop(&buffer, id, &s);
+            if let Status::Failed(message, _) = &stream_result.status {
+                dismiss(&buffer, &mut s)?;
+                return Err(message.as_str().into());
+            }
+            let Some(edit) = s.edits.get_mut(&buffer) else {
+                return Ok(());
+            };
+            edit.render()?;
+            let hunks = edit.hunks().len();
+            if hunks == 0 {
+                dismiss(&buffer, &mut s)?;
+                notify::info("No change.");
+            } else {
+                notify::info(format!(
+                    "{hunks} hunks : `:MistralEditAccept` or `:MistralEditReject` each one."
+                ));
+            }
+        }
+        MistralMessage::UpdateThinking(_)
+        | MistralMessage::RunTool(_)
+        | MistralMessage::OcrDone(_)
+        | MistralMessage::CargoTestDone(_) => {
+            crate::log_libuv!(Warn, "Unexpected message for an edit.");
+        }
+        MistralMessage::Notify { message, level } => {
+            use notify::NotifyLevel::*;
+            match level {
+                Trace => notify::trace(&message),
+                Debug => notify::debug(&message),
+                Info => notify::info(&message),
+                Warn => notify::warn(&message),
+                Error => notify::error(&message),
+                Off => notify::off(&message),
+            }
+        }
+    }
+    Ok(())
+}
@@ -2,6 +2,7 @@ use nvim_oxi::api;
 
 use crate::{
     messages::MistralMessage,
+    mistral::model::stream::Status,
     notify::{IntoNotification as _, NotifyExt as _},
     nvim::model::{self, Locker as _},
     utils::notify,
@@ -18,6 +19,22 @@ fn stop<'lock>(buffer: &api::Buffer, id: usize, state: std::sync::MutexGuard<'lo
         .notify_error();
 }
 
+/// The placeholder removed by `:MistralFIMTodos` is written back where its completion was inserted.
+fn put_back_placeholder(
+    state: &mut model::State,
+    buffer: &api::Buffer,
+    id: usize,
+    reason: &str,
+) -> crate::Result<()> {
+    let Some(placeholder) = state.placeholders.remove(&(buffer.clone(), id)) else {
+        return Ok(());
+    };
+    let lines = placeholder.split('\n').map(ToString::to_string).collect();
+    state.get_mut_buffer_modifier(buffer)?.insert(id, lines)?;
+    notify::warn(format!("`{placeholder}` put back : {reason}"));
+    Ok(())
+}
+
 pub fn handle_nvim_message(
     buf_handle: i32,
     id: usize,
@@ -29,28 +46,90 @@ pub fn handle_nvim_message(
     let mut s = state.lock();
     match message {
         MistralMessage::InitializeTask(cursor) => {
-            s.start_insertion_successive(buffer, id, cursor)?;
+            // Already started for the placeholders removed by `:MistralFIMTodos`.
+            let started = s
+                .buffer_modifiers
+                .get(buffer)
+                .is_some_and(|bm| bm.is_running(id));
+            let backend = s.fim_backends.remove(&(buffer.clone(), id));
+            if !started {
+                let Some(backend) = backend else {
+                    return Err(format!("No backend for the FIM {id}.").into_error());
+                };
+                s.start_fim_insertion(buffer, id, cursor, &backend)?;
+            }
         }
         MistralMessage::UpdateRole(_) => {}
         MistralMessage::UpdateContent(chunk) => {
+            let chunk = match s.fim_outputs.get_mut(&(buffer.clone(), id)) {
+                Some(post_process) => post_process.push(&chunk.join("\n")),
+                None => chunk.join("\n"),
+            };
+            if chunk.is_empty() {
+                return Ok(());
+            }
             let buffer_modifier = s.get_mut_buffer_modifier(buffer)?;
+            let chunk = chunk.split('\n').map(ToString::to_string).collect();
             if let Err(err) = buffer_modifier.insert(id, chunk) {
                 err.into_error().notify();
                 stop(buffer, id, s);
             }
         }
-        MistralMessage::FinalizeTask(_stream_result) => {
+        MistralMessage::FinalizeTask(stream_result) => {
+            // The end held by the post process, ex: a closing brace not repeating the suffix.
+            let mut written = false;
+            if let Some(mut post_process) = s.fim_outputs.remove(&(buffer.clone(), id)) {
+                let rest = post_process.finish();
+                if !rest.is_empty() {
+                    let chunk = rest.split('\n').map(ToString::to_string).collect();
+                    match s.get_mut_buffer_modifier(buffer)?.insert(id, chunk) {
+                        Ok(()) => written = true,
+                        Err(err) => err.into_error().notify(),
+                    }
+                }
+            }
+            // A doc comment is held until the end, the previous one goes once it is written.
+            if let Some(docs) = s.replaced_docs.remove(&(buffer.clone(), id)) {
+                docs.finish(written)?;
+            }
+            match &stream_result.status {
+                Status::Failed(message, _) => put_back_placeholder(&mut s, buffer, id, message)?,
+                _ if stream_result.message.content.trim().is_empty() => {
+                    put_back_placeholder(&mut s, buffer, id, "empty completion.")?
+                }
+                _ => {
+                    s.placeholders.remove(&(buffer.clone(), id));
+                }
+            }
+            s.fim_backends.remove(&(buffer.clone(), id));
             crate::log_libuv!(Debug, "Cleaned up FIM");
             s.buffer_modifier_id_finished(buffer, &id)?;
             s.remove_fim(buffer);
             stop(buffer, id, s);
             crate::log_libuv!(Debug, "FIM Done.");
         }
+        MistralMessage::UpdateThinking(_) => {
+            crate::log_libuv!(Debug, "FIM ignores reasoning traces.");
+        }
         MistralMessage::RunTool(tool) => {
             crate::log_libuv!(Warn, "FIM should not RunTool: {tool:?}");
         }
+        MistralMessage::OcrDone(source) => {
+            crate::log_libuv!(Debug, "OCR cached : {source}");
+        }
+        MistralMessage::CargoTestDone(_) => {
+            crate::log_libuv!(Warn, "FIM should not run cargo.");
+        }
         MistralMessage::Notify { message, level } => {
             use notify::NotifyLevel::*;
+            // The request of a placeholder failed before streaming, no `FinalizeTask` follows.
+            if matches!(level, Error) && s.placeholders.contains_key(&(buffer.clone(), id)) {
+                put_back_placeholder(&mut s, buffer, id, &message)?;
+                s.fim_outputs.remove(&(buffer.clone(), id));
+                s.buffer_modifier_id_finished(buffer, &id)?;
+                s.remove_fim(buffer);
+                return Ok(());
+            }
             match level {
                 Trace => notify::trace(&message),
                 Debug => notify::debug(&message),
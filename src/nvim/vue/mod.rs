@@ -1,2 +1,6 @@
 pub mod chat;
+pub mod edit;
 pub mod fim;
+pub mod ocr;
+pub mod suggestion;
+pub mod unit_tests;
@@ -0,0 +1,115 @@
+use nvim_oxi::api;
+
+use crate::{
+    messages::{IdMessage, MistralMessage, NvimEnveloppe, NvimMessage},
+    notify::{IntoNotification as _, NotifyExt as _},
+    nvim::model::{self, Locker as _},
+    utils::notify,
+};
+
+#[track_caller]
+fn stop<'lock>(buffer: &api::Buffer, id: usize, state: std::sync::MutexGuard<'lock, model::State>) {
+    state
+        .tx_mistral
+        .send(NvimEnveloppe {
+            id: IdMessage::Ocr(buffer.handle(), id),
+            message: NvimMessage::Abort,
+        })
+        .notify_error();
+}
+
+/// The Markdown of `:MistralOcr` is inserted as is, it's not code completed at the cursor (no FIM post process).
+pub fn handle_nvim_message(
+    buf_handle: i32,
+    id: usize,
+    message: MistralMessage,
+    state: &model::SharedState,
+) -> crate::Result<()> {
+    let mut buffer: api::Buffer = buf_handle.into();
+    let buffer = &mut buffer;
+    let mut s = state.lock();
+    match message {
+        MistralMessage::InitializeTask(cursor) => s.start_insertion_successive(buffer, id, cursor)?,
+        MistralMessage::UpdateRole(_) => {}
+        MistralMessage::UpdateContent(chunk) => {
+            let buffer_modifier = s.get_mut_buffer_modifier(buffer)?;
+            if let Err(err) = buffer_modifier.insert(id, chunk) {
+                err.into_error().notify();
+                stop(buffer, id, s);
+            }
+        }
+        MistralMessage::FinalizeTask(_stream_result) => {
+            s.buffer_modifier_id_finished(buffer, &id)?;
+            s.remove_fim(buffer);
+            stop(buffer, id, s);
+            crate::log_libuv!(Debug, "OCR inserted.");
+        }
+        MistralMessage::OcrDone(source) => {
+            crate::log_libuv!(Debug, "OCR cached : {source}");
+        }
+        MistralMessage::UpdateThinking(_) | MistralMessage::RunTool(_) | MistralMessage::CargoTestDone(_) => {
+            crate::log_libuv!(Warn, "OCR only inserts Markdown.");
+        }
+        MistralMessage::Notify { message, level } => {
+            use notify::NotifyLevel::*;
+            match level {
+                Trace => notify::trace(&message),
+                Debug => notify::debug(&message),
+                Info => notify::info(&message),
+                Warn => notify::warn(&message),
+                Error => notify::error(&message),
+                Off => notify::off(&message),
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "prod_mode"))]
+#[nvim_oxi::test]
+#[track_caller]
+fn test_ocr_insertion() -> crate::Result<()> {
+    use tokio::sync::mpsc;
+
+    use crate::{
+        mistral::model::stream::StreamResponse,
+        nvim::model::{Col, Cursor, Row, State, state::chat::assert_content},
+    };
+
+    const MARKDOWN: &'static str = r#"# Spécification
+
+```rust
+fn main() {
+    println!("Salut");
+}
+```
+
+Fin du document."#;
+
+    let (mistral_tx, _mistral_rx) = mpsc::unbounded_channel();
+    let state = &State::new(mistral_tx);
+    let buffer = &mut api::Buffer::current();
+    buffer.set_lines(.., false, ["Avant", "Après"])?;
+    // In tests, we must force the activation of the undotree
+    api::exec2("undo", &Default::default())?;
+    api::exec2("redo", &Default::default())?;
+
+    let id = 0;
+    let cursor = Cursor {
+        row: Row(0),
+        col: Col::MAX,
+    };
+    handle_nvim_message(buffer.handle(), id, MistralMessage::InitializeTask(cursor), state)?;
+    // Start on a new line, like `controlleur::ocr`.
+    let chunk = std::iter::once("")
+        .chain(MARKDOWN.split('\n'))
+        .map(ToString::to_string)
+        .collect();
+    handle_nvim_message(buffer.handle(), id, MistralMessage::UpdateContent(chunk), state)?;
+    let mut response = StreamResponse::new();
+    response.message.content = MARKDOWN.to_string();
+    handle_nvim_message(buffer.handle(), id, MistralMessage::FinalizeTask(response), state)?;
+
+    assert_content(buffer, format!("Avant\n{MARKDOWN}\nAprès"));
+    Ok(())
+}
@@ -0,0 +1,98 @@
+use nvim_oxi::api;
+
+use crate::{
+    messages::MistralMessage,
+    mistral::model::stream::Status,
+    notify::NotifyExt as _,
+    nvim::model::{self, Locker as _},
+    utils::notify,
+};
+
+#[track_caller]
+fn stop<'lock>(buffer: &api::Buffer, id: usize, state: &std::sync::MutexGuard<'lock, model::State>) {
+    state
+        .tx_mistral
+        .send(crate::messages::NvimEnveloppe {
+            id: crate::messages::IdMessage::Suggestion(buffer.handle(), id),
+            message: crate::messages::NvimMessage::Abort,
+        })
+        .notify_error();
+}
+
+/// Abort every request of the suggestion and remove its ghost text.
+pub fn dismiss(buffer: &api::Buffer, state: &mut std::sync::MutexGuard<'_, model::State>) -> crate::Result<()> {
+    let Some(mut suggestion) = state.suggestions.remove(buffer) else {
+        return Ok(());
+    };
+    for id in suggestion.running_ids() {
+        stop(buffer, id, state);
+    }
+    suggestion.clear()
+}
+
+pub fn handle_nvim_message(
+    buf_handle: i32,
+    id: usize,
+    message: MistralMessage,
+    state: &model::SharedState,
+) -> crate::Result<()> {
+    let buffer: api::Buffer = buf_handle.into();
+    let mut s = state.lock();
+    let Some(suggestion) = s.suggestions.get_mut(&buffer) else {
+        // Dismissed while streaming.
+        if let MistralMessage::FinalizeTask(_) = message {
+            stop(&buffer, id, &s);
+        }
+        return Ok(());
+    };
+    let current = suggestion.current;
+    let Some((index, candidate)) = suggestion.candidate_by_id(id) else {
+        return Ok(());
+    };
+    match message {
+        MistralMessage::InitializeTask(_) | MistralMessage::UpdateRole(_) => {}
+        MistralMessage::UpdateContent(chunk) => {
+            let text = candidate.output.push(&chunk.join("\n"));
+            candidate.text.push_str(&text);
+            if index == current && !text.is_empty() {
+                suggestion.render()?;
+            }
+        }
+        MistralMessage::FinalizeTask(stream_result) => {
+            candidate.finished = true;
+            let text = candidate.output.finish();
+            candidate.text.push_str(&text);
+            let empty = candidate.text.is_empty();
+            if index == current && !text.is_empty() {
+                suggestion.render()?;
+            }
+            stop(&buffer, id, &s);
+            if let Status::Failed(message, _) = &stream_result.status {
+                dismiss(&buffer, &mut s)?;
+                return Err(message.as_str().into());
+            }
+            if empty && index == current {
+                dismiss(&buffer, &mut s)?;
+                notify::info("No suggestion.");
+            }
+        }
+        MistralMessage::UpdateThinking(_)
+        | MistralMessage::RunTool(_)
+        | MistralMessage::OcrDone(_)
+        | MistralMessage::CargoTestDone(_) => {
+            crate::log_libuv!(Warn, "Unexpected message for a suggestion.");
+        }
+        MistralMessage::Notify { message, level } => {
+            use notify::NotifyLevel::*;
+            match level {
+                Trace => notify::trace(&message),
+                Debug => notify::debug(&message),
+                Info => notify::info(&message),
+                Warn => notify::warn(&message),
+                Error => notify::error(&message),
+                Off => notify::off(&message),
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,207 @@
+use std::path::{Path, PathBuf};
+
+use code_modifier::{LanguageExt as _, langs::rust::Rust};
+use nvim_oxi::api;
+
+use crate::{
+    messages::{CargoTest, IdMessage, MistralMessage, NvimEnveloppe, NvimMessage},
+    mistral::{
+        controlleur::{cargo::CargoReport, fim::template},
+        model::stream::Status,
+    },
+    notify::{IntoNotification as _, NotifyExt as _},
+    nvim::model::{self, Locker as _, state::MergedTests},
+    utils::{get_option, notify},
+};
+
+#[track_caller]
+fn stop<'lock>(buffer: &api::Buffer, id: usize, state: &std::sync::MutexGuard<'lock, model::State>) {
+    state
+        .tx_mistral
+        .send(NvimEnveloppe {
+            id: IdMessage::Tests(buffer.handle(), id),
+            message: NvimMessage::Abort,
+        })
+        .notify_error();
+}
+
+fn write(buffer: &api::Buffer) -> crate::Result<()> {
+    buffer.call(|_| api::command("silent write"))??;
+    Ok(())
+}
+
+/// Rows of `previous` replaced by the rows of `merged`, the common first and last lines are kept :
+/// `(start, previous_end, merged_end)`.
+fn changed_rows(previous: &[String], merged: &[String]) -> (usize, usize, usize) {
+    let start = previous
+        .iter()
+        .zip(merged)
+        .take_while(|(previous, merged)| previous == merged)
+        .count();
+    let common_end = previous[start..]
+        .iter()
+        .rev()
+        .zip(merged[start..].iter().rev())
+        .take_while(|(previous, merged)| previous == merged)
+        .count();
+    (start, previous.len() - common_end, merged.len() - common_end)
+}
+
+/// Merge the tests of the answer in the `mod tests` of the buffer and write it. Only the changed rows are
+/// set, a buffer modified since `:MistralGenerateTests` is left as is.
+fn merge(buffer: &mut api::Buffer, answer: &str) -> crate::Result<MergedTests> {
+    if get_option::<bool>(buffer, "modified")? {
+        return Err("The buffer changed, save it and generate the tests again.".into_warn());
+    }
+    let answer = template::code_block(answer);
+    let Some(generated) = Rust::new_parser(answer.as_bytes()) else {
+        return Err("Can't parse the answer with tree-sitter.".into_error());
+    };
+    let tests = generated.list_tests();
+    if tests.is_empty() {
+        return Err("No test in the answer.".into_warn());
+    }
+    let previous: Vec<String> = buffer
+        .get_lines(0.., false)?
+        .map(|line| line.to_string())
+        .collect();
+    let code = previous.join("\n");
+    let Some(merged) = Rust::new_parser(code.as_bytes()).and_then(|mut parser| parser.merge_tests(&tests)) else {
+        return Err("Can't merge the tests in the buffer.".into_error());
+    };
+    let merged = String::from_utf8_lossy(&merged);
+    let merged: Vec<String> = merged
+        .trim_end_matches('\n')
+        .split('\n')
+        .map(ToString::to_string)
+        .collect();
+    let (start, previous_end, merged_end) = changed_rows(&previous, &merged);
+    buffer.set_lines(start..previous_end, false, merged[start..merged_end].iter().cloned())?;
+    write(buffer)?;
+    notify::info(format!("{} tests merged, running cargo…", tests.len()));
+    Ok(MergedTests {
+        start,
+        len: merged_end - start,
+        previous: previous[start..previous_end].to_vec(),
+        changedtick: buffer.get_changedtick()?,
+    })
+}
+
+/// Put back the rows replaced by the merge, unless the buffer changed since.
+fn restore(buffer: &mut api::Buffer, merged: MergedTests) -> crate::Result<()> {
+    if buffer.get_changedtick()? != merged.changedtick {
+        return Err("The buffer changed during `cargo test`, the tests are not removed.".into_warn());
+    }
+    let MergedTests {
+        start, len, previous, ..
+    } = merged;
+    buffer.set_lines(start..start + len, false, previous)?;
+    write(buffer)
+}
+
+/// Directory of the file, cargo looks for the manifest from there.
+fn directory(buffer: &api::Buffer) -> PathBuf {
+    buffer
+        .get_name()
+        .ok()
+        .and_then(|path| path.parent().map(Path::to_path_buf))
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+pub fn handle_nvim_message(
+    buf_handle: i32,
+    id: usize,
+    message: MistralMessage,
+    state: &model::SharedState,
+) -> crate::Result<()> {
+    let mut buffer: api::Buffer = buf_handle.into();
+    let mut s = state.lock();
+    let key = (buffer.clone(), id);
+    match message {
+        // Nothing is inserted before the whole answer is there.
+        MistralMessage::InitializeTask(_) | MistralMessage::UpdateRole(_) | MistralMessage::UpdateContent(_) => {}
+        MistralMessage::FinalizeTask(stream_result) => {
+            stop(&buffer, id, &s);
+            if let Status::Failed(message, _) = &stream_result.status {
+                s.generated_tests.remove(&key);
+                return Err(message.as_str().into());
+            }
+            let merged = match merge(&mut buffer, &stream_result.message.content) {
+                Ok(merged) => merged,
+                Err(err) => {
+                    s.generated_tests.remove(&key);
+                    return Err(err);
+                }
+            };
+            let Some(generated) = s.generated_tests.get_mut(&key) else {
+                return Ok(());
+            };
+            generated.merged = Some(merged);
+            let envelop = NvimEnveloppe {
+                id: IdMessage::Tests(buf_handle, id),
+                message: NvimMessage::CargoTest(CargoTest {
+                    filter: generated.name.clone(),
+                    dir: directory(&buffer),
+                }),
+            };
+            s.tx_mistral.send(envelop).notify_error();
+        }
+        MistralMessage::CargoTestDone(report) => {
+            let Some(generated) = s.generated_tests.remove(&key) else {
+                return Ok(());
+            };
+            let name = generated.name;
+            match report {
+                CargoReport { passed: true, .. } => {
+                    notify::info(format!("Tests of `{name}` pass :\n{}", report.summary));
+                    return Ok(());
+                }
+                // Kept, a failing test may show a bug of the function.
+                CargoReport { compiled: true, .. } => {
+                    notify::warn(format!("Tests of `{name}` fail :\n{}", report.summary));
+                    return Ok(());
+                }
+                _ => {}
+            }
+            // Generated tests never stay in the file uncompiled.
+            if let Some(merged) = generated.merged {
+                restore(&mut buffer, merged)?;
+            }
+            return Err(format!("Tests of `{name}` don't compile, removed :\n{}", report.summary).into_warn());
+        }
+        MistralMessage::UpdateThinking(_) | MistralMessage::RunTool(_) | MistralMessage::OcrDone(_) => {
+            crate::log_libuv!(Warn, "Unexpected message for generated tests.");
+        }
+        MistralMessage::Notify { message, level } => {
+            use notify::NotifyLevel::*;
+            match level {
+                Trace => notify::trace(&message),
+                Debug => notify::debug(&message),
+                Info => notify::info(&message),
+                Warn => notify::warn(&message),
+                Error => notify::error(&message),
+                Off => notify::off(&message),
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merged_rows() {
+        let lines = |text: &str| text.split('\n').map(String::from).collect::<Vec<_>>();
+        let previous = lines("fn add() {}\n\n#[cfg(test)]\nmod tests {\n    fn old() {}\n}");
+        let merged = lines("fn add() {}\n\n#[cfg(test)]\nmod tests {\n    fn old() {}\n\n    fn new() {}\n}");
+        assert_eq!(changed_rows(&previous, &merged), (5, 5, 7));
+        let merged = lines("fn add() {}\n\n#[cfg(test)]\nmod tests {\n    fn new() {}\n}");
+        assert_eq!(changed_rows(&previous, &merged), (4, 5, 5));
+        assert_eq!(changed_rows(&previous, &previous), (6, 6, 6));
+        // A repeated line is not counted twice.
+        assert_eq!(changed_rows(&lines("a\na"), &lines("a\na\na")), (2, 2, 3));
+    }
+}